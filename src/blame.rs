@@ -0,0 +1,81 @@
+use std::path::Path;
+
+#[derive(Debug, Clone)]
+pub struct BlameLine {
+    /// Commit that last changed the line
+    pub commit: git2::Oid,
+    /// Path of the file in `commit`, which differs from the blamed path if the file was renamed since
+    pub path: String,
+    pub author: String,
+    pub time: git2::Time,
+    /// Whether the line is the first of a run of lines last changed by the same commit
+    pub starts_hunk: bool,
+    pub content: String,
+}
+
+/// Lines of a file at a commit, each with the commit that last changed it
+#[derive(Debug, Clone)]
+pub struct FileBlame {
+    pub commit: git2::Oid,
+    pub path: String,
+    pub lines: Vec<BlameLine>,
+}
+
+pub fn blame_file(repo: &git2::Repository, commit: git2::Oid, path: &str) -> Result<FileBlame, git2::Error> {
+    let blob = repo.find_commit(commit)?.tree()?
+        .get_path(Path::new(path))?
+        .to_object(repo)?
+        .peel_to_blob()?;
+    if blob.is_binary() {
+        return Err(git2::Error::from_str(&format!("{} is a binary file", path)));
+    }
+    let content = String::from_utf8_lossy(blob.content());
+    let text_lines: Vec<&str> = content.lines().collect();
+
+    let mut options = git2::BlameOptions::new();
+    options.newest_commit(commit);
+    let blame = repo.blame_file(Path::new(path), Some(&mut options))?;
+
+    let mut lines = Vec::with_capacity(text_lines.len());
+    for hunk in blame.iter() {
+        let signature = hunk.final_signature();
+        let author = String::from_utf8_lossy(signature.name_bytes()).to_string();
+        let hunk_path = hunk.path()
+            .map(|hunk_path| hunk_path.to_string_lossy().to_string())
+            .unwrap_or_else(|| path.to_owned());
+
+        // Line numbers of hunks start at 1
+        let start = hunk.final_start_line().saturating_sub(1);
+        for (idx, content) in text_lines.iter().skip(start).take(hunk.lines_in_hunk()).enumerate() {
+            lines.push(BlameLine {
+                commit: hunk.final_commit_id(),
+                path: hunk_path.clone(),
+                author: author.clone(),
+                time: signature.when(),
+                starts_hunk: idx == 0,
+                content: content.to_string(),
+            });
+        }
+    }
+
+    Ok(FileBlame {
+        commit,
+        path: path.to_owned(),
+        lines,
+    })
+}
+
+/// Blames the file as it was before `commit` changed it, `path` being its name in `commit`
+pub fn blame_parent(repo: &git2::Repository, commit: git2::Oid, path: &str) -> Result<FileBlame, git2::Error> {
+    let commit = repo.find_commit(commit)?;
+    let parent = commit.parent(0)
+        .map_err(|_| git2::Error::from_str(&format!("Commit {} has no parent", commit.id())))?;
+
+    if parent.tree()?.get_path(Path::new(path)).is_ok() {
+        return blame_file(repo, parent.id(), path);
+    }
+    match crate::file_history::renamed_from(repo, &commit, path)? {
+        Some(old_path) => blame_file(repo, parent.id(), &old_path),
+        None => Err(git2::Error::from_str(&format!("{} was added by commit {:.8}", path, commit.id()))),
+    }
+}
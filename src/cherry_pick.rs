@@ -0,0 +1,153 @@
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PickOperation {
+    CherryPick,
+    Revert,
+}
+
+impl PickOperation {
+    pub fn title(self) -> &'static str {
+        match self {
+            PickOperation::CherryPick => "Cherry-pick",
+            PickOperation::Revert => "Revert",
+        }
+    }
+
+    /// Operation in progress in the repository, if it is a cherry-pick or a revert
+    pub fn in_progress(repo: &git2::Repository) -> Option<PickOperation> {
+        match repo.state() {
+            git2::RepositoryState::CherryPick | git2::RepositoryState::CherryPickSequence => Some(PickOperation::CherryPick),
+            git2::RepositoryState::Revert | git2::RepositoryState::RevertSequence => Some(PickOperation::Revert),
+            _ => None,
+        }
+    }
+
+    /// Reference that libgit2 leaves to the commit being applied while the operation is in progress
+    fn head_ref_name(self) -> &'static str {
+        match self {
+            PickOperation::CherryPick => "CHERRY_PICK_HEAD",
+            PickOperation::Revert => "REVERT_HEAD",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum PickResult {
+    /// New commit created on top of HEAD
+    Committed(git2::Oid),
+    /// The changes are already there, so nothing was committed
+    Empty,
+    /// Applying stopped with conflicts in these files, which are left in the index and the working tree
+    Conflicts(Vec<String>),
+}
+
+#[derive(Debug, Clone)]
+pub struct PickStep {
+    pub commit: git2::Oid,
+    pub summary: String,
+    pub result: PickResult,
+}
+
+/// Orders commits the way they are applied: cherry-picked from the oldest to the newest
+/// and reverted from the newest to the oldest.
+/// `commits` are in the topological order of the commits list, children before their parents.
+pub fn pick_order(operation: PickOperation, commits: &[git2::Oid]) -> Vec<git2::Oid> {
+    let mut commits = commits.to_vec();
+    if operation == PickOperation::CherryPick {
+        commits.reverse();
+    }
+
+    commits
+}
+
+/// Applies commits one by one onto HEAD in the given order, committing each one that applies cleanly.
+/// Stops at the first commit with conflicts; the commits after it are not included in the result.
+pub fn pick_commits(repo: &git2::Repository, operation: PickOperation, commits: &[git2::Oid]) -> Result<Vec<PickStep>, git2::Error> {
+    if repo.state() != git2::RepositoryState::Clean {
+        return Err(git2::Error::from_str("Another operation is in progress; finish or abort it first"));
+    }
+
+    let mut steps = Vec::new();
+    for commit in commits {
+        let commit = repo.find_commit(*commit)?;
+        let result = pick_commit(repo, operation, &commit)?;
+        let stop = matches!(result, PickResult::Conflicts(_));
+        steps.push(PickStep {
+            commit: commit.id(),
+            summary: String::from_utf8_lossy(commit.summary_bytes().unwrap_or(&[])).to_string(),
+            result,
+        });
+        if stop {
+            break;
+        }
+    }
+
+    Ok(steps)
+}
+
+fn pick_commit(repo: &git2::Repository, operation: PickOperation, commit: &git2::Commit) -> Result<PickResult, git2::Error> {
+    // Merge commits are applied relative to their first parent, like `git cherry-pick -m 1` does
+    let mainline = if commit.parent_count() > 1 { 1 } else { 0 };
+    match operation {
+        PickOperation::CherryPick => {
+            let mut options = git2::CherrypickOptions::new();
+            options.mainline(mainline);
+            repo.cherrypick(commit, Some(&mut options))?;
+        },
+        PickOperation::Revert => {
+            let mut options = git2::RevertOptions::new();
+            options.mainline(mainline);
+            repo.revert(commit, Some(&mut options))?;
+        },
+    }
+
+    let paths = crate::conflicts::conflicted_paths(repo)?;
+    if !paths.is_empty() {
+        return Ok(PickResult::Conflicts(paths));
+    }
+
+    commit_picked(repo, operation, commit)
+}
+
+/// Commits the resolved conflicts of the cherry-pick or revert in progress
+pub fn continue_pick(repo: &git2::Repository) -> Result<PickStep, git2::Error> {
+    let operation = PickOperation::in_progress(repo)
+        .ok_or_else(|| git2::Error::from_str("No cherry-pick or revert is in progress"))?;
+    if repo.index()?.has_conflicts() {
+        return Err(git2::Error::from_str("Conflicts have to be resolved and staged first"));
+    }
+
+    let commit = repo.find_reference(operation.head_ref_name())?.peel_to_commit()?;
+    let result = commit_picked(repo, operation, &commit)?;
+
+    Ok(PickStep {
+        commit: commit.id(),
+        summary: String::from_utf8_lossy(commit.summary_bytes().unwrap_or(&[])).to_string(),
+        result,
+    })
+}
+
+/// Commits the index on top of HEAD with the message prepared by libgit2 and finishes the operation.
+/// Cherry-picked commits keep their original author.
+fn commit_picked(repo: &git2::Repository, operation: PickOperation, commit: &git2::Commit) -> Result<PickResult, git2::Error> {
+    let tree = repo.find_tree(repo.index()?.write_tree()?)?;
+    let head = repo.head()?.peel_to_commit()?;
+    if tree.id() == head.tree_id() {
+        repo.cleanup_state()?;
+        return Ok(PickResult::Empty);
+    }
+
+    let committer = repo.signature()?;
+    let author = match operation {
+        PickOperation::CherryPick => commit.author(),
+        PickOperation::Revert => committer.clone(),
+    };
+    let message = match repo.message() {
+        Ok(message) => message,
+        Err(_) => String::from_utf8_lossy(commit.message_bytes()).to_string(),
+    };
+
+    let oid = repo.commit(Some("HEAD"), &author, &committer, &message, &tree, &[&head])?;
+    repo.cleanup_state()?;
+
+    Ok(PickResult::Committed(oid))
+}
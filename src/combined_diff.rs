@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::diff_settings::DiffSettings;
+use crate::file_diff::{FileDiff, DiffHunk, DiffLine, DiffLineKind};
+use crate::syntax_highlight::LineHighlights;
+
+/// Line of a combined diff: either a line of the merge result or a line lost from some of the parents
+struct CombinedLine {
+    /// One marker per parent: '+' if the line was added relative to that parent,
+    /// '-' if it was removed from that parent, ' ' otherwise
+    markers: Vec<char>,
+    content: String,
+    /// Line number in the merge result; `None` for removed lines
+    lineno: Option<u32>,
+}
+
+impl CombinedLine {
+    fn is_interesting(&self) -> bool {
+        self.markers.iter().any(|marker| *marker != ' ')
+    }
+}
+
+fn blob_at_path<'repo>(repo: &'repo git2::Repository, tree: &git2::Tree, path: &str) -> Result<Option<git2::Blob<'repo>>, git2::Error> {
+    match tree.get_path(Path::new(path)) {
+        Ok(entry) => match entry.kind() {
+            Some(git2::ObjectType::Blob) => repo.find_blob(entry.id()).map(Some),
+            _ => Ok(None),
+        },
+        Err(ref e) if e.code() == git2::ErrorCode::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn split_lines(content: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(content).lines().map(|line| line.to_owned()).collect()
+}
+
+/// Lists paths that differ between a merge commit and every one of its parents,
+/// like `git diff --combined` does.
+pub fn combined_paths(repo: &git2::Repository, commit: &git2::Commit, settings: &DiffSettings) -> Result<Vec<String>, git2::Error> {
+    let tree = commit.tree()?;
+    let mut result: Option<Vec<String>> = None;
+
+    for parent in commit.parents() {
+        let diff = repo.diff_tree_to_tree(Some(&parent.tree()?), Some(&tree), Some(&mut settings.diff_options()))?;
+        let paths: Vec<String> = diff.deltas()
+            .filter_map(|delta| delta.new_file().path_bytes().or_else(|| delta.old_file().path_bytes()))
+            .map(|bytes| String::from_utf8_lossy(bytes).to_string())
+            .collect();
+
+        result = Some(match result {
+            None => paths,
+            Some(previous) => previous.into_iter().filter(|path| paths.contains(path)).collect(),
+        });
+    }
+
+    Ok(result.unwrap_or_default())
+}
+
+/// Builds the combined diff of `path` between a merge commit and all of its parents.
+///
+/// With `dense` set, hunks where the merge result matches one of the parents are omitted, like in `git diff --cc`.
+pub fn combined_file_diff(
+    repo: &git2::Repository,
+    commit: &git2::Commit,
+    path: &str,
+    dense: bool,
+    settings: &DiffSettings,
+) -> Result<FileDiff, git2::Error> {
+    let result_blob = blob_at_path(repo, &commit.tree()?, path)?;
+    let parent_blobs = commit.parents()
+        .map(|parent| blob_at_path(repo, &parent.tree()?, path))
+        .collect::<Result<Vec<_>, _>>()?;
+    let parents_count = parent_blobs.len();
+
+    let mut file_diff = FileDiff {
+        old_path: Some(path.to_owned()),
+        new_path: Some(path.to_owned()),
+        old_id: git2::Oid::zero(),
+        new_id: result_blob.as_ref().map(|blob| blob.id()).unwrap_or_else(git2::Oid::zero),
+        status: git2::Delta::Modified,
+        combined_parents: parents_count,
+        is_binary: false,
+        hunks: Vec::new(),
+    };
+
+    if !settings.binary_as_text && result_blob.iter().chain(parent_blobs.iter().flatten()).any(|blob| blob.is_binary()) {
+        file_diff.is_binary = true;
+        return Ok(file_diff);
+    }
+
+    let result_content = result_blob.as_ref().map(|blob| blob.content()).unwrap_or(&[]);
+    let result_lines = split_lines(result_content);
+
+    // added[i][k]: line k of the result is added relative to parent i
+    let mut added = vec![vec![false; result_lines.len()]; parents_count];
+    // Lines lost from the parents, by the index of the result line they precede
+    let mut lost = HashMap::<usize, Vec<CombinedLine>>::new();
+
+    for (parent_idx, parent_blob) in parent_blobs.iter().enumerate() {
+        let parent_content = parent_blob.as_ref().map(|blob| blob.content()).unwrap_or(&[]);
+
+        let mut diff_options = settings.diff_options();
+        diff_options.context_lines(0);
+        let patch = git2::Patch::from_buffers(parent_content, None, result_content, None, Some(&mut diff_options))?;
+
+        for hunk_idx in 0..patch.num_hunks() {
+            let (hunk, lines_count) = patch.hunk(hunk_idx)?;
+            let position = if hunk.new_lines() == 0 { hunk.new_start() } else { hunk.new_start() - 1 } as usize;
+            let lost_here = lost.entry(position).or_default();
+            // Lines lost from several parents are shown once; matching goes forward to keep the order
+            let mut match_from = 0;
+
+            for line_idx in 0..lines_count {
+                let line = patch.line_in_hunk(hunk_idx, line_idx)?;
+                match line.origin_value() {
+                    git2::DiffLineType::Addition => {
+                        if let Some(lineno) = line.new_lineno() {
+                            if let Some(flag) = added[parent_idx].get_mut(lineno as usize - 1) {
+                                *flag = true;
+                            }
+                        }
+                    },
+                    git2::DiffLineType::Deletion => {
+                        let content = String::from_utf8_lossy(line.content()).trim_end_matches(&['\n', '\r'][..]).to_owned();
+                        let existing = lost_here[match_from..].iter()
+                            .position(|lost_line| lost_line.content == content && lost_line.markers[parent_idx] == ' ');
+                        match existing {
+                            Some(offset) => {
+                                lost_here[match_from + offset].markers[parent_idx] = '-';
+                                match_from += offset + 1;
+                            },
+                            None => {
+                                let mut markers = vec![' '; parents_count];
+                                markers[parent_idx] = '-';
+                                lost_here.push(CombinedLine { markers, content, lineno: None });
+                                match_from = lost_here.len();
+                            },
+                        }
+                    },
+                    _ => {},
+                }
+            }
+        }
+    }
+
+    let mut lines = Vec::<CombinedLine>::new();
+    for idx in 0..=result_lines.len() {
+        if let Some(lost_lines) = lost.remove(&idx) {
+            lines.extend(lost_lines);
+        }
+        if let Some(content) = result_lines.get(idx) {
+            lines.push(CombinedLine {
+                markers: added.iter().map(|added| if added[idx] { '+' } else { ' ' }).collect(),
+                content: content.clone(),
+                lineno: Some(idx as u32 + 1),
+            });
+        }
+    }
+
+    file_diff.hunks = group_into_hunks(&lines, parents_count, dense, settings.context_lines as usize);
+
+    Ok(file_diff)
+}
+
+fn group_into_hunks(lines: &[CombinedLine], parents_count: usize, dense: bool, context_lines: usize) -> Vec<DiffHunk> {
+    let mut ranges = Vec::<(usize, usize)>::new();
+    for (idx, _) in lines.iter().enumerate().filter(|(_, line)| line.is_interesting()) {
+        let start = idx.saturating_sub(context_lines);
+        let end = (idx + context_lines + 1).min(lines.len());
+        match ranges.last_mut() {
+            Some(last) if last.1 >= start => last.1 = end,
+            _ => ranges.push((start, end)),
+        }
+    }
+
+    let at_signs = "@".repeat(parents_count + 1);
+
+    ranges.into_iter()
+        .filter(|(start, end)| {
+            // A hunk is uninteresting when the result there is the same as in one of the parents
+            !dense || (0..parents_count).all(|parent_idx| lines[*start..*end].iter().any(|line| line.markers[parent_idx] != ' '))
+        })
+        .map(|(start, end)| {
+            let hunk_lines = &lines[start..end];
+            let result_linenos: Vec<u32> = hunk_lines.iter().filter_map(|line| line.lineno).collect();
+            let header = format!(
+                "{} +{},{} {}",
+                at_signs,
+                result_linenos.first().cloned().unwrap_or(0),
+                result_linenos.len(),
+                at_signs
+            );
+
+            DiffHunk {
+                header,
+                old_start: 0,
+                new_start: result_linenos.first().cloned().unwrap_or(0),
+                lines: hunk_lines.iter().map(|line| DiffLine {
+                    kind: if line.markers.contains(&'-') {
+                        DiffLineKind::Removed
+                    } else if line.markers.contains(&'+') {
+                        DiffLineKind::Added
+                    } else {
+                        DiffLineKind::Context
+                    },
+                    old_lineno: None,
+                    new_lineno: line.lineno,
+                    markers: line.markers.iter().collect(),
+                    content: line.content.clone(),
+                    raw: Vec::new(),
+                    highlights: LineHighlights::new(),
+                }).collect(),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,63 @@
+/// Recommended maximum length of the summary line
+pub const SUMMARY_MAX_LENGTH: usize = 50;
+/// Recommended maximum length of the lines of the message body
+pub const BODY_MAX_LENGTH: usize = 72;
+
+/// Builds a commit message from the summary line and the body
+pub fn compose_message(summary: &str, body: &str) -> String {
+    let mut message = summary.trim().to_owned();
+
+    let body = body.trim();
+    if !body.is_empty() {
+        message.push_str("\n\n");
+        message.push_str(body);
+    }
+
+    message.push('\n');
+    message
+}
+
+/// Appends a `Signed-off-by` trailer for `identity` unless the message already has it
+pub fn add_sign_off(message: &str, identity: &str) -> String {
+    let mut message = message.trim_end().to_owned();
+
+    let trailer = format!("Signed-off-by: {}", identity);
+    if !message.lines().any(|line| line.trim() == trailer) {
+        // Trailers are separated from the message by a blank line, unless they follow other trailers
+        let last_line_is_trailer = message.lines().last()
+            .map(|line| line.starts_with("Signed-off-by: ") || line.starts_with("Co-authored-by: "))
+            .unwrap_or(false);
+        message.push_str(if last_line_is_trailer { "\n" } else { "\n\n" });
+        message.push_str(&trailer);
+    }
+
+    message.push('\n');
+    message
+}
+
+/// Splits a commit message into the summary line and the body
+pub fn split_message(message: &str) -> (String, String) {
+    let mut lines = message.trim().splitn(2, '\n');
+    let summary = lines.next().unwrap_or("").trim().to_owned();
+    let body = lines.next().unwrap_or("").trim().to_owned();
+
+    (summary, body)
+}
+
+/// Parses an identity in the `Name <email>` form
+pub fn parse_identity(identity: &str) -> Option<(String, String)> {
+    let identity = identity.trim();
+    let email_start = identity.rfind('<')?;
+    let email_end = identity.rfind('>')?;
+    if email_end != identity.len() - 1 || email_end < email_start {
+        return None;
+    }
+
+    let name = identity[..email_start].trim();
+    let email = identity[email_start + 1..email_end].trim();
+    if name.is_empty() || email.is_empty() {
+        return None;
+    }
+
+    Some((name.to_owned(), email.to_owned()))
+}
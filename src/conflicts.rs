@@ -0,0 +1,104 @@
+use std::path::Path;
+
+/// Way to resolve a conflicted file
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ConflictResolution {
+    /// Use the version of HEAD
+    TakeOurs,
+    /// Use the version being merged or applied
+    TakeTheirs,
+    /// Use the working tree file as edited by the user
+    MarkResolved,
+}
+
+/// Contents of the three versions of a conflicted file; `None` if the file does not exist in a version
+#[derive(Debug, Clone, Default)]
+pub struct ConflictSides {
+    pub base: Option<Vec<u8>>,
+    pub ours: Option<Vec<u8>>,
+    pub theirs: Option<Vec<u8>>,
+}
+
+/// Paths of the files with conflicts in the index
+pub fn conflicted_paths(repo: &git2::Repository) -> Result<Vec<String>, git2::Error> {
+    let index = repo.index()?;
+    let mut paths = Vec::new();
+    for conflict in index.conflicts()? {
+        let conflict = conflict?;
+        if let Some(entry) = conflict.our.or(conflict.their).or(conflict.ancestor) {
+            paths.push(String::from_utf8_lossy(&entry.path).to_string());
+        }
+    }
+
+    Ok(paths)
+}
+
+fn find_conflict(repo: &git2::Repository, path: &str) -> Result<git2::IndexConflict, git2::Error> {
+    let index = repo.index()?;
+    for conflict in index.conflicts()? {
+        let conflict = conflict?;
+        let conflict_path = conflict.our.as_ref().or(conflict.their.as_ref()).or(conflict.ancestor.as_ref())
+            .map(|entry| entry.path.clone());
+        if conflict_path.as_deref() == Some(path.as_bytes()) {
+            return Ok(conflict);
+        }
+    }
+
+    Err(git2::Error::from_str(&format!("File {} has no conflicts", path)))
+}
+
+pub fn load_conflict_sides(repo: &git2::Repository, path: &str) -> Result<ConflictSides, git2::Error> {
+    let conflict = find_conflict(repo, path)?;
+    let load = |entry: Option<git2::IndexEntry>| -> Result<Option<Vec<u8>>, git2::Error> {
+        match entry {
+            Some(entry) => Ok(Some(repo.find_blob(entry.id)?.content().to_owned())),
+            None => Ok(None),
+        }
+    };
+
+    Ok(ConflictSides {
+        base: load(conflict.ancestor)?,
+        ours: load(conflict.our)?,
+        theirs: load(conflict.their)?,
+    })
+}
+
+/// Resolves the conflict of a file, writing the chosen version to the working tree and staging it
+pub fn resolve_conflict(repo: &git2::Repository, path: &str, resolution: ConflictResolution) -> Result<(), git2::Error> {
+    let workdir = repo.workdir().ok_or_else(|| git2::Error::from_str("Repository has no working tree"))?.to_owned();
+    let file_path = workdir.join(path);
+
+    let chosen = match resolution {
+        ConflictResolution::TakeOurs => Some(find_conflict(repo, path)?.our),
+        ConflictResolution::TakeTheirs => Some(find_conflict(repo, path)?.their),
+        ConflictResolution::MarkResolved => None,
+    };
+    match chosen {
+        Some(Some(entry)) => {
+            let blob = repo.find_blob(entry.id)?;
+            std::fs::write(&file_path, blob.content())
+                .map_err(|e| git2::Error::from_str(&format!("Error writing {}: {}", path, e)))?;
+        },
+        // The chosen side deleted the file
+        Some(None) if file_path.symlink_metadata().is_ok() => {
+            std::fs::remove_file(&file_path)
+                .map_err(|e| git2::Error::from_str(&format!("Error removing {}: {}", path, e)))?;
+        },
+        Some(None) | None => {},
+    }
+
+    let mut index = repo.index()?;
+    if file_path.symlink_metadata().is_ok() {
+        index.add_path(Path::new(path))?;
+    } else {
+        index.remove_path(Path::new(path))?;
+    }
+    index.write()
+}
+
+/// Drops the changes of a merge, cherry-pick or revert in progress, resetting the index and the working tree to HEAD
+pub fn abort(repo: &git2::Repository) -> Result<(), git2::Error> {
+    let head = repo.head()?.peel_to_commit()?;
+    repo.reset(head.as_object(), git2::ResetType::Hard, None)?;
+    repo.cleanup_state()
+}
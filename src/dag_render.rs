@@ -0,0 +1,94 @@
+use crate::dag_layout::{Node, LayoutRow, CellId};
+
+pub const CELL_WIDTH: f64 = 14.0;
+const DOT_RADIUS: f64 = 4.0;
+const LINE_WIDTH: f64 = 2.0;
+
+const LANE_COLORS: [(f64, f64, f64); 8] = [
+    (0.20, 0.40, 0.80),
+    (0.85, 0.25, 0.20),
+    (0.15, 0.60, 0.25),
+    (0.75, 0.45, 0.05),
+    (0.55, 0.25, 0.70),
+    (0.05, 0.60, 0.65),
+    (0.80, 0.25, 0.55),
+    (0.45, 0.45, 0.45),
+];
+
+pub fn row_width<TNode: Node>(row: &LayoutRow<TNode>, next_row: Option<&LayoutRow<TNode>>) -> f64 {
+    let cells_count = row.cells.len().max(next_row.map(|r| r.cells.len()).unwrap_or(0));
+
+    CELL_WIDTH * cells_count as f64
+}
+
+/// Draws a single row of the commit graph: the lower halves of the links coming from the previous row,
+/// the upper halves of the links going to the next row and the dot of the row's own node.
+///
+/// A link between two rows is split at the middle of its horizontal span, where the row boundary is, and
+/// link colors are picked by the id of the cell the link leads to, so that both halves of a link
+/// (drawn as parts of two different rows) join up and get the same color.
+pub fn render_row<TNode: Node>(
+    cr: &cairo::Context,
+    row: &LayoutRow<TNode>,
+    next_row: Option<&LayoutRow<TNode>>,
+    height: f64,
+) {
+    let mid_y = height / 2.0;
+
+    cr.set_line_width(LINE_WIDTH);
+    cr.set_line_cap(cairo::LineCap::Round);
+
+    for (from, to) in row.top_links.iter() {
+        if let Some(cell) = row.cells.get(to.0 as usize) {
+            set_lane_color(cr, &cell.id);
+            draw_link(cr, link_mid_x(*from, *to), 0.0, cell_x(*to), mid_y);
+        }
+    }
+
+    if let Some(next_row) = next_row {
+        for (from, to) in row.bot_links.iter() {
+            if let Some(cell) = next_row.cells.get(to.0 as usize) {
+                set_lane_color(cr, &cell.id);
+                draw_link(cr, cell_x(*from), mid_y, link_mid_x(*from, *to), height);
+            }
+        }
+    }
+
+    if let Some(cell) = row.cells.get(row.active_cell.0 as usize) {
+        let x = cell_x(row.active_cell);
+        cr.arc(x, mid_y, DOT_RADIUS, 0.0, 2.0 * std::f64::consts::PI);
+        set_lane_color(cr, &cell.id);
+        cr.fill_preserve();
+        cr.set_source_rgb(0.1, 0.1, 0.1);
+        cr.set_line_width(1.0);
+        cr.stroke();
+    }
+}
+
+fn cell_x(cell: CellId) -> f64 {
+    CELL_WIDTH * cell.0 as f64 + CELL_WIDTH / 2.0
+}
+
+/// Where the link between cells of adjacent rows crosses the boundary of the rows
+fn link_mid_x(from: CellId, to: CellId) -> f64 {
+    (cell_x(from) + cell_x(to)) / 2.0
+}
+
+fn draw_link(cr: &cairo::Context, x1: f64, y1: f64, x2: f64, y2: f64) {
+    cr.move_to(x1, y1);
+    if (x1 - x2).abs() < f64::EPSILON {
+        cr.line_to(x2, y2);
+    } else {
+        let y_mid = (y1 + y2) / 2.0;
+        cr.curve_to(x1, y_mid, x2, y_mid, x2, y2);
+    }
+    cr.stroke();
+}
+
+fn set_lane_color<TId: std::hash::Hash>(cr: &cairo::Context, id: &TId) {
+    use std::hash::Hasher;
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    id.hash(&mut hasher);
+    let (r, g, b) = LANE_COLORS[(hasher.finish() % LANE_COLORS.len() as u64) as usize];
+    cr.set_source_rgb(r, g, b);
+}
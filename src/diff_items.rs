@@ -0,0 +1,116 @@
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ChangeType {
+    None,
+    Deleted,
+    Added,
+    Renamed,
+    Modified,
+    Copied,
+    TypeChange,
+    ModeChange,
+    /// Unmerged file with conflicts in the index
+    Conflicted,
+}
+
+impl ChangeType {
+    pub fn from_delta(delta: &git2::DiffDelta) -> ChangeType {
+        match delta.status() {
+            git2::Delta::Added | git2::Delta::Untracked => ChangeType::Added,
+            git2::Delta::Deleted => ChangeType::Deleted,
+            git2::Delta::Renamed => ChangeType::Renamed,
+            git2::Delta::Copied => ChangeType::Copied,
+            git2::Delta::Typechange => ChangeType::TypeChange,
+            git2::Delta::Conflicted => ChangeType::Conflicted,
+            git2::Delta::Modified
+                if delta.old_file().id() == delta.new_file().id() && delta.old_file().mode() != delta.new_file().mode()
+                => ChangeType::ModeChange,
+            _ => ChangeType::Modified,
+        }
+    }
+
+    /// Value stored in the change type column of the diff items list store
+    pub fn code(self) -> i32 {
+        match self {
+            ChangeType::None => 0,
+            ChangeType::Deleted => 1,
+            ChangeType::Added => 2,
+            ChangeType::Renamed => 3,
+            ChangeType::Modified => 4,
+            ChangeType::Copied => 5,
+            ChangeType::TypeChange => 6,
+            ChangeType::ModeChange => 7,
+            ChangeType::Conflicted => 8,
+        }
+    }
+
+    pub fn icon_name(self) -> &'static str {
+        match self {
+            ChangeType::None => "",
+            ChangeType::Deleted => "list-remove",
+            ChangeType::Added => "list-add",
+            ChangeType::Renamed => "go-next",
+            ChangeType::Modified => "document-edit",
+            ChangeType::Copied => "edit-copy",
+            ChangeType::TypeChange => "view-refresh",
+            ChangeType::ModeChange => "document-properties",
+            ChangeType::Conflicted => "dialog-warning",
+        }
+    }
+
+    /// Short colored label in the spirit of `git status --short`
+    pub fn markup(self) -> &'static str {
+        match self {
+            ChangeType::None => "",
+            ChangeType::Deleted => "<span foreground=\"#c0392b\" weight=\"bold\">D</span>",
+            ChangeType::Added => "<span foreground=\"#27ae60\" weight=\"bold\">A</span>",
+            ChangeType::Renamed => "<span foreground=\"#2874a6\" weight=\"bold\">R</span>",
+            ChangeType::Modified => "<span foreground=\"#b9770e\" weight=\"bold\">M</span>",
+            ChangeType::Copied => "<span foreground=\"#7d3c98\" weight=\"bold\">C</span>",
+            ChangeType::TypeChange => "<span foreground=\"#7f8c8d\" weight=\"bold\">T</span>",
+            ChangeType::ModeChange => "<span foreground=\"#7f8c8d\" weight=\"bold\">X</span>",
+            ChangeType::Conflicted => "<span foreground=\"#c0392b\" weight=\"bold\">U</span>",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DiffItem {
+    pub path: String,
+    pub change_type: ChangeType,
+    /// Path of the file this one was renamed or copied from; empty otherwise
+    pub old_path: String,
+}
+
+impl DiffItem {
+    pub fn display_path(&self) -> String {
+        if self.old_path.is_empty() {
+            self.path.clone()
+        } else {
+            format!("{} \u{2192} {}", self.old_path, self.path)
+        }
+    }
+}
+
+/// Detects renames and copies in `diff` and lists its deltas.
+pub fn collect_diff_items(diff: &mut git2::Diff, find_options: &mut git2::DiffFindOptions) -> Result<Vec<DiffItem>, git2::Error> {
+    diff.find_similar(Some(find_options))?;
+
+    Ok(diff.deltas().map(|delta| {
+        let path = String::from_utf8_lossy(
+            match (delta.new_file().path_bytes(), delta.old_file().path_bytes()) {
+                (Some(bytes), _) => bytes,
+                (None, Some(bytes)) => bytes,
+                (None, None) => b"(none)",
+            }
+        ).to_string();
+        let change_type = ChangeType::from_delta(&delta);
+        let old_path = match change_type {
+            ChangeType::Renamed | ChangeType::Copied => delta.old_file().path_bytes()
+                .map(|bytes| String::from_utf8_lossy(bytes).to_string())
+                .unwrap_or_default(),
+            _ => String::new(),
+        };
+
+        DiffItem { path, change_type, old_path }
+    }).collect())
+}
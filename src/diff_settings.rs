@@ -0,0 +1,161 @@
+/// Prefix of the keys in the repository config under which diff settings are stored
+const CONFIG_SECTION: &str = "stainless-git.diff";
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum WhitespaceMode {
+    Show,
+    IgnoreAtEol,
+    IgnoreChange,
+    IgnoreAll,
+}
+
+impl WhitespaceMode {
+    pub const ALL: [WhitespaceMode; 4] = [WhitespaceMode::Show, WhitespaceMode::IgnoreAtEol, WhitespaceMode::IgnoreChange, WhitespaceMode::IgnoreAll];
+
+    pub fn id(self) -> &'static str {
+        match self {
+            WhitespaceMode::Show => "show",
+            WhitespaceMode::IgnoreAtEol => "ignore-eol",
+            WhitespaceMode::IgnoreChange => "ignore-change",
+            WhitespaceMode::IgnoreAll => "ignore-all",
+        }
+    }
+
+    pub fn title(self) -> &'static str {
+        match self {
+            WhitespaceMode::Show => "Show whitespace changes",
+            WhitespaceMode::IgnoreAtEol => "Ignore whitespace at end of line",
+            WhitespaceMode::IgnoreChange => "Ignore changes in amount of whitespace",
+            WhitespaceMode::IgnoreAll => "Ignore all whitespace",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<WhitespaceMode> {
+        Self::ALL.iter().cloned().find(|mode| mode.id() == id)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DiffAlgorithm {
+    Myers,
+    Minimal,
+    Patience,
+}
+
+impl DiffAlgorithm {
+    pub const ALL: [DiffAlgorithm; 3] = [DiffAlgorithm::Myers, DiffAlgorithm::Minimal, DiffAlgorithm::Patience];
+
+    pub fn id(self) -> &'static str {
+        match self {
+            DiffAlgorithm::Myers => "myers",
+            DiffAlgorithm::Minimal => "minimal",
+            DiffAlgorithm::Patience => "patience",
+        }
+    }
+
+    pub fn title(self) -> &'static str {
+        match self {
+            DiffAlgorithm::Myers => "Default (Myers)",
+            DiffAlgorithm::Minimal => "Minimal",
+            DiffAlgorithm::Patience => "Patience",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<DiffAlgorithm> {
+        Self::ALL.iter().cloned().find(|algorithm| algorithm.id() == id)
+    }
+}
+
+/// Options applied to all diffs shown for a repository; persisted in the repository's local config
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct DiffSettings {
+    pub whitespace: WhitespaceMode,
+    pub context_lines: u32,
+    pub algorithm: DiffAlgorithm,
+    /// Show changes in binary files as if they were text
+    pub binary_as_text: bool,
+    /// Similarity percentage for detecting renames and copies
+    pub rename_threshold: u16,
+}
+
+impl Default for DiffSettings {
+    fn default() -> Self {
+        DiffSettings {
+            whitespace: WhitespaceMode::Show,
+            context_lines: 3,
+            algorithm: DiffAlgorithm::Myers,
+            binary_as_text: false,
+            rename_threshold: 50,
+        }
+    }
+}
+
+impl DiffSettings {
+    /// Reads settings from the repository config; missing or invalid values are replaced by defaults
+    pub fn load(repo: &git2::Repository) -> DiffSettings {
+        let mut settings = DiffSettings::default();
+
+        let config = match repo.config() {
+            Ok(config) => config,
+            Err(_) => return settings,
+        };
+        let key = |name: &str| format!("{}.{}", CONFIG_SECTION, name);
+
+        if let Some(whitespace) = config.get_string(&key("whitespace")).ok().and_then(|id| WhitespaceMode::from_id(&id)) {
+            settings.whitespace = whitespace;
+        }
+        if let Ok(context_lines) = config.get_i32(&key("contextLines")) {
+            settings.context_lines = context_lines.max(0) as u32;
+        }
+        if let Some(algorithm) = config.get_string(&key("algorithm")).ok().and_then(|id| DiffAlgorithm::from_id(&id)) {
+            settings.algorithm = algorithm;
+        }
+        if let Ok(binary_as_text) = config.get_bool(&key("binaryAsText")) {
+            settings.binary_as_text = binary_as_text;
+        }
+        if let Ok(rename_threshold) = config.get_i32(&key("renameThreshold")) {
+            settings.rename_threshold = rename_threshold.clamp(0, 100) as u16;
+        }
+
+        settings
+    }
+
+    pub fn save(&self, repo: &git2::Repository) -> Result<(), git2::Error> {
+        let mut config = repo.config()?.open_level(git2::ConfigLevel::Local)?;
+        let key = |name: &str| format!("{}.{}", CONFIG_SECTION, name);
+
+        config.set_str(&key("whitespace"), self.whitespace.id())?;
+        config.set_i32(&key("contextLines"), self.context_lines as i32)?;
+        config.set_str(&key("algorithm"), self.algorithm.id())?;
+        config.set_bool(&key("binaryAsText"), self.binary_as_text)?;
+        config.set_i32(&key("renameThreshold"), i32::from(self.rename_threshold))?;
+
+        Ok(())
+    }
+
+    pub fn diff_options(&self) -> git2::DiffOptions {
+        let mut options = git2::DiffOptions::new();
+
+        options.context_lines(self.context_lines);
+        options.ignore_whitespace_eol(self.whitespace == WhitespaceMode::IgnoreAtEol);
+        options.ignore_whitespace_change(self.whitespace == WhitespaceMode::IgnoreChange);
+        options.ignore_whitespace(self.whitespace == WhitespaceMode::IgnoreAll);
+        options.minimal(self.algorithm == DiffAlgorithm::Minimal);
+        options.patience(self.algorithm == DiffAlgorithm::Patience);
+        options.force_text(self.binary_as_text);
+
+        options
+    }
+
+    pub fn find_options(&self) -> git2::DiffFindOptions {
+        let mut options = git2::DiffFindOptions::new();
+
+        options.renames(true);
+        options.copies(true);
+        options.rename_threshold(self.rename_threshold);
+        options.copy_threshold(self.rename_threshold);
+        options.ignore_whitespace(self.whitespace != WhitespaceMode::Show);
+
+        options
+    }
+}
@@ -22,7 +22,7 @@ pub fn dump_git_layout(repo_path: &std::ffi::OsString) {
     let repo = git2::Repository::discover(&repo_path).unwrap();
 
     let mut revwalk = repo.revwalk().unwrap();
-    revwalk.set_sorting(git2::Sort::TIME | git2::Sort::TOPOLOGICAL/* | git2::Sort::REVERSE*/);
+    revwalk.set_sorting(git2::Sort::TIME | git2::Sort::TOPOLOGICAL/* | git2::Sort::REVERSE*/).unwrap();
     revwalk.push_glob("*").unwrap();
 
     let nodes = revwalk.map(|oid_err|
@@ -0,0 +1,155 @@
+use crate::syntax_highlight::{LineHighlights, MAX_HIGHLIGHTED_FILE_SIZE, highlight_file_diff};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DiffLineKind {
+    Context,
+    Added,
+    Removed,
+    /// "\ No newline at end of file" marker
+    NoNewline,
+}
+
+#[derive(Debug, Clone)]
+pub struct DiffLine {
+    pub kind: DiffLineKind,
+    pub old_lineno: Option<u32>,
+    pub new_lineno: Option<u32>,
+    /// Per-parent markers of a line of a combined diff; empty for regular diffs
+    pub markers: String,
+    /// Line content without the trailing newline
+    pub content: String,
+    /// Bytes of the line as they are in the file, including the line ending if there is one, to build patches from.
+    /// Empty for "No newline" markers and lines of combined diffs.
+    pub raw: Vec<u8>,
+    pub highlights: LineHighlights,
+}
+
+#[derive(Debug, Clone)]
+pub struct DiffHunk {
+    pub header: String,
+    /// First line of the hunk in the old and the new version, as in the hunk header
+    pub old_start: u32,
+    pub new_start: u32,
+    pub lines: Vec<DiffLine>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FileDiff {
+    pub old_path: Option<String>,
+    pub new_path: Option<String>,
+    /// Blob ids of the old and new versions; zero if the file is absent or the id is not known
+    pub old_id: git2::Oid,
+    pub new_id: git2::Oid,
+    pub status: git2::Delta,
+    /// Number of parents for a combined diff of a merge commit; 0 for regular diffs
+    pub combined_parents: usize,
+    pub is_binary: bool,
+    pub hunks: Vec<DiffHunk>,
+}
+
+impl FileDiff {
+    pub fn from_patch(patch: &git2::Patch) -> Result<FileDiff, git2::Error> {
+        let mut hunks = Vec::with_capacity(patch.num_hunks());
+        for hunk_idx in 0..patch.num_hunks() {
+            let (hunk, lines_count) = patch.hunk(hunk_idx)?;
+            let mut lines = Vec::with_capacity(lines_count);
+            for line_idx in 0..lines_count {
+                let line = patch.line_in_hunk(hunk_idx, line_idx)?;
+                let kind = match line.origin_value() {
+                    git2::DiffLineType::Addition => DiffLineKind::Added,
+                    git2::DiffLineType::Deletion => DiffLineKind::Removed,
+                    git2::DiffLineType::ContextEOFNL | git2::DiffLineType::AddEOFNL | git2::DiffLineType::DeleteEOFNL
+                        => DiffLineKind::NoNewline,
+                    _ => DiffLineKind::Context,
+                };
+                let content = String::from_utf8_lossy(line.content());
+                let content = match kind {
+                    // libgit2 reports the marker as "\n\\ No newline at end of file\n"
+                    DiffLineKind::NoNewline => content.trim_matches(&['\n', '\r'][..]).trim_start_matches("\\ "),
+                    _ => content.trim_end_matches(&['\n', '\r'][..]),
+                };
+                lines.push(DiffLine {
+                    kind,
+                    old_lineno: line.old_lineno(),
+                    new_lineno: line.new_lineno(),
+                    markers: String::new(),
+                    content: content.to_owned(),
+                    raw: if kind == DiffLineKind::NoNewline { Vec::new() } else { line.content().to_vec() },
+                    highlights: LineHighlights::new(),
+                });
+            }
+
+            hunks.push(DiffHunk {
+                header: String::from_utf8_lossy(hunk.header()).trim_end().to_owned(),
+                old_start: hunk.old_start(),
+                new_start: hunk.new_start(),
+                lines,
+            });
+        }
+
+        let mut result = Self::from_delta(&patch.delta());
+        result.hunks = hunks;
+
+        Ok(result)
+    }
+
+    /// File diff without any hunks, e.g. for unchanged or binary files
+    pub fn from_delta(delta: &git2::DiffDelta) -> FileDiff {
+        let path_to_string = |file: git2::DiffFile| file.path_bytes().map(|bytes| String::from_utf8_lossy(bytes).to_string());
+
+        FileDiff {
+            old_path: path_to_string(delta.old_file()),
+            new_path: path_to_string(delta.new_file()),
+            old_id: delta.old_file().id(),
+            new_id: delta.new_file().id(),
+            status: delta.status(),
+            combined_parents: 0,
+            is_binary: delta.flags().contains(git2::DiffFlags::BINARY),
+            hunks: Vec::new(),
+        }
+    }
+
+    /// Whether single hunks and lines of this diff can be applied separately
+    pub fn is_partially_applicable(&self) -> bool {
+        self.status == git2::Delta::Modified && !self.is_binary && self.combined_parents == 0
+    }
+
+    /// Fills in syntax highlighting of the lines, using full contents of the old and new blobs if they are available
+    pub fn highlight(&mut self, repo: &git2::Repository) {
+        if self.is_binary || self.hunks.is_empty() {
+            return;
+        }
+
+        let blob_text = |id: git2::Oid| {
+            if id.is_zero() {
+                return None;
+            }
+            let blob = repo.find_blob(id).ok()?;
+            if blob.is_binary() || blob.size() > MAX_HIGHLIGHTED_FILE_SIZE {
+                return None;
+            }
+            Some(String::from_utf8_lossy(blob.content()).to_string())
+        };
+
+        let old_content = blob_text(self.old_id);
+        let new_content = blob_text(self.new_id);
+
+        highlight_file_diff(self, old_content.as_deref(), new_content.as_deref());
+    }
+
+    /// Finds the delta of `diff` that changes `path` and builds its file diff.
+    /// Deltas are matched by their new path first, so that renamed files are found by their new name.
+    pub fn find_in_diff(diff: &git2::Diff, path: &str) -> Result<Option<FileDiff>, git2::Error> {
+        let path = path.as_bytes();
+        let idx = diff.deltas().position(|delta| delta.new_file().path_bytes() == Some(path))
+            .or_else(|| diff.deltas().position(|delta| delta.old_file().path_bytes() == Some(path)));
+
+        match idx {
+            None => Ok(None),
+            Some(idx) => match git2::Patch::from_diff(diff, idx)? {
+                Some(patch) => Self::from_patch(&patch).map(Some),
+                None => Ok(diff.get_delta(idx).map(|delta| Self::from_delta(&delta))),
+            },
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Commits that changed a file, like `git log --follow`
+#[derive(Debug, Clone)]
+pub struct FileHistory {
+    /// Commits changing the file in the order of the walk, each with its parents rewritten to the closest
+    /// ancestors that also change the file
+    pub commits: Vec<(git2::Oid, Vec<git2::Oid>)>,
+    /// Names of the file, the one it was asked for first, then the names it had before each rename
+    pub paths: Vec<String>,
+}
+
+/// Walks the commits of `revwalk`, newest first, keeping those that change the file at `path`.
+/// When the file turns out to be added by renaming another file, the older commits are searched for changes
+/// to the file under its old name.
+pub fn file_history(repo: &git2::Repository, revwalk: git2::Revwalk, path: &str) -> Result<FileHistory, git2::Error> {
+    let mut path = path.to_owned();
+    let mut paths = vec![path.clone()];
+    // Each walked commit with its parents and whether it changes the file
+    let mut walked = Vec::<(git2::Oid, Vec<git2::Oid>, bool)>::new();
+
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        let entry_id = |tree: &git2::Tree| tree.get_path(Path::new(&path)).ok().map(|entry| entry.id());
+
+        let current = entry_id(&commit.tree()?);
+        let in_parents = commit.parents()
+            .map(|parent| parent.tree().map(|tree| entry_id(&tree)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Like `git log`, a merge changes the file only if its version differs from all parents
+        let changes = if in_parents.is_empty() {
+            current.is_some()
+        } else {
+            in_parents.iter().all(|in_parent| *in_parent != current)
+        };
+        if changes && current.is_some() && in_parents.as_slice() == [None] {
+            if let Some(old_path) = renamed_from(repo, &commit, &path)? {
+                paths.push(old_path.clone());
+                path = old_path;
+            }
+        }
+
+        walked.push((commit.id(), commit.parent_ids().collect(), changes));
+    }
+
+    // Parents come after their children in the walk; going backwards, each commit is mapped to the closest
+    // commits changing the file among itself and its ancestors
+    let mut closest = HashMap::<git2::Oid, Vec<git2::Oid>>::new();
+    let mut commits = Vec::new();
+    for (oid, parents, changes) in walked.into_iter().rev() {
+        let mut rewritten_parents = Vec::new();
+        for parent in parents.iter() {
+            for ancestor in closest.get(parent).into_iter().flatten() {
+                if !rewritten_parents.contains(ancestor) {
+                    rewritten_parents.push(*ancestor);
+                }
+            }
+        }
+
+        if changes {
+            closest.insert(oid, vec![oid]);
+            commits.push((oid, rewritten_parents));
+        } else {
+            closest.insert(oid, rewritten_parents);
+        }
+    }
+    commits.reverse();
+
+    Ok(FileHistory { commits, paths })
+}
+
+/// Former path of the file at `path` if `commit` renamed it
+pub fn renamed_from(repo: &git2::Repository, commit: &git2::Commit, path: &str) -> Result<Option<String>, git2::Error> {
+    let parent_tree = commit.parent(0)?.tree()?;
+    let mut diff = repo.diff_tree_to_tree(Some(&parent_tree), Some(&commit.tree()?), None)?;
+    diff.find_similar(Some(git2::DiffFindOptions::new().renames(true)))?;
+
+    let old_path = diff.deltas()
+        .filter(|delta| delta.status() == git2::Delta::Renamed)
+        .find(|delta| delta.new_file().path() == Some(Path::new(path)))
+        .and_then(|delta| delta.old_file().path().map(|old_path| old_path.to_string_lossy().to_string()));
+
+    Ok(old_path)
+}
@@ -1,8 +1,29 @@
 #[macro_use]
 mod async_ui;
 mod screens;
+mod widgets;
 mod dag_layout;
+mod dag_render;
+mod blame;
+mod cherry_pick;
+mod combined_diff;
+mod commit_message;
+mod conflicts;
+mod diff_items;
+mod diff_settings;
 mod dump_git_layout;
+mod file_diff;
+mod file_history;
+mod merge;
+mod partial_patch;
+mod rebase;
+mod reflog;
+mod refs;
+mod ref_labels;
+mod stash;
+mod syntax_highlight;
+mod tree_items;
+mod word_diff;
 
 use gtk::prelude::*;
 
@@ -0,0 +1,88 @@
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum MergeOutcome {
+    /// HEAD already contains the merged commit
+    UpToDate,
+    /// HEAD was moved forward to the merged commit
+    FastForward(git2::Oid),
+    /// Merge commit created
+    Merged(git2::Oid),
+    /// Merging stopped with conflicts in these files, which are left in the index and the working tree
+    Conflicts(Vec<String>),
+}
+
+/// Merges a reference into the current branch: fast-forwards if possible and allowed by the configuration,
+/// otherwise merges and commits the result unless there are conflicts.
+pub fn merge_ref(repo: &git2::Repository, ref_name: &str) -> Result<MergeOutcome, git2::Error> {
+    if repo.state() != git2::RepositoryState::Clean {
+        return Err(git2::Error::from_str("Another operation is in progress; finish or abort it first"));
+    }
+    if repo.head_detached()? {
+        return Err(git2::Error::from_str("HEAD is detached; check out a branch to merge into"));
+    }
+
+    let reference = repo.find_reference(ref_name)?;
+    let their_head = repo.reference_to_annotated_commit(&reference)?;
+    let (analysis, preference) = repo.merge_analysis(&[&their_head])?;
+
+    if analysis.is_up_to_date() {
+        return Ok(MergeOutcome::UpToDate);
+    }
+    if analysis.is_unborn() {
+        return Err(git2::Error::from_str("Current branch has no commits to merge into"));
+    }
+
+    if analysis.is_fast_forward() && !preference.is_no_fast_forward() {
+        let target = their_head.id();
+        crate::refs::checkout_commit(repo, target)?;
+        repo.head()?.set_target(target, &format!("merge {}: Fast-forward", reference.shorthand().unwrap_or(ref_name)))?;
+        return Ok(MergeOutcome::FastForward(target));
+    }
+    if preference.is_fastforward_only() {
+        return Err(git2::Error::from_str("Only fast-forward merges are allowed by merge.ff, and this one is not"));
+    }
+
+    let mut checkout = git2::build::CheckoutBuilder::new();
+    checkout.safe();
+    repo.merge(&[&their_head], None, Some(&mut checkout))?;
+
+    let paths = crate::conflicts::conflicted_paths(repo)?;
+    if !paths.is_empty() {
+        return Ok(MergeOutcome::Conflicts(paths));
+    }
+
+    conclude_merge(repo).map(MergeOutcome::Merged)
+}
+
+/// Commits of MERGE_HEAD while a merge is in progress
+pub fn merge_heads(repo: &git2::Repository) -> Result<Vec<git2::Oid>, git2::Error> {
+    let content = std::fs::read_to_string(repo.path().join("MERGE_HEAD"))
+        .map_err(|e| git2::Error::from_str(&format!("Error reading MERGE_HEAD: {}", e)))?;
+
+    content.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .map(git2::Oid::from_str)
+        .collect()
+}
+
+/// Creates the merge commit of the merge in progress once all conflicts are resolved
+pub fn conclude_merge(repo: &git2::Repository) -> Result<git2::Oid, git2::Error> {
+    let mut index = repo.index()?;
+    if index.has_conflicts() {
+        return Err(git2::Error::from_str("Conflicts have to be resolved and staged first"));
+    }
+    let tree = repo.find_tree(index.write_tree()?)?;
+
+    let head = repo.head()?.peel_to_commit()?;
+    let merged = merge_heads(repo)?.into_iter()
+        .map(|oid| repo.find_commit(oid))
+        .collect::<Result<Vec<_>, _>>()?;
+    let parents: Vec<&git2::Commit> = std::iter::once(&head).chain(merged.iter()).collect();
+
+    let signature = repo.signature()?;
+    let message = repo.message().unwrap_or_else(|_| "Merge\n".to_owned());
+    let oid = repo.commit(Some("HEAD"), &signature, &signature, &message, &tree, &parents)?;
+    repo.cleanup_state()?;
+
+    Ok(oid)
+}
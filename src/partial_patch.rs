@@ -0,0 +1,341 @@
+use std::collections::HashSet;
+
+use crate::file_diff::{FileDiff, DiffLine, DiffLineKind};
+
+/// Changed lines picked in a diff view, by their numbers in the old and in the new version of the file.
+/// Line numbers do not depend on the options of the displayed diff, so the selection also applies to
+/// a diff of the same file made with default options, which is the one that matches the index.
+#[derive(Debug, Clone, Default)]
+pub struct SelectedLines {
+    removed: HashSet<u32>,
+    added: HashSet<u32>,
+}
+
+impl SelectedLines {
+    /// The changed lines of `diff` for which `is_selected(hunk_idx, line_idx)` holds
+    pub fn lines(diff: &FileDiff, is_selected: impl Fn(usize, usize) -> bool) -> SelectedLines {
+        let mut selected = SelectedLines::default();
+        for (hunk_idx, hunk) in diff.hunks.iter().enumerate() {
+            for (line_idx, line) in hunk.lines.iter().enumerate() {
+                if !is_selected(hunk_idx, line_idx) {
+                    continue;
+                }
+                match line.kind {
+                    DiffLineKind::Removed => selected.removed.extend(line.old_lineno),
+                    DiffLineKind::Added => selected.added.extend(line.new_lineno),
+                    _ => {},
+                }
+            }
+        }
+
+        selected
+    }
+
+    /// All lines spanned by the hunks of `diff` for which `is_selected(hunk_idx)` holds, context included,
+    /// so that changes hidden by the diff options, e.g. whitespace changes, are selected along with the hunk
+    pub fn hunks(diff: &FileDiff, is_selected: impl Fn(usize) -> bool) -> SelectedLines {
+        let mut selected = SelectedLines::default();
+        for (_, hunk) in diff.hunks.iter().enumerate().filter(|(hunk_idx, _)| is_selected(*hunk_idx)) {
+            for line in hunk.lines.iter() {
+                selected.removed.extend(line.old_lineno);
+                selected.added.extend(line.new_lineno);
+            }
+        }
+
+        selected
+    }
+
+    pub fn contains(&self, line: &DiffLine) -> bool {
+        match line.kind {
+            DiffLineKind::Removed => line.old_lineno.map(|lineno| self.removed.contains(&lineno)).unwrap_or(false),
+            DiffLineKind::Added => line.new_lineno.map(|lineno| self.added.contains(&lineno)).unwrap_or(false),
+            _ => false,
+        }
+    }
+
+    /// Whether some changed line of `diff` is selected
+    pub fn selects_changes_of(&self, diff: &FileDiff) -> bool {
+        diff.hunks.iter().flat_map(|hunk| hunk.lines.iter()).any(|line| self.contains(line))
+    }
+}
+
+/// Builds a patch that applies only the selected changed lines of `diff`.
+///
+/// Unselected removed lines are kept as context and unselected added lines are dropped.
+/// With `reverse` set the patch undoes the selected changes instead, e.g. to unstage them.
+/// Lines are copied byte for byte, so line endings and non-UTF-8 content are preserved.
+/// Returns `None` if no changed line is selected.
+pub fn build_partial_patch(diff: &FileDiff, reverse: bool, is_selected: impl Fn(usize, usize) -> bool) -> Option<Vec<u8>> {
+    let (old_path, new_path) = match (&diff.old_path, &diff.new_path) {
+        (Some(old_path), Some(new_path)) => (old_path, new_path),
+        _ => return None,
+    };
+    let (old_path, new_path) = if reverse { (new_path, old_path) } else { (old_path, new_path) };
+
+    let mut hunks_text = Vec::new();
+    // Shift of the following hunks caused by the lines added and removed by the previous ones
+    let mut offset: i64 = 0;
+
+    for (hunk_idx, hunk) in diff.hunks.iter().enumerate() {
+        let old_start = if reverse { hunk.new_start } else { hunk.old_start };
+
+        let mut lines: Vec<(u8, &[u8])> = Vec::new();
+        let mut old_count = 0u32;
+        let mut new_count = 0u32;
+        let mut has_changes = false;
+
+        for (line_idx, line) in hunk.lines.iter().enumerate() {
+            let kind = match (line.kind, reverse) {
+                (DiffLineKind::Added, true) => DiffLineKind::Removed,
+                (DiffLineKind::Removed, true) => DiffLineKind::Added,
+                (kind, _) => kind,
+            };
+            let selected = is_selected(hunk_idx, line_idx);
+
+            let prefix = match kind {
+                DiffLineKind::Context => b' ',
+                DiffLineKind::Removed if selected => b'-',
+                DiffLineKind::Removed => b' ',
+                DiffLineKind::Added if selected => b'+',
+                // "No newline" markers are written again below for the lines that are kept
+                DiffLineKind::Added | DiffLineKind::NoNewline => continue,
+            };
+
+            if prefix != b'+' {
+                old_count += 1;
+            }
+            if prefix != b'-' {
+                new_count += 1;
+            }
+            has_changes |= prefix != b' ';
+            lines.push((prefix, &line.raw));
+        }
+
+        // Only the last line of the new version may lack the newline. A line without it that is followed
+        // by other lines gets the newline, so a kept context line is removed and added back with it.
+        let last_new_idx = lines.iter().rposition(|(prefix, _)| *prefix != b'-');
+        let mut body = Vec::new();
+        for (idx, (prefix, raw)) in lines.iter().enumerate() {
+            let has_newline = raw.ends_with(b"\n");
+            if !has_newline && *prefix != b'-' && Some(idx) != last_new_idx {
+                if *prefix == b' ' {
+                    body.push(b'-');
+                    body.extend_from_slice(raw);
+                    body.extend_from_slice(b"\n\\ No newline at end of file\n");
+                }
+                body.push(b'+');
+                body.extend_from_slice(raw);
+                body.push(b'\n');
+                continue;
+            }
+
+            body.push(*prefix);
+            body.extend_from_slice(raw);
+            if !has_newline {
+                body.extend_from_slice(b"\n\\ No newline at end of file\n");
+            }
+        }
+
+        if !has_changes {
+            continue;
+        }
+
+        let new_start = (i64::from(old_start) + offset).max(0);
+        offset += i64::from(new_count) - i64::from(old_count);
+
+        hunks_text.extend_from_slice(format!("@@ -{},{} +{},{} @@\n", old_start, old_count, new_start, new_count).as_bytes());
+        hunks_text.extend_from_slice(&body);
+    }
+
+    if hunks_text.is_empty() {
+        return None;
+    }
+
+    let mut patch = format!("diff --git a/{old} b/{new}\n--- a/{old}\n+++ b/{new}\n", old = old_path, new = new_path).into_bytes();
+    patch.extend_from_slice(&hunks_text);
+
+    Some(patch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_diff::DiffHunk;
+    use crate::syntax_highlight::LineHighlights;
+
+    fn line(kind: DiffLineKind, old_lineno: Option<u32>, new_lineno: Option<u32>, content: &str) -> DiffLine {
+        raw_line(kind, old_lineno, new_lineno, format!("{}\n", content).as_bytes())
+    }
+
+    fn raw_line(kind: DiffLineKind, old_lineno: Option<u32>, new_lineno: Option<u32>, raw: &[u8]) -> DiffLine {
+        DiffLine {
+            kind,
+            old_lineno,
+            new_lineno,
+            markers: String::new(),
+            content: String::from_utf8_lossy(raw).trim_end_matches(&['\n', '\r'][..]).to_owned(),
+            raw: raw.to_vec(),
+            highlights: LineHighlights::new(),
+        }
+    }
+
+    fn single_hunk_diff(old_count: usize, new_count: usize, lines: Vec<DiffLine>) -> FileDiff {
+        FileDiff {
+            old_path: Some("file.txt".to_owned()),
+            new_path: Some("file.txt".to_owned()),
+            old_id: git2::Oid::zero(),
+            new_id: git2::Oid::zero(),
+            status: git2::Delta::Modified,
+            combined_parents: 0,
+            is_binary: false,
+            hunks: vec![DiffHunk {
+                header: format!("@@ -1,{} +1,{} @@", old_count, new_count),
+                old_start: 1,
+                new_start: 1,
+                lines,
+            }],
+        }
+    }
+
+    /// "a b c d" changed to "a B c D e": two replaced lines and an added one in a single hunk
+    fn mixed_hunk_diff() -> FileDiff {
+        single_hunk_diff(4, 5, vec![
+            line(DiffLineKind::Context, Some(1), Some(1), "a"),
+            line(DiffLineKind::Removed, Some(2), None, "b"),
+            line(DiffLineKind::Added, None, Some(2), "B"),
+            line(DiffLineKind::Context, Some(3), Some(3), "c"),
+            line(DiffLineKind::Removed, Some(4), None, "d"),
+            line(DiffLineKind::Added, None, Some(4), "D"),
+            line(DiffLineKind::Added, None, Some(5), "e"),
+        ])
+    }
+
+    #[test]
+    fn stages_selected_lines_of_mixed_hunk() {
+        let diff = mixed_hunk_diff();
+        // Replaces "b" and adds "e", leaving "d" as it is
+        let patch = build_partial_patch(&diff, false, |_, line_idx| [1, 2, 6].contains(&line_idx));
+
+        assert_eq!(patch.as_deref(), Some(concat!(
+            "diff --git a/file.txt b/file.txt\n",
+            "--- a/file.txt\n",
+            "+++ b/file.txt\n",
+            "@@ -1,4 +1,5 @@\n",
+            " a\n",
+            "-b\n",
+            "+B\n",
+            " c\n",
+            " d\n",
+            "+e\n",
+        ).as_bytes()));
+    }
+
+    #[test]
+    fn unstages_selected_lines_of_mixed_hunk() {
+        let diff = mixed_hunk_diff();
+        // Undoes the replacement of "d" only, keeping the other staged changes
+        let patch = build_partial_patch(&diff, true, |_, line_idx| [4, 5].contains(&line_idx));
+
+        assert_eq!(patch.as_deref(), Some(concat!(
+            "diff --git a/file.txt b/file.txt\n",
+            "--- a/file.txt\n",
+            "+++ b/file.txt\n",
+            "@@ -1,5 +1,5 @@\n",
+            " a\n",
+            " B\n",
+            " c\n",
+            "+d\n",
+            "-D\n",
+            " e\n",
+        ).as_bytes()));
+    }
+
+    #[test]
+    fn keeps_crlf_line_endings() {
+        let diff = single_hunk_diff(2, 2, vec![
+            raw_line(DiffLineKind::Context, Some(1), Some(1), b"a\r\n"),
+            raw_line(DiffLineKind::Removed, Some(2), None, b"b\r\n"),
+            raw_line(DiffLineKind::Added, None, Some(2), b"B\r\n"),
+        ]);
+        let patch = build_partial_patch(&diff, false, |_, line_idx| line_idx == 2);
+
+        assert_eq!(patch.as_deref(), Some(concat!(
+            "diff --git a/file.txt b/file.txt\n",
+            "--- a/file.txt\n",
+            "+++ b/file.txt\n",
+            "@@ -1,2 +1,3 @@\n",
+            " a\r\n",
+            " b\r\n",
+            "+B\r\n",
+        ).as_bytes()));
+    }
+
+    #[test]
+    fn keeps_non_utf8_lines() {
+        let diff = single_hunk_diff(1, 1, vec![
+            raw_line(DiffLineKind::Removed, Some(1), None, b"caf\xe9\n"),
+            raw_line(DiffLineKind::Added, None, Some(1), b"caf\xc3\xa9\n"),
+        ]);
+        let patch = build_partial_patch(&diff, false, |_, _| true);
+
+        assert_eq!(patch.as_deref(), Some(&b"diff --git a/file.txt b/file.txt\n--- a/file.txt\n+++ b/file.txt\n@@ -1,1 +1,1 @@\n-caf\xe9\n+caf\xc3\xa9\n"[..]));
+    }
+
+    #[test]
+    fn adds_newline_to_kept_last_line_followed_by_added_lines() {
+        // "x" changed to "y", neither of them ending with a newline
+        let diff = single_hunk_diff(1, 1, vec![
+            raw_line(DiffLineKind::Removed, Some(1), None, b"x"),
+            raw_line(DiffLineKind::NoNewline, None, None, b""),
+            raw_line(DiffLineKind::Added, None, Some(1), b"y"),
+            raw_line(DiffLineKind::NoNewline, None, None, b""),
+        ]);
+        let patch = build_partial_patch(&diff, false, |_, line_idx| line_idx == 2);
+
+        assert_eq!(patch.as_deref(), Some(concat!(
+            "diff --git a/file.txt b/file.txt\n",
+            "--- a/file.txt\n",
+            "+++ b/file.txt\n",
+            "@@ -1,1 +1,2 @@\n",
+            "-x\n",
+            "\\ No newline at end of file\n",
+            "+x\n",
+            "+y\n",
+            "\\ No newline at end of file\n",
+        ).as_bytes()));
+
+        // Unstaging the removal of "x" only puts it back before "y"
+        let patch = build_partial_patch(&diff, true, |_, line_idx| line_idx == 0);
+
+        assert_eq!(patch.as_deref(), Some(concat!(
+            "diff --git a/file.txt b/file.txt\n",
+            "--- a/file.txt\n",
+            "+++ b/file.txt\n",
+            "@@ -1,1 +1,2 @@\n",
+            "+x\n",
+            " y\n",
+            "\\ No newline at end of file\n",
+        ).as_bytes()));
+    }
+
+    #[test]
+    fn no_patch_without_selected_changes() {
+        let diff = mixed_hunk_diff();
+
+        assert_eq!(build_partial_patch(&diff, false, |_, line_idx| [0, 3].contains(&line_idx)), None);
+        assert_eq!(build_partial_patch(&diff, true, |_, _| false), None);
+    }
+
+    #[test]
+    fn selected_lines_match_by_line_numbers() {
+        let diff = mixed_hunk_diff();
+        let selected = SelectedLines::lines(&diff, |_, line_idx| [2, 4].contains(&line_idx));
+        let hunk_lines = &diff.hunks[0].lines;
+
+        assert!(selected.contains(&hunk_lines[2]));
+        assert!(selected.contains(&hunk_lines[4]));
+        assert!(!selected.contains(&hunk_lines[1]));
+        assert!(!selected.contains(&hunk_lines[5]));
+        assert!(selected.selects_changes_of(&diff));
+    }
+}
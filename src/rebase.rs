@@ -0,0 +1,349 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+
+/// Directory of the git directory where the plan of a rebase in progress is kept, so that it survives
+/// the application being closed, including while no `git2::Rebase` state exists on disk
+const PLAN_DIR: &str = "stainless-rebase";
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RebaseAction {
+    Pick,
+    /// Pick with a new message
+    Reword,
+    /// Pick and stop to let the commit be amended
+    Edit,
+    /// Meld into the previous commit, appending the message
+    Squash,
+    /// Meld into the previous commit, dropping the message
+    Fixup,
+    Drop,
+}
+
+impl RebaseAction {
+    pub const ALL: [RebaseAction; 6] = [
+        RebaseAction::Pick,
+        RebaseAction::Reword,
+        RebaseAction::Edit,
+        RebaseAction::Squash,
+        RebaseAction::Fixup,
+        RebaseAction::Drop,
+    ];
+
+    pub fn title(self) -> &'static str {
+        match self {
+            RebaseAction::Pick => "pick",
+            RebaseAction::Reword => "reword",
+            RebaseAction::Edit => "edit",
+            RebaseAction::Squash => "squash",
+            RebaseAction::Fixup => "fixup",
+            RebaseAction::Drop => "drop",
+        }
+    }
+
+    pub fn from_title(title: &str) -> Option<RebaseAction> {
+        RebaseAction::ALL.iter().cloned().find(|action| action.title() == title)
+    }
+
+    fn melds(self) -> bool {
+        self == RebaseAction::Squash || self == RebaseAction::Fixup
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TodoItem {
+    pub action: RebaseAction,
+    pub commit: git2::Oid,
+    pub summary: String,
+    /// Message of the new commit for `RebaseAction::Reword`; the original message otherwise
+    pub message: String,
+}
+
+/// Interactive rebase in progress: the commits left to apply and where to apply them.
+/// Each commit is applied with a `git2::Rebase` of its own, so that conflicts leave the usual
+/// rebase state in the repository until they are resolved.
+#[derive(Debug, Clone)]
+pub struct RebasePlan {
+    /// Branch being rebased, e.g. `refs/heads/master`; `None` if HEAD was detached
+    branch: Option<String>,
+    orig_head: git2::Oid,
+    /// Commit that the next item is applied on
+    tip: git2::Oid,
+    todo: VecDeque<TodoItem>,
+    /// Item whose step stopped with conflicts
+    current: Option<TodoItem>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum RebaseProgress {
+    /// All items are applied; the branch points to this commit
+    Finished(git2::Oid),
+    /// Applying an item stopped with conflicts in these files
+    Conflicts(Vec<String>),
+    /// Stopped after applying an item marked `edit` as this commit
+    Edit(git2::Oid),
+}
+
+/// Lists the commits of HEAD that are not reachable from `onto`, oldest first, all to be picked.
+/// Merge commits are left out, as they are not rebased.
+pub fn load_todo(repo: &git2::Repository, onto: git2::Oid) -> Result<Vec<TodoItem>, git2::Error> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
+    revwalk.push_head()?;
+    revwalk.hide(onto)?;
+
+    let mut todo = Vec::new();
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        if commit.parent_count() > 1 {
+            continue;
+        }
+        todo.push(TodoItem {
+            action: RebaseAction::Pick,
+            commit: commit.id(),
+            summary: String::from_utf8_lossy(commit.summary_bytes().unwrap_or(&[])).to_string(),
+            message: String::from_utf8_lossy(commit.message_bytes()).to_string(),
+        });
+    }
+
+    Ok(todo)
+}
+
+/// Detaches HEAD at `onto` and applies the items of `todo` one by one
+pub fn start(repo: &git2::Repository, onto: git2::Oid, todo: Vec<TodoItem>) -> Result<(RebasePlan, RebaseProgress), git2::Error> {
+    if repo.state() != git2::RepositoryState::Clean {
+        return Err(git2::Error::from_str("Another operation is in progress; finish or abort it first"));
+    }
+    if let Some(item) = todo.iter().find(|item| item.action != RebaseAction::Drop) {
+        if item.action.melds() {
+            return Err(git2::Error::from_str(&format!("Cannot {} the first commit: there is no previous commit", item.action.title())));
+        }
+    }
+
+    let head = repo.head()?;
+    let branch = if repo.head_detached()? { None } else { head.name().map(|name| name.to_owned()) };
+    let orig_head = head.peel_to_commit()?.id();
+
+    crate::refs::checkout_commit(repo, onto)?;
+    repo.set_head_detached(onto)?;
+
+    let mut plan = RebasePlan {
+        branch,
+        orig_head,
+        tip: onto,
+        todo: todo.into(),
+        current: None,
+    };
+    match run(repo, &mut plan).and_then(|progress| save_progress(repo, &plan, progress)) {
+        Ok(progress) => Ok((plan, progress)),
+        Err(e) => {
+            // The original error is more useful than a failure to roll back
+            let _ = abort(repo, Some(&plan));
+            Err(e)
+        },
+    }
+}
+
+/// Goes on with a rebase stopped with conflicts, once they are resolved, or stopped to edit a commit
+pub fn continue_rebase(repo: &git2::Repository, plan: &mut RebasePlan) -> Result<RebaseProgress, git2::Error> {
+    if plan.current.is_some() {
+        if repo.index()?.has_conflicts() {
+            return Err(git2::Error::from_str("Conflicts have to be resolved and staged first"));
+        }
+        let mut rebase = repo.open_rebase(None)?;
+        if commit_step(repo, plan, &mut rebase)? == RebaseAction::Edit {
+            return save_progress(repo, plan, RebaseProgress::Edit(plan.tip));
+        }
+    } else {
+        // The edited commit may have been amended
+        plan.tip = repo.head()?.peel_to_commit()?.id();
+    }
+
+    let progress = run(repo, plan);
+    // Keeps the saved plan in line with the steps done so far, even if the next one failed
+    if progress.is_err() {
+        let _ = save_plan(repo, plan);
+    }
+    save_progress(repo, plan, progress?)
+}
+
+/// Returns the branch, the index and the working tree to where they were before the rebase
+pub fn abort(repo: &git2::Repository, plan: Option<&RebasePlan>) -> Result<(), git2::Error> {
+    let plan = match plan {
+        Some(plan) => plan,
+        // A rebase started elsewhere, e.g. from the command line, knows where to go back to
+        None => return repo.open_rebase(None)?.abort(),
+    };
+
+    repo.cleanup_state()?;
+    remove_plan(repo)?;
+    match &plan.branch {
+        Some(branch) => repo.set_head(branch)?,
+        None => repo.set_head_detached(plan.orig_head)?,
+    }
+    let orig_head = repo.find_commit(plan.orig_head)?;
+    repo.reset(orig_head.as_object(), git2::ResetType::Hard, None)
+}
+
+fn run(repo: &git2::Repository, plan: &mut RebasePlan) -> Result<RebaseProgress, git2::Error> {
+    while let Some(item) = plan.todo.pop_front() {
+        if item.action == RebaseAction::Drop {
+            continue;
+        }
+
+        let commit = repo.find_commit(item.commit)?;
+        let parent = commit.parent_id(0)
+            .map_err(|_| git2::Error::from_str(&format!("Cannot rebase root commit {}", commit.id())))?;
+
+        let mut rebase = repo.rebase(
+            Some(&repo.find_annotated_commit(commit.id())?),
+            Some(&repo.find_annotated_commit(parent)?),
+            Some(&repo.find_annotated_commit(plan.tip)?),
+            None
+        )?;
+        plan.current = Some(item);
+        if let Some(operation) = rebase.next() {
+            operation?;
+        }
+
+        let paths = crate::conflicts::conflicted_paths(repo)?;
+        if !paths.is_empty() {
+            return Ok(RebaseProgress::Conflicts(paths));
+        }
+
+        if commit_step(repo, plan, &mut rebase)? == RebaseAction::Edit {
+            return Ok(RebaseProgress::Edit(plan.tip));
+        }
+    }
+
+    if let Some(branch) = &plan.branch {
+        repo.reference(branch, plan.tip, true, "rebase: finished")?;
+        repo.set_head(branch)?;
+    }
+
+    Ok(RebaseProgress::Finished(plan.tip))
+}
+
+/// Commits the changes applied for the current item and finishes its `git2::Rebase`; returns the item's action
+fn commit_step(repo: &git2::Repository, plan: &mut RebasePlan, rebase: &mut git2::Rebase) -> Result<RebaseAction, git2::Error> {
+    let item = plan.current.take().ok_or_else(|| git2::Error::from_str("No rebase step is in progress"))?;
+    let committer = repo.signature()?;
+
+    if item.action.melds() {
+        let tip = repo.find_commit(plan.tip)?;
+        let tree = repo.find_tree(repo.index()?.write_tree()?)?;
+        let tip_message = String::from_utf8_lossy(tip.message_bytes()).to_string();
+        let message = match item.action {
+            RebaseAction::Squash => format!("{}\n\n{}", tip_message.trim_end(), item.message),
+            _ => tip_message,
+        };
+        plan.tip = tip.amend(Some("HEAD"), None, Some(&committer), None, Some(&message), Some(&tree))?;
+    } else {
+        let message = if item.action == RebaseAction::Reword { Some(item.message.as_str()) } else { None };
+        match rebase.commit(None, &committer, message) {
+            Ok(oid) => plan.tip = oid,
+            // The changes are already there, so the commit is left out
+            Err(ref e) if e.code() == git2::ErrorCode::Applied => {},
+            Err(e) => return Err(e),
+        }
+    }
+    rebase.finish(None)?;
+
+    Ok(item.action)
+}
+
+/// Plan of the rebase started from the application and left unfinished, if any
+pub fn load_plan(repo: &git2::Repository) -> Result<Option<RebasePlan>, git2::Error> {
+    let dir = plan_dir(repo);
+    if !dir.exists() {
+        return Ok(None);
+    }
+
+    let read = |name: &str| std::fs::read_to_string(dir.join(name))
+        .map_err(|e| git2::Error::from_str(&format!("Error reading {}: {}", dir.join(name).display(), e)));
+    let read_oid = |name: &str| git2::Oid::from_str(read(name)?.trim());
+    let parse_item = |line: &str| -> Result<TodoItem, git2::Error> {
+        let mut fields = line.splitn(3, ' ');
+        let action = fields.next().and_then(RebaseAction::from_title)
+            .ok_or_else(|| git2::Error::from_str(&format!("Invalid rebase step: {}", line)))?;
+        let commit = git2::Oid::from_str(fields.next().unwrap_or(""))?;
+        let message = if action == RebaseAction::Reword {
+            read(&format!("message-{}", commit))?
+        } else {
+            String::from_utf8_lossy(repo.find_commit(commit)?.message_bytes()).to_string()
+        };
+
+        Ok(TodoItem {
+            action,
+            commit,
+            summary: fields.next().unwrap_or("").to_owned(),
+            message,
+        })
+    };
+
+    let branch = if dir.join("head-name").exists() { Some(read("head-name")?.trim().to_owned()) } else { None };
+    let current = if dir.join("current").exists() { Some(parse_item(read("current")?.trim_end())?) } else { None };
+    let todo = read("todo")?.lines()
+        .filter(|line| !line.is_empty())
+        .map(parse_item)
+        .collect::<Result<_, _>>()?;
+
+    Ok(Some(RebasePlan {
+        branch,
+        orig_head: read_oid("orig-head")?,
+        tip: read_oid("tip")?,
+        todo,
+        current,
+    }))
+}
+
+fn plan_dir(repo: &git2::Repository) -> PathBuf {
+    repo.path().join(PLAN_DIR)
+}
+
+/// Saves the plan of a rebase that stopped, or removes it once the rebase is finished
+fn save_progress(repo: &git2::Repository, plan: &RebasePlan, progress: RebaseProgress) -> Result<RebaseProgress, git2::Error> {
+    match progress {
+        RebaseProgress::Finished(_) => remove_plan(repo)?,
+        _ => save_plan(repo, plan)?,
+    }
+
+    Ok(progress)
+}
+
+fn save_plan(repo: &git2::Repository, plan: &RebasePlan) -> Result<(), git2::Error> {
+    let dir = plan_dir(repo);
+    remove_plan(repo)?;
+
+    let write = |name: &str, content: &str| std::fs::write(dir.join(name), content)
+        .map_err(|e| git2::Error::from_str(&format!("Error writing {}: {}", dir.join(name).display(), e)));
+    let write_item = |item: &TodoItem| -> Result<String, git2::Error> {
+        if item.action == RebaseAction::Reword {
+            write(&format!("message-{}", item.commit), &item.message)?;
+        }
+        Ok(format!("{} {} {}\n", item.action.title(), item.commit, item.summary))
+    };
+
+    std::fs::create_dir(&dir)
+        .map_err(|e| git2::Error::from_str(&format!("Error creating {}: {}", dir.display(), e)))?;
+    if let Some(branch) = &plan.branch {
+        write("head-name", branch)?;
+    }
+    write("orig-head", &plan.orig_head.to_string())?;
+    write("tip", &plan.tip.to_string())?;
+    if let Some(item) = &plan.current {
+        let line = write_item(item)?;
+        write("current", &line)?;
+    }
+    let todo = plan.todo.iter().map(write_item).collect::<Result<String, _>>()?;
+    write("todo", &todo)
+}
+
+fn remove_plan(repo: &git2::Repository) -> Result<(), git2::Error> {
+    let dir = plan_dir(repo);
+    if !dir.exists() {
+        return Ok(());
+    }
+
+    std::fs::remove_dir_all(&dir)
+        .map_err(|e| git2::Error::from_str(&format!("Error removing {}: {}", dir.display(), e)))
+}
@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum RefLabelKind {
+    DetachedHead,
+    LocalBranch { is_head: bool },
+    RemoteBranch,
+    AnnotatedTag,
+    LightweightTag,
+    Other,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub struct RefLabel {
+    pub kind: RefLabelKind,
+    pub name: String,
+}
+
+impl RefLabel {
+    fn colors(&self) -> (&'static str, &'static str) {
+        match self.kind {
+            RefLabelKind::DetachedHead => ("#ffffff", "#c0392b"),
+            RefLabelKind::LocalBranch { is_head: true } => ("#ffffff", "#27ae60"),
+            RefLabelKind::LocalBranch { is_head: false } => ("#000000", "#a9dfbf"),
+            RefLabelKind::RemoteBranch => ("#000000", "#aed6f1"),
+            RefLabelKind::AnnotatedTag => ("#000000", "#f7dc6f"),
+            RefLabelKind::LightweightTag => ("#000000", "#fcf3cf"),
+            RefLabelKind::Other => ("#000000", "#d5d8dc"),
+        }
+    }
+
+    pub fn markup(&self) -> String {
+        let (foreground, background) = self.colors();
+        let text = match self.kind {
+            RefLabelKind::LocalBranch { is_head: true } => format!("HEAD \u{2192} {}", self.name),
+            _ => self.name.clone(),
+        };
+        let style = match self.kind {
+            RefLabelKind::DetachedHead | RefLabelKind::LocalBranch { is_head: true } => " weight=\"bold\"",
+            RefLabelKind::LightweightTag => " style=\"italic\"",
+            _ => "",
+        };
+
+        format!(
+            "<span foreground=\"{}\" background=\"{}\"{}> {} </span>",
+            foreground,
+            background,
+            style,
+            glib::markup_escape_text(&text)
+        )
+    }
+}
+
+/// Collects labels of all references (and of HEAD) grouped by the commit they point to.
+/// References that do not point to a commit (e.g. tags of trees) are skipped.
+pub fn collect_ref_labels(repo: &git2::Repository) -> Result<HashMap<git2::Oid, Vec<RefLabel>>, git2::Error> {
+    let mut result = HashMap::<git2::Oid, Vec<RefLabel>>::new();
+
+    let head_ref_name = match repo.head() {
+        Ok(head) => {
+            if repo.head_detached()? {
+                if let Ok(commit) = head.peel_to_commit() {
+                    result.entry(commit.id()).or_default().push(RefLabel {
+                        kind: RefLabelKind::DetachedHead,
+                        name: "HEAD".to_owned(),
+                    });
+                }
+                None
+            } else {
+                head.name().map(|name| name.to_owned())
+            }
+        },
+        Err(_) => None,
+    };
+
+    for reference in repo.references()? {
+        let reference = reference?;
+        let commit = match reference.peel_to_commit() {
+            Ok(commit) => commit,
+            Err(_) => continue,
+        };
+        let name = String::from_utf8_lossy(reference.shorthand_bytes()).to_string();
+
+        let kind = if reference.is_branch() {
+            RefLabelKind::LocalBranch { is_head: reference.name().is_some() && reference.name() == head_ref_name.as_deref() }
+        } else if reference.is_remote() {
+            RefLabelKind::RemoteBranch
+        } else if reference.is_tag() {
+            let is_annotated = reference.target()
+                .map(|target| repo.find_tag(target).is_ok())
+                .unwrap_or(false);
+            if is_annotated {
+                RefLabelKind::AnnotatedTag
+            } else {
+                RefLabelKind::LightweightTag
+            }
+        } else {
+            RefLabelKind::Other
+        };
+
+        result.entry(commit.id()).or_default().push(RefLabel { kind, name });
+    }
+
+    for labels in result.values_mut() {
+        labels.sort();
+    }
+
+    Ok(result)
+}
@@ -0,0 +1,30 @@
+#[derive(Debug, Clone)]
+pub struct ReflogEntry {
+    /// Name like `HEAD@{0}` or `master@{2}`, the first entry being the most recent
+    pub selector: String,
+    /// Zero OID if the reference was created by the entry
+    pub old_id: git2::Oid,
+    pub new_id: git2::Oid,
+    pub message: String,
+    pub time: git2::Time,
+}
+
+/// Entries of the reflog of a reference, e.g. `HEAD` or `refs/heads/master`, most recent first
+pub fn read_reflog(repo: &git2::Repository, ref_name: &str) -> Result<Vec<ReflogEntry>, git2::Error> {
+    let short_name = ref_name.strip_prefix("refs/heads/")
+        .or_else(|| ref_name.strip_prefix("refs/remotes/"))
+        .unwrap_or(ref_name);
+
+    let reflog = repo.reflog(ref_name)?;
+    let entries = reflog.iter().enumerate().map(|(idx, entry)| {
+        ReflogEntry {
+            selector: format!("{}@{{{}}}", short_name, idx),
+            old_id: entry.id_old(),
+            new_id: entry.id_new(),
+            message: String::from_utf8_lossy(entry.message_bytes().unwrap_or(&[])).to_string(),
+            time: entry.committer().when(),
+        }
+    }).collect();
+
+    Ok(entries)
+}
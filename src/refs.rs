@@ -0,0 +1,214 @@
+use std::path::PathBuf;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum RefKind {
+    LocalBranch,
+    RemoteBranch,
+    Tag,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RefEntry {
+    pub kind: RefKind,
+    /// Short name, e.g. `master` or `origin/master`
+    pub name: String,
+    /// Full name, e.g. `refs/heads/master`
+    pub full_name: String,
+    /// Commit the reference points to
+    pub target: git2::Oid,
+    /// Whether HEAD points to this branch
+    pub is_head: bool,
+}
+
+/// Lists local branches, remote-tracking branches and tags, sorted by kind and name.
+/// References that do not point to a commit are skipped.
+pub fn collect_refs(repo: &git2::Repository) -> Result<Vec<RefEntry>, git2::Error> {
+    let head_ref_name = match repo.head() {
+        Ok(head) if !repo.head_detached()? => head.name().map(|name| name.to_owned()),
+        _ => None,
+    };
+
+    let mut result = Vec::new();
+
+    for reference in repo.references()? {
+        let reference = reference?;
+        // Symbolic refs such as `origin/HEAD` only stand for another ref
+        if reference.kind() == Some(git2::ReferenceType::Symbolic) {
+            continue;
+        }
+        let kind = if reference.is_branch() {
+            RefKind::LocalBranch
+        } else if reference.is_remote() {
+            RefKind::RemoteBranch
+        } else if reference.is_tag() {
+            RefKind::Tag
+        } else {
+            continue;
+        };
+        let target = match reference.peel_to_commit() {
+            Ok(commit) => commit.id(),
+            Err(_) => continue,
+        };
+        let full_name = String::from_utf8_lossy(reference.name_bytes()).to_string();
+
+        result.push(RefEntry {
+            kind,
+            name: String::from_utf8_lossy(reference.shorthand_bytes()).to_string(),
+            is_head: Some(&full_name) == head_ref_name.as_ref(),
+            full_name,
+            target,
+        });
+    }
+
+    result.sort_by(|a, b| (a.kind, &a.name).cmp(&(b.kind, &b.name)));
+
+    Ok(result)
+}
+
+pub fn create_branch(repo: &git2::Repository, name: &str, target: git2::Oid) -> Result<(), git2::Error> {
+    repo.branch(name, &repo.find_commit(target)?, false)?;
+
+    Ok(())
+}
+
+pub fn rename_branch(repo: &git2::Repository, name: &str, new_name: &str) -> Result<(), git2::Error> {
+    repo.find_branch(name, git2::BranchType::Local)?.rename(new_name, false)?;
+
+    Ok(())
+}
+
+/// Whether the tip of a local branch is reachable from HEAD or from the branch's upstream
+pub fn is_branch_merged(repo: &git2::Repository, name: &str) -> Result<bool, git2::Error> {
+    let branch = repo.find_branch(name, git2::BranchType::Local)?;
+    let tip = branch.get().peel_to_commit()?.id();
+
+    let mut merge_targets = Vec::new();
+    if let Ok(head) = repo.head() {
+        merge_targets.push(head.peel_to_commit()?.id());
+    }
+    if let Ok(upstream) = branch.upstream() {
+        merge_targets.push(upstream.get().peel_to_commit()?.id());
+    }
+
+    for target in merge_targets {
+        if target == tip || repo.graph_descendant_of(target, tip)? {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+pub fn delete_branch(repo: &git2::Repository, name: &str) -> Result<(), git2::Error> {
+    repo.find_branch(name, git2::BranchType::Local)?.delete()
+}
+
+/// Checks out a reference without overwriting local changes.
+///
+/// Local branches become the current branch. For a remote-tracking branch, a local branch with the same name
+/// tracking it is created (or reused if it points to the same commit). Tags are checked out as a detached HEAD.
+pub fn checkout_ref(repo: &git2::Repository, entry: &RefEntry) -> Result<(), git2::Error> {
+    // Local branch to create for a remote branch, once its commit is checked out
+    let mut tracking_branch = None;
+    let head_name = match entry.kind {
+        RefKind::LocalBranch => Some(entry.full_name.clone()),
+        RefKind::RemoteBranch => {
+            let local_name = entry.name.split_once('/').map(|(_, name)| name).unwrap_or(&entry.name);
+            match repo.find_branch(local_name, git2::BranchType::Local) {
+                Ok(branch) => {
+                    if branch.get().peel_to_commit()?.id() != entry.target {
+                        return Err(git2::Error::from_str(&format!(
+                            "Local branch {} already exists and points to another commit", local_name
+                        )));
+                    }
+                    Some(String::from_utf8_lossy(branch.get().name_bytes()).to_string())
+                },
+                Err(ref e) if e.code() == git2::ErrorCode::NotFound => {
+                    tracking_branch = Some(local_name);
+                    None
+                },
+                Err(e) => return Err(e),
+            }
+        },
+        RefKind::Tag => None,
+    };
+
+    checkout_commit(repo, entry.target)?;
+
+    let head_name = match tracking_branch {
+        Some(local_name) => {
+            let mut branch = repo.branch(local_name, &repo.find_commit(entry.target)?, false)?;
+            branch.set_upstream(Some(&entry.name))?;
+            Some(String::from_utf8_lossy(branch.get().name_bytes()).to_string())
+        },
+        None => head_name,
+    };
+    match head_name {
+        Some(head_name) => repo.set_head(&head_name),
+        None => repo.set_head_detached(entry.target),
+    }
+}
+
+/// Updates the index and the working tree to the tree of a commit, refusing to overwrite local changes.
+/// Does not move HEAD.
+pub fn checkout_commit(repo: &git2::Repository, commit: git2::Oid) -> Result<(), git2::Error> {
+    let commit = repo.find_commit(commit)?;
+
+    let mut conflicts = Vec::<PathBuf>::new();
+    let result = {
+        let mut checkout = git2::build::CheckoutBuilder::new();
+        checkout.safe();
+        checkout.notify_on(git2::CheckoutNotificationType::CONFLICT);
+        checkout.notify(|_, path, _, _, _| {
+            if let Some(path) = path {
+                conflicts.push(path.to_owned());
+            }
+            true
+        });
+        repo.checkout_tree(commit.as_object(), Some(&mut checkout))
+    };
+
+    match result {
+        Err(_) if !conflicts.is_empty() => {
+            let paths: Vec<String> = conflicts.iter().map(|path| path.to_string_lossy().to_string()).collect();
+            Err(git2::Error::from_str(&format!(
+                "Checkout would overwrite local changes in:\n{}\nCommit, stash or discard them first.",
+                paths.join("\n")
+            )))
+        },
+        result => result,
+    }
+}
+
+/// Creates a tag at a commit: an annotated one signed with the default identity if `message` is given,
+/// a lightweight one otherwise
+pub fn create_tag(repo: &git2::Repository, name: &str, target: git2::Oid, message: Option<&str>) -> Result<(), git2::Error> {
+    let target = repo.find_object(target, Some(git2::ObjectType::Commit))?;
+    match message {
+        Some(message) => repo.tag(name, &target, &repo.signature()?, message, false)?,
+        None => repo.tag_lightweight(name, &target, false)?,
+    };
+
+    Ok(())
+}
+
+pub fn delete_tag(repo: &git2::Repository, name: &str) -> Result<(), git2::Error> {
+    repo.tag_delete(name)
+}
+
+/// Points a tag to another commit. An annotated tag is replaced with a new one that has the same message.
+pub fn move_tag(repo: &git2::Repository, name: &str, target: git2::Oid) -> Result<(), git2::Error> {
+    let reference = repo.find_reference(&format!("refs/tags/{}", name))?;
+    let annotated_tag = reference.target().and_then(|oid| repo.find_tag(oid).ok());
+    let target = repo.find_object(target, Some(git2::ObjectType::Commit))?;
+
+    match annotated_tag {
+        Some(tag) => {
+            let message = String::from_utf8_lossy(tag.message_bytes().unwrap_or(&[])).to_string();
+            repo.tag(name, &target, &repo.signature()?, &message, true)?
+        },
+        None => repo.tag_lightweight(name, &target, true)?,
+    };
+
+    Ok(())
+}
@@ -1,332 +1,3341 @@
 use gtk::prelude::*;
 use futures::prelude::*;
 
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::rc::Rc;
+use std::cell::{Cell, RefCell};
 
 use crate::async_ui::gtk_spawn_local;
 use crate::async_ui::promise::Promise;
+use crate::blame::FileBlame;
+use crate::cherry_pick::{PickOperation, PickResult, PickStep};
+use crate::conflicts::{ConflictResolution, ConflictSides};
+use crate::dag_layout::{Node, LayoutRow, layout_nodes};
+use crate::diff_settings::{DiffSettings, WhitespaceMode, DiffAlgorithm};
+use crate::diff_items::{ChangeType, DiffItem, collect_diff_items};
+use crate::file_diff::FileDiff;
+use crate::merge::MergeOutcome;
+use crate::partial_patch::SelectedLines;
+use crate::rebase::{RebasePlan, RebaseProgress, TodoItem};
+use crate::reflog::ReflogEntry;
+use crate::ref_labels::RefLabel;
+use crate::refs::{RefEntry, RefKind};
+use crate::stash::{StashApplied, StashEntry, StashOptions};
+use crate::syntax_highlight::LineHighlights;
+use crate::tree_items::TreeItem;
+use crate::widgets::blame_view::BlameView;
+use crate::widgets::commit_graph_renderer::CommitGraphRenderer;
+use crate::widgets::commit_panel::CommitPanel;
+use crate::widgets::conflicts_view::ConflictsView;
+use crate::widgets::diff_view::{DiffView, DiffPosition};
+use crate::widgets::rebase_editor::RebaseEditor;
+use crate::widgets::reflog_view::ReflogView;
+use crate::widgets::tree_browser::TreeBrowser;
+use crate::widgets::refs_sidebar::{RefsSidebar, RefAction};
+
+#[derive(Debug, Clone)]
+pub struct CommitNode {
+    id: git2::Oid,
+    parents: Vec<git2::Oid>,
+}
+
+impl Node for CommitNode {
+    type NodeId = git2::Oid;
+
+    fn id(&self) -> git2::Oid {
+        self.id
+    }
+
+    fn parents(&self) -> &[git2::Oid] {
+        &self.parents
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RefsFilter {
+    Head,
+    LocalBranches,
+    AllBranches,
+    AllRefs,
+}
+
+impl RefsFilter {
+    const ALL: [RefsFilter; 4] = [RefsFilter::Head, RefsFilter::LocalBranches, RefsFilter::AllBranches, RefsFilter::AllRefs];
+
+    fn id(self) -> &'static str {
+        match self {
+            RefsFilter::Head => "head",
+            RefsFilter::LocalBranches => "local",
+            RefsFilter::AllBranches => "branches",
+            RefsFilter::AllRefs => "all",
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            RefsFilter::Head => "HEAD only",
+            RefsFilter::LocalBranches => "Local branches",
+            RefsFilter::AllBranches => "All branches",
+            RefsFilter::AllRefs => "All refs",
+        }
+    }
+
+    fn from_id(id: &str) -> Option<RefsFilter> {
+        Self::ALL.iter().cloned().find(|filter| filter.id() == id)
+    }
+
+    fn push_tips(self, revwalk: &mut git2::Revwalk) -> Result<(), git2::Error> {
+        match self {
+            RefsFilter::Head => revwalk.push_head(),
+            RefsFilter::LocalBranches => {
+                revwalk.push_head()?;
+                revwalk.push_glob("refs/heads/*")
+            },
+            RefsFilter::AllBranches => {
+                revwalk.push_head()?;
+                revwalk.push_glob("refs/heads/*")?;
+                revwalk.push_glob("refs/remotes/*")
+            },
+            RefsFilter::AllRefs => {
+                revwalk.push_head()?;
+                revwalk.push_glob("refs/*")
+            },
+        }
+    }
+}
+
+/// What the selected commit is compared with when a single commit is selected
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CompareWith {
+    Parents,
+    WorkingTree,
+    Index,
+}
+
+impl CompareWith {
+    const ALL: [CompareWith; 3] = [CompareWith::Parents, CompareWith::WorkingTree, CompareWith::Index];
+
+    fn id(self) -> &'static str {
+        match self {
+            CompareWith::Parents => "parents",
+            CompareWith::WorkingTree => "workdir",
+            CompareWith::Index => "index",
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            CompareWith::Parents => "Parent commits",
+            CompareWith::WorkingTree => "Working tree",
+            CompareWith::Index => "Index",
+        }
+    }
+
+    fn from_id(id: &str) -> Option<CompareWith> {
+        Self::ALL.iter().cloned().find(|compare_with| compare_with.id() == id)
+    }
+}
+
+pub struct MainScreenUi {
+    window: gtk::Window,
+
+    refs_filter_combo_box: gtk::ComboBoxText,
+    dense_combined_diff_check_button: gtk::CheckButton,
+    compare_with_combo_box: gtk::ComboBoxText,
+
+    diff_whitespace_combo_box: gtk::ComboBoxText,
+    diff_context_lines_spin_button: gtk::SpinButton,
+    diff_algorithm_combo_box: gtk::ComboBoxText,
+    diff_binary_as_text_check_button: gtk::CheckButton,
+    diff_rename_threshold_spin_button: gtk::SpinButton,
+
+    commits_list_store: gtk::ListStore,
+    commits_tree_view: gtk::TreeView,
+    file_history_bar: gtk::Box,
+    file_history_label: gtk::Label,
+    commits_graph: Rc<RefCell<Vec<LayoutRow<CommitNode>>>>,
+    /// Nodes of the commits list in the order of the rows; kept to re-layout the graph when a commit is created
+    commit_nodes: RefCell<Vec<CommitNode>>,
+
+    diff_items_list_store: gtk::ListStore,
+    diff_items_tree_view: gtk::TreeView,
+    diff_items_menu: gtk::Menu,
+    file_history_menu_item: gtk::MenuItem,
+    blame_menu_item: gtk::MenuItem,
+    blame_view: Rc<BlameView>,
+    details_notebook: gtk::Notebook,
+    tree_browser: Rc<TreeBrowser>,
+
+    commit_info_view: gtk::TextView,
+
+    diff_view: Rc<DiffView>,
+
+    staging_hbox: gtk::Box,
+    stage_file_button: gtk::Button,
+    stage_hunks_button: gtk::Button,
+    stage_lines_button: gtk::Button,
+    discard_file_button: gtk::Button,
+    discard_hunks_button: gtk::Button,
+
+    commit_panel_toggle_button: gtk::ToggleButton,
+    commit_panel: Rc<CommitPanel>,
+
+    refs_sidebar: Rc<RefsSidebar>,
+    reflog_view: Rc<ReflogView>,
+    commits_menu: gtk::Menu,
+    create_tag_menu_item: gtk::MenuItem,
+    cherry_pick_menu_item: gtk::MenuItem,
+    revert_menu_item: gtk::MenuItem,
+    rebase_menu_item: gtk::MenuItem,
+    stash_menu: gtk::Menu,
+    apply_stash_menu_item: gtk::MenuItem,
+    pop_stash_menu_item: gtk::MenuItem,
+    drop_stash_menu_item: gtk::MenuItem,
+
+    conflicts_view: Rc<ConflictsView>,
+    /// Operation that the conflicts view is shown for
+    stopped_operation: Cell<Option<StoppedOperation>>,
+    /// Commit to select once the commits list is loaded, if it was not listed with the previous refs filter
+    pending_selected_commit: RefCell<Option<git2::Oid>>,
+    /// Pending application of the diff options changed with the spin buttons
+    diff_settings_timeout: RefCell<Option<glib::SourceId>>,
+}
+
+pub struct MainScreenBackend {
+    repo: Mutex<git2::Repository>,
+    requested_comparison: Mutex<Option<Comparison>>,
+    requested_file_diff: Mutex<Option<FileDiffRequest>>,
+    /// Number of the latest request to load the commits list; results of the earlier requests are dropped
+    commits_generation: Mutex<u64>,
+    diff_settings: Mutex<DiffSettings>,
+    /// Commits left to cherry-pick or revert after the one that stopped with conflicts
+    pending_picks: Mutex<Vec<git2::Oid>>,
+    /// Interactive rebase started from the application that stopped with conflicts or to edit a commit
+    rebase_plan: Mutex<Option<RebasePlan>>,
+    /// Commits listed in addition to the refs filter, e.g. commits of the reflog that no reference points to anymore
+    extra_tips: Mutex<Vec<git2::Oid>>,
+    /// File whose history is listed instead of all the commits of the refs filter
+    requested_file_history: Mutex<Option<String>>,
+    /// Names of the file whose history is listed, which the diff items are restricted to; empty if all commits are listed
+    file_history_paths: Mutex<Vec<String>>,
+}
+
+/// Value of the parent column of diff items that belong to the combined diff of a merge commit
+const COMBINED_DIFF_PARENT: &str = "combined";
+/// Value of the parent column of diff items of unstaged changes, which are relative to the index
+const INDEX_DIFF_PARENT: &str = "index";
+
+/// Values of the commit ID column of the pseudo-rows shown above HEAD for uncommitted changes
+const UNSTAGED_CHANGES_ROW: &str = "unstaged";
+const STAGED_CHANGES_ROW: &str = "staged";
+/// Prefix of the commit ID column of the pseudo-rows shown below the uncommitted changes for stash entries,
+/// followed by the stash commit ID
+const STASH_ROW_PREFIX: &str = "stash:";
+
+/// Page of the details notebook with the tree browser, next to the page of the diff items
+const TREE_PAGE: u32 = 1;
+/// Files larger than this are only partly shown in the tree browser preview
+const MAX_PREVIEW_SIZE: usize = 1024 * 1024;
+/// Delay before diff options changed with a spin button are applied, so that stepping through values
+/// does not reload the diff at each step
+const DIFF_SETTINGS_DELAY_MS: u32 = 400;
+
+/// Whether a row of the commits list stands for uncommitted changes or a stash entry rather than a commit
+fn is_pseudo_row(id: &str) -> bool {
+    id == UNSTAGED_CHANGES_ROW || id == STAGED_CHANGES_ROW || id.starts_with(STASH_ROW_PREFIX)
+}
+
+/// New side of a diff
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DiffTarget {
+    Commit(git2::Oid),
+    Index,
+    WorkingTree,
+}
+
+/// Selection of the commits list that the diff items are listed for
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Comparison {
+    /// Commit against each of its parents
+    Parents(git2::Oid),
+    /// Changes from commit `old` to `new`
+    Range { old: git2::Oid, new: DiffTarget },
+    /// Working tree against the index
+    Unstaged,
+    /// Index against HEAD
+    Staged,
+    /// Stashed working tree against the commit the stash entry was created on
+    Stash(git2::Oid),
+}
+
+impl Comparison {
+    fn target(self) -> DiffTarget {
+        match self {
+            Comparison::Parents(commit) => DiffTarget::Commit(commit),
+            Comparison::Range { new, .. } => new,
+            Comparison::Unstaged => DiffTarget::WorkingTree,
+            Comparison::Staged => DiffTarget::Index,
+            Comparison::Stash(stash) => DiffTarget::Commit(stash),
+        }
+    }
+}
+
+/// Values of the columns of a row of the commits list
+#[derive(Debug, Clone)]
+struct CommitRow {
+    id: String,
+    summary: String,
+    summary_markup: String,
+    date: String,
+    author: String,
+}
+
+impl CommitRow {
+    fn new(commit: &git2::Commit, labels: &[RefLabel]) -> CommitRow {
+        let summary = String::from_utf8_lossy(commit.summary_bytes().unwrap_or(&[])).to_string();
+        let summary_markup = summary_markup(&summary, labels);
+        use chrono::TimeZone;
+        let timestamp =
+            chrono::Utc.timestamp(commit.author().when().seconds(), 0)
+            .with_timezone(&chrono::FixedOffset::east(commit.author().when().offset_minutes() * 60));
+        let author = String::from_utf8_lossy(commit.author().name_bytes()).to_string();
+        let email = String::from_utf8_lossy(commit.author().email_bytes()).to_string();
+
+        CommitRow {
+            id: commit.id().to_string(),
+            summary,
+            summary_markup,
+            date: timestamp.format("%Y-%m-%d %H:%M:%S %:z").to_string(),
+            author: format!("{} <{}>", author, email),
+        }
+    }
+
+    fn stash(entry: &StashEntry, commit: &git2::Commit) -> CommitRow {
+        let summary = format!("{}: {}", entry.name(), entry.message);
+
+        CommitRow {
+            id: format!("{}{}", STASH_ROW_PREFIX, entry.id),
+            summary_markup: format!("<span foreground=\"#8e44ad\"><i>{}</i></span>", glib::markup_escape_text(&summary)),
+            summary,
+            ..CommitRow::new(commit, &[])
+        }
+    }
+}
+
+/// Commit summary prefixed with ref labels
+fn summary_markup(summary: &str, labels: &[RefLabel]) -> String {
+    let mut markup = String::new();
+    for label in labels {
+        markup.push_str(&label.markup());
+        markup.push(' ');
+    }
+    markup.push_str(&glib::markup_escape_text(summary));
+
+    markup
+}
+
+/// Tag to create, as entered in the tag dialog
+#[derive(Debug, Clone)]
+struct TagRequest {
+    name: String,
+    /// Message of an annotated tag; `None` for a lightweight tag
+    message: Option<String>,
+}
+
+/// Changes to stash, as entered in the stash dialog
+#[derive(Debug, Clone)]
+struct StashRequest {
+    message: Option<String>,
+    options: StashOptions,
+}
+
+#[derive(Debug, Clone)]
+pub struct CommitRequest {
+    message: String,
+    /// Author in the `Name <email>` form; empty for the default identity
+    author: String,
+    amend: bool,
+    sign_off: bool,
+}
+
+/// Commit created by `MainScreenBackend::create_commit` along with the changes of the commits list it causes
+#[derive(Debug, Clone)]
+struct CreatedCommit {
+    node: CommitNode,
+    row: CommitRow,
+    /// Commit that HEAD pointed to before; zero OID if HEAD was unborn
+    previous_head: git2::Oid,
+    /// Updated row of the previous HEAD commit; `None` if HEAD was unborn
+    previous_head_row: Option<CommitRow>,
+    /// Previous HEAD was amended and no ref points to it any longer
+    previous_head_unreferenced: bool,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum StagingScope {
+    File,
+    Hunks,
+    Lines,
+}
+
+/// Change of the index or the working tree made from the uncommitted changes views
+#[derive(Debug, Clone)]
+pub enum StagingOperation {
+    /// Stages or unstages all changes of a file; `old_path` is the path before rename, empty if not renamed
+    File { path: String, old_path: String, unstage: bool },
+    /// Applies the selected changed lines of a file to the index; `unstage` reverts them
+    Lines { path: String, lines: SelectedLines, unstage: bool },
+    /// Replaces a working tree file with its version from the index, removing it if it is untracked
+    DiscardFile { path: String, old_path: String },
+    /// Reverts the selected unstaged changed lines of a file in the working tree
+    DiscardLines { path: String, lines: SelectedLines },
+}
+
+/// Operation that stopped with conflicts and waits to be continued or aborted
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum StoppedOperation {
+    Merge,
+    Pick(PickOperation),
+    Rebase,
+}
+
+impl StoppedOperation {
+    /// `rebasing` tells whether a rebase started from the application is stopped, possibly with no conflicts
+    fn in_progress(repo: &git2::Repository, rebasing: bool) -> Option<StoppedOperation> {
+        if rebasing {
+            return Some(StoppedOperation::Rebase);
+        }
+        match repo.state() {
+            git2::RepositoryState::Merge => Some(StoppedOperation::Merge),
+            git2::RepositoryState::Rebase | git2::RepositoryState::RebaseInteractive | git2::RepositoryState::RebaseMerge => {
+                Some(StoppedOperation::Rebase)
+            },
+            _ => PickOperation::in_progress(repo).map(StoppedOperation::Pick),
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            StoppedOperation::Merge => "Merge",
+            StoppedOperation::Pick(operation) => operation.title(),
+            StoppedOperation::Rebase => "Rebase",
+        }
+    }
+}
+
+/// Outcome of cherry-picking or reverting commits
+#[derive(Debug, Clone)]
+pub struct PickReport {
+    operation: PickOperation,
+    steps: Vec<PickStep>,
+    /// Number of commits left to apply after the one that stopped with conflicts
+    remaining: usize,
+}
+
+/// Description of the compared commits and the changed files, each with the value of the parent column of diff items
+type ComparisonChanges = (String, Vec<(String, DiffItem)>);
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DiffBase {
+    /// Zero OID for the empty tree, e.g. for root commits
+    Commit(git2::Oid),
+    Index,
+    /// All parents of a merge commit
+    Combined { dense: bool },
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct FileDiffRequest {
+    target: DiffTarget,
+    base: DiffBase,
+    path: String,
+    /// Path before rename or copy; empty if not renamed
+    old_path: String,
+}
+
+impl MainScreenBackend {
+    fn load_file_diff(&self, request: &FileDiffRequest) -> Result<Option<FileDiff>, git2::Error> {
+        let repo = self.repo.lock().unwrap();
+        let diff_settings = *self.diff_settings.lock().unwrap();
+
+        if let (DiffBase::Combined { dense }, DiffTarget::Commit(commit)) = (request.base, request.target) {
+            let commit = repo.find_commit(commit)?;
+            let mut file_diff = crate::combined_diff::combined_file_diff(&repo, &commit, &request.path, dense, &diff_settings)?;
+            file_diff.highlight(&repo);
+            return Ok(Some(file_diff));
+        }
+
+        let mut diff_options = diff_settings.diff_options();
+        diff_options.disable_pathspec_match(true);
+        diff_options.pathspec(&request.path);
+        if !request.old_path.is_empty() {
+            // Copy sources are unmodified, so they have to be included to be detected
+            diff_options.pathspec(&request.old_path);
+            diff_options.include_unmodified(true);
+        }
+
+        let mut diff = match request.base {
+            DiffBase::Commit(base) => diff_to_target(&repo, commit_tree(&repo, base)?.as_ref(), request.target, &mut diff_options)?,
+            DiffBase::Index => diff_index_to_workdir(&repo, &mut diff_options)?,
+            DiffBase::Combined { .. } => return Ok(None),
+        };
+
+        if !request.old_path.is_empty() {
+            let mut find_options = diff_settings.find_options();
+            find_options.copies_from_unmodified(true);
+            diff.find_similar(Some(&mut find_options))?;
+        }
+
+        let mut file_diff = FileDiff::find_in_diff(&diff, &request.path)?;
+        if let Some(file_diff) = file_diff.as_mut() {
+            file_diff.highlight(&repo);
+        }
+
+        Ok(file_diff)
+    }
+
+    /// Describes the compared commits and lists the changed files, grouped by the base they are compared with
+    fn load_comparison(&self, comparison: Comparison) -> Result<Option<ComparisonChanges>, String> {
+        if Some(comparison) != *self.requested_comparison.lock().unwrap() {
+            return Ok(None);
+        }
+
+        let (summary, mut changes) = match comparison {
+            Comparison::Parents(oid) => self.load_commit_changes(oid),
+            Comparison::Range { old, new } => self.load_range_changes(old, new),
+            Comparison::Unstaged | Comparison::Staged => self.load_uncommitted_changes(comparison == Comparison::Staged),
+            Comparison::Stash(stash) => self.load_stash_changes(stash),
+        }?;
+
+        let file_history_paths = self.file_history_paths.lock().unwrap();
+        if !file_history_paths.is_empty() {
+            // The history may be the one of a directory
+            let is_in_history = |path: &str| file_history_paths.iter()
+                .any(|history_path| path == history_path || path.starts_with(&format!("{}/", history_path)));
+            changes.retain(|(_, item)| is_in_history(&item.path) || (!item.old_path.is_empty() && is_in_history(&item.old_path)));
+        }
+
+        Ok(Some((summary, changes)))
+    }
+
+    /// Files that go into the commit being created: the staged changes, or for amending, the changes of HEAD and the staged ones
+    fn load_commit_files(&self, amend: bool) -> Result<Vec<DiffItem>, git2::Error> {
+        let repo = self.repo.lock().unwrap();
+        let diff_settings = *self.diff_settings.lock().unwrap();
+
+        let head = head_commit_id(&repo)?;
+        let base = if amend && !head.is_zero() {
+            repo.find_commit(head)?.parent_ids().next().unwrap_or_else(git2::Oid::zero)
+        } else {
+            head
+        };
+
+        let base_tree = commit_tree(&repo, base)?;
+        let mut diff = repo.diff_tree_to_index(base_tree.as_ref(), None, Some(&mut diff_settings.diff_options()))?;
+
+        collect_diff_items(&mut diff, &mut diff_settings.find_options())
+    }
+
+    fn load_head_message(&self) -> Result<Option<String>, git2::Error> {
+        let repo = self.repo.lock().unwrap();
+
+        let head = head_commit_id(&repo)?;
+        if head.is_zero() {
+            return Ok(None);
+        }
+        let message = String::from_utf8_lossy(repo.find_commit(head)?.message_bytes()).to_string();
+
+        Ok(Some(message))
+    }
+
+    /// Creates a commit of the index on top of HEAD, or replaces HEAD with it when amending
+    fn create_commit(&self, request: &CommitRequest) -> Result<CreatedCommit, git2::Error> {
+        let repo = self.repo.lock().unwrap();
+
+        let committer = repo.signature()?;
+        let author = if request.author.is_empty() {
+            None
+        } else {
+            let (name, email) = crate::commit_message::parse_identity(&request.author)
+                .ok_or_else(|| git2::Error::from_str("Author must be given as Name <email>"))?;
+            Some(git2::Signature::now(&name, &email)?)
+        };
+        let message = if request.sign_off {
+            let identity = format!(
+                "{} <{}>",
+                String::from_utf8_lossy(committer.name_bytes()),
+                String::from_utf8_lossy(committer.email_bytes())
+            );
+            crate::commit_message::add_sign_off(&request.message, &identity)
+        } else {
+            request.message.clone()
+        };
+
+        let mut index = repo.index()?;
+        if index.has_conflicts() {
+            return Err(git2::Error::from_str("Conflicts have to be resolved before committing"));
+        }
+        let tree = repo.find_tree(index.write_tree()?)?;
+
+        let previous_head = head_commit_id(&repo)?;
+        let head_commit = if previous_head.is_zero() { None } else { Some(repo.find_commit(previous_head)?) };
+
+        let commit_id = if request.amend {
+            let head_commit = head_commit.as_ref().ok_or_else(|| git2::Error::from_str("There is no commit to amend"))?;
+            head_commit.amend(Some("HEAD"), author.as_ref(), Some(&committer), None, Some(&message), Some(&tree))?
+        } else {
+            if head_commit.as_ref().map(|commit| commit.tree_id()) == Some(tree.id()) {
+                return Err(git2::Error::from_str("No changes are staged"));
+            }
+            // Committing while a merge is stopped with conflicts concludes it
+            let merged = if repo.state() == git2::RepositoryState::Merge {
+                crate::merge::merge_heads(&repo)?.into_iter()
+                    .map(|oid| repo.find_commit(oid))
+                    .collect::<Result<Vec<_>, _>>()?
+            } else {
+                Vec::new()
+            };
+            let parents: Vec<&git2::Commit> = head_commit.iter().chain(merged.iter()).collect();
+            let commit_id = repo.commit(Some("HEAD"), author.as_ref().unwrap_or(&committer), &committer, &message, &tree, &parents)?;
+            if !merged.is_empty() {
+                repo.cleanup_state()?;
+            }
+            commit_id
+        };
+
+        let commit = repo.find_commit(commit_id)?;
+        let mut ref_labels = crate::ref_labels::collect_ref_labels(&repo)?;
+
+        let previous_head_labels = ref_labels.remove(&previous_head).unwrap_or_default();
+        let previous_head_row = head_commit.as_ref().map(|head_commit| CommitRow::new(head_commit, &previous_head_labels));
+        let previous_head_unreferenced = request.amend && previous_head_labels.is_empty();
+
+        Ok(CreatedCommit {
+            node: CommitNode {
+                id: commit.id(),
+                parents: commit.parent_ids().collect(),
+            },
+            row: CommitRow::new(&commit, &ref_labels.remove(&commit.id()).unwrap_or_default()),
+            previous_head,
+            previous_head_row,
+            previous_head_unreferenced,
+        })
+    }
+
+    /// `commits` are in the order of the commits list, from top to bottom
+    fn pick_commits(&self, operation: PickOperation, commits: &[git2::Oid]) -> Result<PickReport, git2::Error> {
+        let repo = self.repo.lock().unwrap();
+
+        ensure_no_uncommitted_changes(&repo)?;
+
+        let commits = crate::cherry_pick::pick_order(operation, commits);
+        let steps = crate::cherry_pick::pick_commits(&repo, operation, &commits)?;
+        self.stop_picks(operation, steps, commits)
+    }
+
+    fn continue_pick(&self) -> Result<PickReport, git2::Error> {
+        let repo = self.repo.lock().unwrap();
+
+        let operation = PickOperation::in_progress(&repo)
+            .ok_or_else(|| git2::Error::from_str("No cherry-pick or revert is in progress"))?;
+        let continued = crate::cherry_pick::continue_pick(&repo)?;
+
+        let pending = self.pending_picks.lock().unwrap().clone();
+        let mut steps = crate::cherry_pick::pick_commits(&repo, operation, &pending)?;
+        let mut commits = pending;
+        commits.insert(0, continued.commit);
+        steps.insert(0, continued);
+
+        self.stop_picks(operation, steps, commits)
+    }
+
+    /// Remembers the commits that are not applied yet because applying `commits` stopped with conflicts
+    fn stop_picks(&self, operation: PickOperation, steps: Vec<PickStep>, commits: Vec<git2::Oid>) -> Result<PickReport, git2::Error> {
+        let remaining = commits[steps.len()..].to_vec();
+        let report = PickReport { operation, steps, remaining: remaining.len() };
+        *self.pending_picks.lock().unwrap() = remaining;
+
+        Ok(report)
+    }
+
+    fn merge(&self, ref_name: &str) -> Result<MergeOutcome, git2::Error> {
+        let repo = self.repo.lock().unwrap();
+
+        ensure_no_uncommitted_changes(&repo)?;
+
+        crate::merge::merge_ref(&repo, ref_name)
+    }
+
+    fn conclude_merge(&self) -> Result<MergeOutcome, git2::Error> {
+        crate::merge::conclude_merge(&self.repo.lock().unwrap()).map(MergeOutcome::Merged)
+    }
+
+    fn load_rebase_todo(&self, onto: git2::Oid) -> Result<Vec<TodoItem>, git2::Error> {
+        crate::rebase::load_todo(&self.repo.lock().unwrap(), onto)
+    }
+
+    fn start_rebase(&self, onto: git2::Oid, todo: Vec<TodoItem>) -> Result<RebaseProgress, git2::Error> {
+        let repo = self.repo.lock().unwrap();
+
+        ensure_no_uncommitted_changes(&repo)?;
+
+        let (plan, progress) = crate::rebase::start(&repo, onto, todo)?;
+        if let RebaseProgress::Finished(_) = progress {
+            return Ok(progress);
+        }
+        *self.rebase_plan.lock().unwrap() = Some(plan);
+
+        Ok(progress)
+    }
+
+    fn continue_rebase(&self) -> Result<RebaseProgress, git2::Error> {
+        let repo = self.repo.lock().unwrap();
+        let mut rebase_plan = self.rebase_plan.lock().unwrap();
+
+        let plan = rebase_plan.as_mut()
+            .ok_or_else(|| git2::Error::from_str("The rebase was not started from this window; it can only be aborted"))?;
+        let progress = crate::rebase::continue_rebase(&repo, plan)?;
+        if let RebaseProgress::Finished(_) = progress {
+            *rebase_plan = None;
+        }
+
+        Ok(progress)
+    }
+
+    fn abort_operation(&self) -> Result<(), git2::Error> {
+        let repo = self.repo.lock().unwrap();
+        let mut rebase_plan = self.rebase_plan.lock().unwrap();
+
+        self.pending_picks.lock().unwrap().clear();
+        if let Some(StoppedOperation::Rebase) = StoppedOperation::in_progress(&repo, rebase_plan.is_some()) {
+            return crate::rebase::abort(&repo, rebase_plan.take().as_ref());
+        }
+        crate::conflicts::abort(&repo)
+    }
+
+    /// Operation in progress along with the files that still have conflicts
+    fn stopped_operation_conflicts(&self, repo: &git2::Repository) -> Result<Option<(StoppedOperation, Vec<String>)>, git2::Error> {
+        let rebasing = self.rebase_plan.lock().unwrap().is_some();
+        match StoppedOperation::in_progress(repo, rebasing) {
+            Some(operation) => Ok(Some((operation, crate::conflicts::conflicted_paths(repo)?))),
+            None => Ok(None),
+        }
+    }
+
+    fn apply_staging(&self, operation: &StagingOperation) -> Result<(), git2::Error> {
+        let repo = self.repo.lock().unwrap();
+
+        match operation {
+            StagingOperation::File { path, old_path, unstage: false } => {
+                let workdir = repo.workdir().ok_or_else(|| git2::Error::from_str("Repository has no working tree"))?.to_owned();
+                let mut index = repo.index()?;
+                for path in [path, old_path].iter().filter(|path| !path.is_empty()) {
+                    if workdir.join(path).symlink_metadata().is_ok() {
+                        index.add_path(std::path::Path::new(path))?;
+                    } else {
+                        index.remove_path(std::path::Path::new(path))?;
+                    }
+                }
+                index.write()
+            },
+            StagingOperation::File { path, old_path, unstage: true } => {
+                let paths = [path, old_path].iter().filter(|path| !path.is_empty()).cloned().collect::<Vec<_>>();
+                let head = head_commit_id(&repo)?;
+                if head.is_zero() {
+                    // Nothing is committed yet, so unstaging removes the files from the index
+                    let mut index = repo.index()?;
+                    for path in paths {
+                        index.remove_path(std::path::Path::new(path))?;
+                    }
+                    index.write()
+                } else {
+                    repo.reset_default(Some(repo.find_commit(head)?.as_object()), paths)
+                }
+            },
+            StagingOperation::Lines { path, lines, unstage } => {
+                // Unstaging reverts the lines in the staged changes
+                let diff = git2::Diff::from_buffer(&selected_lines_patch(&repo, path, lines, *unstage, *unstage)?)?;
+                repo.apply(&diff, git2::ApplyLocation::Index, None)
+            },
+            StagingOperation::DiscardFile { path, old_path } => {
+                let mut checkout = git2::build::CheckoutBuilder::new();
+                checkout.force();
+                checkout.remove_untracked(true);
+                for path in [path, old_path].iter().filter(|path| !path.is_empty()) {
+                    checkout.path(path.as_str());
+                }
+                repo.checkout_index(None, Some(&mut checkout))
+            },
+            StagingOperation::DiscardLines { path, lines } => {
+                let diff = git2::Diff::from_buffer(&selected_lines_patch(&repo, path, lines, false, true)?)?;
+                repo.apply(&diff, git2::ApplyLocation::WorkDir, None)
+            },
+        }
+    }
+
+    fn load_uncommitted_changes(&self, staged: bool) -> Result<ComparisonChanges, String> {
+        let repo = self.repo.lock().unwrap();
+        let diff_settings = *self.diff_settings.lock().unwrap();
+
+        let (summary, parent, mut diff) = if staged {
+            let head = head_commit_id(&repo).map_err(|e| format!("{}", e))?;
+            let head_tree = commit_tree(&repo, head).map_err(|e| format!("{}", e))?;
+            let diff = repo.diff_tree_to_index(head_tree.as_ref(), None, Some(&mut diff_settings.diff_options()))
+                .map_err(|e| format!("{}", e))?;
+
+            ("Staged changes\n\nChanges added to the index that will be included in the next commit\n", head.to_string(), diff)
+        } else {
+            let diff = diff_index_to_workdir(&repo, &mut diff_settings.diff_options())
+                .map_err(|e| format!("{}", e))?;
+
+            ("Unstaged changes\n\nChanges in the working tree that are not added to the index\n", INDEX_DIFF_PARENT.to_string(), diff)
+        };
+
+        let changes = collect_diff_items(&mut diff, &mut diff_settings.find_options())
+            .map_err(|e| format!("{}", e))?
+            .into_iter()
+            .map(|item| (parent.clone(), item))
+            .collect();
+
+        Ok((summary.to_string(), changes))
+    }
+
+    fn load_commit_changes(&self, oid: git2::Oid) -> Result<ComparisonChanges, String> {
+        let repo = self.repo.lock().unwrap();
+        let diff_settings = *self.diff_settings.lock().unwrap();
+        let commit = repo.find_commit(oid).map_err(|e| format!("{}", e))?;
+
+        let message = String::from_utf8_lossy(
+            commit.message_bytes()
+        ).to_string();
+
+        let parents_count = commit.parent_count();
+
+        let mut commit_summary = String::new();
+
+        use std::fmt::Write;
+        use chrono::TimeZone;
+
+        write!(&mut commit_summary, "Commit {}\n", commit.id()).unwrap();
+        for parent in commit.parents() {
+            write!(&mut commit_summary, "Parent {}\n", parent.id()).unwrap();
+        }
+        let author_timestamp =
+            chrono::Utc.timestamp(commit.author().when().seconds(), 0)
+            .with_timezone(&chrono::FixedOffset::east(commit.author().when().offset_minutes() * 60));
+        let author_name = String::from_utf8_lossy(commit.author().name_bytes()).to_string();
+        let author_email = String::from_utf8_lossy(commit.author().email_bytes()).to_string();
+        let committer_timestamp =
+            chrono::Utc.timestamp(commit.committer().when().seconds(), 0)
+            .with_timezone(&chrono::FixedOffset::east(commit.committer().when().offset_minutes() * 60));
+        let committer_name = String::from_utf8_lossy(commit.committer().name_bytes()).to_string();
+        let committer_email = String::from_utf8_lossy(commit.committer().email_bytes()).to_string();
+
+        write!(
+            &mut commit_summary,
+            "Timestamp {}\nAuthor {} <{}>\n",
+            author_timestamp.format("%Y-%m-%d %H:%M:%S %:z"),
+            author_name,
+            author_email
+        ).unwrap();
+
+        if author_timestamp != committer_timestamp || author_name != committer_name || author_email != committer_email {
+            write!(
+                &mut commit_summary,
+                "Commit timestamp {}\nCommitter {} <{}>\n",
+                committer_timestamp.format("%Y-%m-%d %H:%M:%S %:z"),
+                committer_name,
+                committer_email
+            ).unwrap();
+        }
+
+        write!(&mut commit_summary, "\n{}", message).unwrap();
+
+        let mut changes = Vec::new();
+
+        if parents_count > 1 {
+            changes.push((
+                "".to_string(),
+                DiffItem {
+                    path: "Combined changes (files changed from all parents)".to_string(),
+                    change_type: ChangeType::None,
+                    old_path: "".to_string(),
+                },
+            ));
+
+            let paths = crate::combined_diff::combined_paths(&repo, &commit, &diff_settings).map_err(|e| format!("{}", e))?;
+            for path in paths {
+                changes.push((
+                    COMBINED_DIFF_PARENT.to_string(),
+                    DiffItem {
+                        path,
+                        change_type: ChangeType::Modified,
+                        old_path: "".to_string(),
+                    },
+                ));
+            }
+        }
+
+        if parents_count > 0 {
+            for parent in commit.parents() {
+
+                if parents_count > 1 {
+                    changes.push((
+                        "".to_string(),
+                        DiffItem {
+                            path: format!("Changes from {}", parent.id()),
+                            change_type: ChangeType::None,
+                            old_path: "".to_string(),
+                        },
+                    ));
+                }
+
+                let mut diff = repo.diff_tree_to_tree(
+                    Some(&parent.tree().map_err(|e| format!("{}", e))?),
+                    Some(&commit.tree().map_err(|e| format!("{}", e))?),
+                    Some(&mut diff_settings.diff_options())
+                ).map_err(|e| format!("{}", e))?;
+
+                for item in collect_diff_items(&mut diff, &mut diff_settings.find_options()).map_err(|e| format!("{}", e))? {
+                    changes.push((
+                        parent.id().to_string(),
+                        item
+                    ));
+                }
+            }
+        } else {
+            let mut diff = repo.diff_tree_to_tree(
+                None,
+                Some(&commit.tree().map_err(|e| format!("{}", e))?),
+                Some(&mut diff_settings.diff_options())
+            ).map_err(|e| format!("{}", e))?;
+
+            for item in collect_diff_items(&mut diff, &mut diff_settings.find_options()).map_err(|e| format!("{}", e))? {
+                changes.push((
+                    git2::Oid::zero().to_string(),
+                    item
+                ));
+            }
+        }
+
+        Ok((commit_summary, changes))
+    }
+
+    fn load_range_changes(&self, old: git2::Oid, new: DiffTarget) -> Result<ComparisonChanges, String> {
+        let repo = self.repo.lock().unwrap();
+        let diff_settings = *self.diff_settings.lock().unwrap();
+
+        let describe_commit = |oid: git2::Oid| -> Result<String, String> {
+            let commit = repo.find_commit(oid).map_err(|e| format!("{}", e))?;
+            Ok(format!("commit {}\n    {}", oid, String::from_utf8_lossy(commit.summary_bytes().unwrap_or(&[]))))
+        };
+
+        let summary = format!(
+            "Changes from {}\nto {}\n",
+            describe_commit(old)?,
+            match new {
+                DiffTarget::Commit(new) => describe_commit(new)?,
+                DiffTarget::Index => "the index".to_string(),
+                DiffTarget::WorkingTree => "the working tree".to_string(),
+            }
+        );
+
+        let old_tree = commit_tree(&repo, old).map_err(|e| format!("{}", e))?;
+        let mut diff = diff_to_target(&repo, old_tree.as_ref(), new, &mut diff_settings.diff_options())
+            .map_err(|e| format!("{}", e))?;
+
+        let changes = collect_diff_items(&mut diff, &mut diff_settings.find_options())
+            .map_err(|e| format!("{}", e))?
+            .into_iter()
+            .map(|item| (old.to_string(), item))
+            .collect();
+
+        Ok((summary, changes))
+    }
+
+    /// Changes of the stashed working tree; untracked files stashed with it are only listed in the summary
+    fn load_stash_changes(&self, stash: git2::Oid) -> Result<ComparisonChanges, String> {
+        let repo = self.repo.lock().unwrap();
+        let diff_settings = *self.diff_settings.lock().unwrap();
+
+        let commit = repo.find_commit(stash).map_err(|e| format!("{}", e))?;
+        let base = commit.parent(0).map_err(|e| format!("{}", e))?;
+
+        let mut summary = format!(
+            "Stash {}\nOn commit {}\n    {}\n\n{}\n",
+            stash,
+            base.id(),
+            String::from_utf8_lossy(base.summary_bytes().unwrap_or(&[])),
+            String::from_utf8_lossy(commit.message_bytes()).trim_end()
+        );
+        // The third parent holds the untracked files, if they were stashed
+        if let Ok(untracked) = commit.parent(2) {
+            let tree = untracked.tree().map_err(|e| format!("{}", e))?;
+            summary.push_str("\nUntracked files:\n");
+            tree.walk(git2::TreeWalkMode::PreOrder, |root, entry| {
+                if entry.kind() == Some(git2::ObjectType::Blob) {
+                    summary.push_str(&format!("    {}{}\n", root, String::from_utf8_lossy(entry.name_bytes())));
+                }
+                git2::TreeWalkResult::Ok
+            }).map_err(|e| format!("{}", e))?;
+        }
+
+        let base_tree = base.tree().map_err(|e| format!("{}", e))?;
+        let mut diff = diff_to_target(&repo, Some(&base_tree), DiffTarget::Commit(stash), &mut diff_settings.diff_options())
+            .map_err(|e| format!("{}", e))?;
+
+        let changes = collect_diff_items(&mut diff, &mut diff_settings.find_options())
+            .map_err(|e| format!("{}", e))?
+            .into_iter()
+            .map(|item| (base.id().to_string(), item))
+            .collect();
+
+        Ok((summary, changes))
+    }
+
+    fn save_stash(&self, request: &StashRequest) -> Result<(), git2::Error> {
+        crate::stash::save(&mut self.repo.lock().unwrap(), request.message.as_deref(), request.options).map(|_| ())
+    }
+
+    fn apply_stash(&self, stash: git2::Oid, pop: bool) -> Result<StashApplied, git2::Error> {
+        let mut repo = self.repo.lock().unwrap();
+
+        if StoppedOperation::in_progress(&repo, self.rebase_plan.lock().unwrap().is_some()).is_some() {
+            return Err(git2::Error::from_str("Another operation is in progress; finish or abort it first"));
+        }
+
+        crate::stash::apply(&mut repo, stash, pop)
+    }
+
+    fn drop_stash(&self, stash: git2::Oid) -> Result<(), git2::Error> {
+        crate::stash::drop(&mut self.repo.lock().unwrap(), stash)
+    }
+
+    /// Blames a file at a commit, or at HEAD if `commit` is `None`
+    fn blame_file(&self, commit: Option<git2::Oid>, path: &str) -> Result<FileBlame, git2::Error> {
+        let repo = self.repo.lock().unwrap();
+
+        let commit = match commit {
+            Some(commit) => commit,
+            None => repo.head()?.peel_to_commit()?.id(),
+        };
+        crate::blame::blame_file(&repo, commit, path)
+    }
+
+    fn blame_parent(&self, commit: git2::Oid, path: &str) -> Result<FileBlame, git2::Error> {
+        crate::blame::blame_parent(&self.repo.lock().unwrap(), commit, path)
+    }
+
+    fn list_tree(&self, tree_id: git2::Oid, path: &str) -> Result<Vec<TreeItem>, git2::Error> {
+        crate::tree_items::list_tree(&self.repo.lock().unwrap(), tree_id, path)
+    }
+
+    fn list_commit_tree(&self, commit: git2::Oid) -> Result<Vec<TreeItem>, git2::Error> {
+        crate::tree_items::list_commit_tree(&self.repo.lock().unwrap(), commit)
+    }
+
+    /// Text of a file at `path` for the tree browser preview, with the syntax highlighting of its lines
+    fn load_preview(&self, blob: git2::Oid, path: &str) -> Result<(String, Vec<LineHighlights>), git2::Error> {
+        let content = crate::tree_items::load_blob(&self.repo.lock().unwrap(), blob)?;
+        if content.contains(&0) {
+            return Ok((format!("(binary file, {} bytes)", content.len()), Vec::new()));
+        }
+
+        let text = String::from_utf8_lossy(&content[..content.len().min(MAX_PREVIEW_SIZE)]).to_string();
+        let highlights = match crate::syntax_highlight::detect_language(path, text.lines().next()) {
+            Some(language) => crate::syntax_highlight::highlight_text(language, &text),
+            None => Vec::new(),
+        };
+        if content.len() > MAX_PREVIEW_SIZE {
+            return Ok((format!("{}\n\n(only the first {} of {} bytes are shown)", text, MAX_PREVIEW_SIZE, content.len()), highlights));
+        }
+
+        Ok((text, highlights))
+    }
+
+    fn save_blob(&self, blob: git2::Oid, file_path: &std::path::Path) -> Result<(), git2::Error> {
+        let content = crate::tree_items::load_blob(&self.repo.lock().unwrap(), blob)?;
+        std::fs::write(file_path, content)
+            .map_err(|e| git2::Error::from_str(&format!("Error writing {}: {}", file_path.display(), e)))
+    }
+
+    fn load_reflog(&self, ref_name: &str) -> Result<Vec<ReflogEntry>, git2::Error> {
+        crate::reflog::read_reflog(&self.repo.lock().unwrap(), ref_name)
+    }
+
+    /// Lists `commit` along with the refs from now on; returns `false` if it already was
+    fn add_extra_tip(&self, commit: git2::Oid) -> bool {
+        let mut extra_tips = self.extra_tips.lock().unwrap();
+        if extra_tips.contains(&commit) {
+            return false;
+        }
+        extra_tips.push(commit);
+        true
+    }
+}
+
+/// Tree of a commit; `None` for the zero OID, which stands for the empty tree
+fn commit_tree(repo: &git2::Repository, oid: git2::Oid) -> Result<Option<git2::Tree<'_>>, git2::Error> {
+    if oid.is_zero() {
+        Ok(None)
+    } else {
+        repo.find_commit(oid)?.tree().map(Some)
+    }
+}
+
+/// HEAD commit; zero OID if HEAD is unborn
+fn head_commit_id(repo: &git2::Repository) -> Result<git2::Oid, git2::Error> {
+    match repo.head() {
+        Ok(head) => head.peel_to_commit().map(|commit| commit.id()),
+        Err(ref e) if e.code() == git2::ErrorCode::UnbornBranch || e.code() == git2::ErrorCode::NotFound => Ok(git2::Oid::zero()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Whether the working tree has changes that are not staged and whether the index has staged changes
+fn uncommitted_changes_status(repo: &git2::Repository) -> Result<(bool, bool), git2::Error> {
+    if repo.is_bare() {
+        return Ok((false, false));
+    }
+
+    let mut status_options = git2::StatusOptions::new();
+    status_options.include_untracked(true);
+    status_options.exclude_submodules(true);
+    let statuses = repo.statuses(Some(&mut status_options))?;
+
+    let unstaged_flags = git2::Status::WT_NEW | git2::Status::WT_MODIFIED | git2::Status::WT_DELETED
+        | git2::Status::WT_TYPECHANGE | git2::Status::WT_RENAMED | git2::Status::CONFLICTED;
+    let staged_flags = git2::Status::INDEX_NEW | git2::Status::INDEX_MODIFIED | git2::Status::INDEX_DELETED
+        | git2::Status::INDEX_TYPECHANGE | git2::Status::INDEX_RENAMED;
+
+    Ok((
+        statuses.iter().any(|entry| entry.status().intersects(unstaged_flags)),
+        statuses.iter().any(|entry| entry.status().intersects(staged_flags)),
+    ))
+}
+
+/// Fails if there are uncommitted changes, which operations creating commits on top of HEAD refuse:
+/// staged changes would end up in the new commits, and aborting resets the whole working tree
+fn ensure_no_uncommitted_changes(repo: &git2::Repository) -> Result<(), git2::Error> {
+    let (has_unstaged, has_staged) = uncommitted_changes_status(repo)?;
+    if has_unstaged || has_staged {
+        return Err(git2::Error::from_str("Commit, stash or discard the uncommitted changes first"));
+    }
+
+    Ok(())
+}
+
+/// Unstaged changes, including untracked files
+fn diff_index_to_workdir<'repo>(repo: &'repo git2::Repository, diff_options: &mut git2::DiffOptions) -> Result<git2::Diff<'repo>, git2::Error> {
+    diff_options.include_untracked(true);
+    diff_options.recurse_untracked_dirs(true);
+    diff_options.show_untracked_content(true);
+
+    repo.diff_index_to_workdir(None, Some(diff_options))
+}
+
+/// Patch applying the selected lines of the staged or unstaged changes of a file; `reverse` makes a patch that reverts them.
+/// The patch is made from a diff with default options, as whitespace and context settings of the displayed diff would
+/// make it not match the index.
+fn selected_lines_patch(repo: &git2::Repository, path: &str, lines: &SelectedLines, staged: bool, reverse: bool) -> Result<Vec<u8>, git2::Error> {
+    let mut diff_options = git2::DiffOptions::new();
+    diff_options.pathspec(path);
+    diff_options.disable_pathspec_match(true);
+    let diff = if staged {
+        let head_tree = commit_tree(repo, head_commit_id(repo)?)?;
+        repo.diff_tree_to_index(head_tree.as_ref(), None, Some(&mut diff_options))?
+    } else {
+        diff_index_to_workdir(repo, &mut diff_options)?
+    };
+
+    let file_diff = FileDiff::find_in_diff(&diff, path)?
+        .filter(|file_diff| file_diff.is_partially_applicable())
+        .ok_or_else(|| git2::Error::from_str(&format!("{} has no changes that can be applied partially", path)))?;
+    crate::partial_patch::build_partial_patch(&file_diff, reverse, |hunk_idx, line_idx| {
+        lines.contains(&file_diff.hunks[hunk_idx].lines[line_idx])
+    }).ok_or_else(|| git2::Error::from_str("The selected lines are not changed anymore"))
+}
+
+fn diff_to_target<'repo>(
+    repo: &'repo git2::Repository,
+    old_tree: Option<&git2::Tree<'repo>>,
+    target: DiffTarget,
+    diff_options: &mut git2::DiffOptions,
+) -> Result<git2::Diff<'repo>, git2::Error> {
+    match target {
+        DiffTarget::Commit(commit) => repo.diff_tree_to_tree(old_tree, commit_tree(repo, commit)?.as_ref(), Some(diff_options)),
+        DiffTarget::Index => repo.diff_tree_to_index(old_tree, None, Some(diff_options)),
+        DiffTarget::WorkingTree => repo.diff_tree_to_workdir_with_index(old_tree, Some(diff_options)),
+    }
+}
+
+pub struct MainScreen {
+    backend: Arc<MainScreenBackend>,
+    ui: MainScreenUi,
+}
+
+impl MainScreen {
+    pub async fn create(repo_path: PathBuf) -> std::result::Result<Rc<MainScreen>, String> {
+        let window = gtk::Window::new(gtk::WindowType::Toplevel);
+        window.set_title(&repo_path.to_string_lossy());
+
+        let commits_list_store = gtk::ListStore::new(&[
+            gtk::Type::String, // Commit ID as string; UNSTAGED_CHANGES_ROW or STAGED_CHANGES_ROW for uncommitted changes, STASH_ROW_PREFIX and the ID for stash entries
+            gtk::Type::String, // Commit message
+            gtk::Type::String, // Commit date as string
+            gtk::Type::String, // Commit author email
+            gtk::Type::I32, // Index of the row in commits_graph; -1 if there is no graph row
+            gtk::Type::String, // Commit message as markup, prefixed with ref labels
+        ]);
+
+        let commits_graph = Rc::new(RefCell::new(Vec::<LayoutRow<CommitNode>>::new()));
+
+        let diff_items_list_store = gtk::ListStore::new(&[
+            gtk::Type::String, // Parent OID, zero OID for root commits, COMBINED_DIFF_PARENT for combined diff, INDEX_DIFF_PARENT for unstaged changes; empty if this a "grouping" item
+            gtk::Type::String, // File path
+            gtk::Type::I32, // Change type, see ChangeType::code
+            gtk::Type::String, // Renamed or copied from path; empty if not renamed
+            gtk::Type::String, // Change type icon name
+            gtk::Type::String, // Change type label as markup
+            gtk::Type::String, // Path as displayed, "old \u{2192} new" for renames
+        ]);
+
+        let main_vbox = gtk::Box::new(gtk::Orientation::Vertical, 0);
+
+        window.add(&main_vbox);
+
+        let toolbar_hbox = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+        toolbar_hbox.set_border_width(4);
+        main_vbox.pack_start(&toolbar_hbox, false, false, 0);
+
+        toolbar_hbox.pack_start(&gtk::Label::new(Some("Show:")), false, false, 0);
+
+        let refs_filter_combo_box = gtk::ComboBoxText::new();
+        for filter in RefsFilter::ALL.iter() {
+            refs_filter_combo_box.append(Some(filter.id()), filter.title());
+        }
+        refs_filter_combo_box.set_active_id(Some(RefsFilter::Head.id()));
+        toolbar_hbox.pack_start(&refs_filter_combo_box, false, false, 0);
+
+        let dense_combined_diff_check_button = gtk::CheckButton::new_with_label("Hide merge hunks taken from one parent");
+        dense_combined_diff_check_button.set_active(true);
+        toolbar_hbox.pack_start(&dense_combined_diff_check_button, false, false, 0);
+
+        toolbar_hbox.pack_start(&gtk::Label::new(Some("Compare with:")), false, false, 0);
+
+        let compare_with_combo_box = gtk::ComboBoxText::new();
+        for compare_with in CompareWith::ALL.iter() {
+            compare_with_combo_box.append(Some(compare_with.id()), compare_with.title());
+        }
+        compare_with_combo_box.set_active_id(Some(CompareWith::Parents.id()));
+        compare_with_combo_box.set_tooltip_text(Some("Base of the diff when a single commit is selected; select two commits to compare them with each other"));
+        toolbar_hbox.pack_start(&compare_with_combo_box, false, false, 0);
+
+        let commit_panel_toggle_button = gtk::ToggleButton::new_with_label("Commit\u{2026}");
+        toolbar_hbox.pack_end(&commit_panel_toggle_button, false, false, 0);
+
+        let stash_button = gtk::Button::new_with_label("Stash\u{2026}");
+        stash_button.set_tooltip_text(Some("Save the uncommitted changes as a stash entry and reset them"));
+        toolbar_hbox.pack_end(&stash_button, false, false, 0);
+
+        let diff_options_button = gtk::MenuButton::new();
+        diff_options_button.set_label("Diff options");
+        toolbar_hbox.pack_end(&diff_options_button, false, false, 0);
+
+        let diff_whitespace_combo_box = gtk::ComboBoxText::new();
+        for mode in WhitespaceMode::ALL.iter() {
+            diff_whitespace_combo_box.append(Some(mode.id()), mode.title());
+        }
+        let diff_context_lines_spin_button = gtk::SpinButton::new_with_range(0.0, 100.0, 1.0);
+        let diff_algorithm_combo_box = gtk::ComboBoxText::new();
+        for algorithm in DiffAlgorithm::ALL.iter() {
+            diff_algorithm_combo_box.append(Some(algorithm.id()), algorithm.title());
+        }
+        let diff_binary_as_text_check_button = gtk::CheckButton::new_with_label("Show changes in binary files as text");
+        let diff_rename_threshold_spin_button = gtk::SpinButton::new_with_range(1.0, 100.0, 5.0);
+
+        {
+            let grid = gtk::Grid::new();
+            grid.set_border_width(8);
+            grid.set_row_spacing(4);
+            grid.set_column_spacing(8);
+
+            let rows: [(&str, &gtk::Widget); 5] = [
+                ("Whitespace", diff_whitespace_combo_box.upcast_ref()),
+                ("Context lines", diff_context_lines_spin_button.upcast_ref()),
+                ("Algorithm", diff_algorithm_combo_box.upcast_ref()),
+                ("Rename similarity, %", diff_rename_threshold_spin_button.upcast_ref()),
+                ("", diff_binary_as_text_check_button.upcast_ref()),
+            ];
+            for (row_idx, (title, widget)) in rows.iter().enumerate() {
+                let label = gtk::Label::new(Some(*title));
+                label.set_halign(gtk::Align::Start);
+                grid.attach(&label, 0, row_idx as i32, 1, 1);
+                grid.attach(*widget, 1, row_idx as i32, 1, 1);
+            }
+            grid.show_all();
+
+            let popover = gtk::Popover::new(Some(&diff_options_button));
+            popover.add(&grid);
+            diff_options_button.set_popover(Some(&popover));
+        }
+
+        let refs_sidebar = RefsSidebar::new();
+
+        let vpane = gtk::Paned::new(gtk::Orientation::Vertical);
+
+        let reflog_view = ReflogView::new();
+
+        let sidebar_vpane = gtk::Paned::new(gtk::Orientation::Vertical);
+        sidebar_vpane.pack1(refs_sidebar.widget(), true, true);
+        sidebar_vpane.pack2(reflog_view.widget(), false, true);
+
+        let sidebar_hpane = gtk::Paned::new(gtk::Orientation::Horizontal);
+        sidebar_hpane.pack1(&sidebar_vpane, false, true);
+        sidebar_hpane.pack2(&vpane, true, false);
+        main_vbox.pack_start(&sidebar_hpane, true, true, 0);
+
+        let commits_tree_view = gtk::TreeView::new();
+
+        let scrolled_window = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+
+        scrolled_window.add(&commits_tree_view);
+
+        commits_tree_view.set_model(Some(&commits_list_store));
+        commits_tree_view.get_selection().set_mode(gtk::SelectionMode::Multiple);
+
+        {
+            let cell_renderer = CommitGraphRenderer::create(commits_graph.clone());
+            cell_renderer.set_padding(0, 0);
+            let column = gtk::TreeViewColumn::new();
+            column.set_title("Graph");
+            column.set_resizable(true);
+            column.set_expand(false);
+            column.set_sizing(gtk::TreeViewColumnSizing::Autosize);
+            column.pack_start(&cell_renderer, true);
+            column.add_attribute(&cell_renderer, "row", 4);
+
+            commits_tree_view.append_column(&column);
+        }
+
+        {
+            let cell_renderer = gtk::CellRendererText::new();
+            cell_renderer.set_property_ellipsize(pango::EllipsizeMode::End);
+            let column = gtk::TreeViewColumn::new();
+            column.set_title("Summary");
+            column.set_resizable(true);
+            column.set_expand(true);
+            column.pack_start(&cell_renderer, true);
+            column.add_attribute(&cell_renderer, "markup", 5);
+
+            commits_tree_view.append_column(&column);
+        }
+
+        {
+            let cell_renderer = gtk::CellRendererText::new();
+            let column = gtk::TreeViewColumn::new();
+            column.set_title("Time");
+            column.set_resizable(true);
+            column.set_expand(false);
+            column.pack_start(&cell_renderer, true);
+            column.add_attribute(&cell_renderer, "text", 2);
+
+            commits_tree_view.append_column(&column);
+        }
+
+        {
+            let cell_renderer = gtk::CellRendererText::new();
+            cell_renderer.set_property_ellipsize(pango::EllipsizeMode::End);
+            let column = gtk::TreeViewColumn::new();
+            column.set_title("Author");
+            column.set_resizable(true);
+            column.set_expand(true);
+            column.pack_start(&cell_renderer, true);
+            column.add_attribute(&cell_renderer, "text", 3);
+
+            commits_tree_view.append_column(&column);
+        }
+
+        // Shown while the history of a file is listed instead of all commits
+        let file_history_bar = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+        file_history_bar.set_border_width(2);
+        let file_history_label = gtk::Label::new(None);
+        file_history_label.set_halign(gtk::Align::Start);
+        file_history_label.set_ellipsize(pango::EllipsizeMode::Start);
+        let close_file_history_button = gtk::Button::new_with_label("Show all commits");
+        file_history_bar.pack_start(&file_history_label, true, true, 0);
+        file_history_bar.pack_end(&close_file_history_button, false, false, 0);
+        file_history_bar.show_all();
+        file_history_bar.set_no_show_all(true);
+        file_history_bar.hide();
+
+        let commits_vbox = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        commits_vbox.pack_start(&file_history_bar, false, false, 0);
+        commits_vbox.pack_start(&scrolled_window, true, true, 0);
+
+        vpane.pack1(&commits_vbox, true, false);
+
+        let commits_menu = gtk::Menu::new();
+        let cherry_pick_menu_item = gtk::MenuItem::new_with_label("Cherry-pick onto HEAD");
+        let revert_menu_item = gtk::MenuItem::new_with_label("Revert");
+        let create_tag_menu_item = gtk::MenuItem::new_with_label("Create tag here\u{2026}");
+        commits_menu.append(&cherry_pick_menu_item);
+        commits_menu.append(&revert_menu_item);
+        let rebase_menu_item = gtk::MenuItem::new_with_label("Rebase current branch onto here\u{2026}");
+        commits_menu.append(&rebase_menu_item);
+        commits_menu.append(&gtk::SeparatorMenuItem::new());
+        commits_menu.append(&create_tag_menu_item);
+        commits_menu.show_all();
+
+        let stash_menu = gtk::Menu::new();
+        let apply_stash_menu_item = gtk::MenuItem::new_with_label("Apply");
+        let pop_stash_menu_item = gtk::MenuItem::new_with_label("Pop");
+        let drop_stash_menu_item = gtk::MenuItem::new_with_label("Drop\u{2026}");
+        stash_menu.append(&apply_stash_menu_item);
+        stash_menu.append(&pop_stash_menu_item);
+        stash_menu.append(&gtk::SeparatorMenuItem::new());
+        stash_menu.append(&drop_stash_menu_item);
+        stash_menu.show_all();
+
+        let diff_items_menu = gtk::Menu::new();
+        let file_history_menu_item = gtk::MenuItem::new_with_label("Show history of this file");
+        diff_items_menu.append(&file_history_menu_item);
+        let blame_menu_item = gtk::MenuItem::new_with_label("Blame this file");
+        diff_items_menu.append(&blame_menu_item);
+        diff_items_menu.show_all();
+
+        let commit_info_view = gtk::TextView::new();
+        commit_info_view.set_editable(false);
+
+        let scrolled_window_2 = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+
+        let details_hpane = gtk::Paned::new(gtk::Orientation::Horizontal);
+        vpane.pack2(&details_hpane, true, false);
+
+        let commit_panel = CommitPanel::new();
+        commit_panel.widget().show_all();
+        commit_panel.widget().set_no_show_all(true);
+        commit_panel.widget().hide();
+
+        let blame_view = BlameView::new(&window);
+
+        // Shown while a merge, cherry-pick or revert is stopped with conflicts
+        let conflicts_view = ConflictsView::new();
+        conflicts_view.widget().show_all();
+        conflicts_view.widget().set_no_show_all(true);
+        conflicts_view.widget().hide();
+
+        let tree_browser = TreeBrowser::new();
+
+        let details_notebook = gtk::Notebook::new();
+        details_notebook.append_page(&scrolled_window_2, Some(&gtk::Label::new(Some("Changes"))));
+        details_notebook.append_page(tree_browser.widget(), Some(&gtk::Label::new(Some("Tree"))));
+
+        let details_vbox = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        details_vbox.pack_start(&details_notebook, true, true, 0);
+        details_vbox.pack_start(commit_panel.widget(), false, false, 0);
+        details_hpane.pack1(&details_vbox, true, false);
+
+        let diff_view = DiffView::new();
+
+        // Shown only for uncommitted changes
+        let staging_hbox = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+        staging_hbox.set_border_width(2);
+        let stage_file_button = gtk::Button::new();
+        let stage_hunks_button = gtk::Button::new();
+        let stage_lines_button = gtk::Button::new();
+        stage_hunks_button.set_tooltip_text(Some("Hunks under the selection or the last clicked row"));
+        stage_lines_button.set_tooltip_text(Some("Changed lines under the selection or the last clicked row"));
+        for button in [&stage_file_button, &stage_hunks_button, &stage_lines_button].iter() {
+            staging_hbox.pack_start(*button, false, false, 0);
+            button.show();
+        }
+        let discard_file_button = gtk::Button::new_with_label("Discard file");
+        let discard_hunks_button = gtk::Button::new_with_label("Discard hunk");
+        discard_hunks_button.set_tooltip_text(Some("Hunks under the selection or the last clicked row"));
+        for button in [&discard_hunks_button, &discard_file_button].iter() {
+            staging_hbox.pack_end(*button, false, false, 0);
+        }
+        staging_hbox.set_no_show_all(true);
+
+        let diff_vbox = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        diff_vbox.pack_start(conflicts_view.widget(), false, false, 0);
+        diff_vbox.pack_start(&staging_hbox, false, false, 0);
+        diff_vbox.pack_start(diff_view.widget(), true, true, 0);
+        details_hpane.pack2(&diff_vbox, true, false);
+
+        let commit_info_viewport = gtk::Viewport::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+
+        scrolled_window_2.add(&commit_info_viewport);
+
+        let commit_info_vbox = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        commit_info_vbox.pack_start(&commit_info_view, false, false, 0);
+        commit_info_viewport.add(&commit_info_vbox);
+
+        let diff_items_tree_view = gtk::TreeView::new();
+        commit_info_vbox.add(&diff_items_tree_view);
+
+        diff_items_tree_view.set_model(Some(&diff_items_list_store));
+
+        {
+            let column = gtk::TreeViewColumn::new();
+            column.set_title("Change");
+            column.set_resizable(true);
+            column.set_expand(true);
+
+            let cell_renderer_change_icon = gtk::CellRendererPixbuf::new();
+            column.pack_start(&cell_renderer_change_icon, false);
+            column.add_attribute(&cell_renderer_change_icon, "icon-name", 4); /* 4: change type icon */
+
+            let cell_renderer_change_type = gtk::CellRendererText::new();
+            cell_renderer_change_type.set_property_ellipsize(pango::EllipsizeMode::End);
+            column.pack_start(&cell_renderer_change_type, false);
+            column.add_attribute(&cell_renderer_change_type, "markup", 5); /* 5: change type label */
+            
+            let cell_renderer_path = gtk::CellRendererText::new();
+            cell_renderer_path.set_property_ellipsize(pango::EllipsizeMode::End);
+            column.pack_start(&cell_renderer_path, true);
+            column.add_attribute(&cell_renderer_path, "text", 6); /* 6: displayed file path */
+
+            diff_items_tree_view.append_column(&column);
+        }
+
+        let (repo, diff_settings, rebase_plan) = async_std::task::spawn(async move {
+            use git2::Repository;
+            let repo = Repository::discover(repo_path).map_err(|e| format!("Error opening repository: {}", e))?;
+            let diff_settings = DiffSettings::load(&repo);
+            // A rebase may have been left unfinished when the application was last closed
+            let rebase_plan = crate::rebase::load_plan(&repo)
+                .map_err(|e| format!("Error loading the rebase in progress: {}", e))?;
+
+            Ok::<_, String>((repo, diff_settings, rebase_plan))
+        }).await?;
+
+        diff_whitespace_combo_box.set_active_id(Some(diff_settings.whitespace.id()));
+        diff_context_lines_spin_button.set_value(f64::from(diff_settings.context_lines));
+        diff_algorithm_combo_box.set_active_id(Some(diff_settings.algorithm.id()));
+        diff_binary_as_text_check_button.set_active(diff_settings.binary_as_text);
+        diff_rename_threshold_spin_button.set_value(f64::from(diff_settings.rename_threshold));
+
+        let main_screen = MainScreen {
+            backend: Arc::new(MainScreenBackend {
+                repo: Mutex::new(repo),
+                requested_comparison: Mutex::new(None),
+                requested_file_diff: Mutex::new(None),
+                commits_generation: Mutex::new(0),
+                diff_settings: Mutex::new(diff_settings),
+                pending_picks: Mutex::new(Vec::new()),
+                rebase_plan: Mutex::new(rebase_plan),
+                extra_tips: Mutex::new(Vec::new()),
+                requested_file_history: Mutex::new(None),
+                file_history_paths: Mutex::new(Vec::new()),
+            }),
+            ui: MainScreenUi {
+                window,
+                refs_filter_combo_box,
+                dense_combined_diff_check_button,
+                compare_with_combo_box,
+                diff_whitespace_combo_box,
+                diff_context_lines_spin_button,
+                diff_algorithm_combo_box,
+                diff_binary_as_text_check_button,
+                diff_rename_threshold_spin_button,
+                commits_list_store,
+                commits_tree_view,
+                file_history_bar,
+                file_history_label,
+                commits_graph,
+                commit_nodes: RefCell::new(Vec::new()),
+                commit_info_view,
+                diff_items_list_store,
+                diff_items_tree_view,
+                diff_items_menu,
+                file_history_menu_item,
+                blame_menu_item,
+                blame_view,
+                details_notebook,
+                tree_browser,
+                diff_view,
+                staging_hbox,
+                stage_file_button,
+                stage_hunks_button,
+                stage_lines_button,
+                discard_file_button,
+                discard_hunks_button,
+                commit_panel_toggle_button,
+                commit_panel,
+                refs_sidebar,
+                reflog_view,
+                commits_menu,
+                create_tag_menu_item,
+                cherry_pick_menu_item,
+                revert_menu_item,
+                rebase_menu_item,
+                stash_menu,
+                apply_stash_menu_item,
+                pop_stash_menu_item,
+                drop_stash_menu_item,
+                conflicts_view,
+                stopped_operation: Cell::new(None),
+                pending_selected_commit: RefCell::new(None),
+                diff_settings_timeout: RefCell::new(None),
+            },
+        };
+
+        let main_screen = Rc::new(main_screen);
+
+        stash_button.connect_clicked(capture!(main_screen; move |_| {
+            Self::save_stash(main_screen.clone());
+        }));
+        close_file_history_button.connect_clicked(capture!(main_screen; move |_| {
+            Self::show_file_history(main_screen.clone(), None);
+        }));
+
+        Self::subscribe(main_screen.clone());
+        Ok(main_screen)
+    }
+
+    fn subscribe(main_screen: Rc<Self>) {
+        main_screen.ui.diff_items_tree_view.get_selection().connect_changed(capture!(main_screen; move |_| {
+            Self::load_selected_file_diff(main_screen.clone());
+        }));
+
+        main_screen.ui.diff_items_tree_view.connect_row_activated(capture!(main_screen; move |_, _, _| {
+            Self::stage_selected(main_screen.clone(), StagingScope::File);
+        }));
+
+        main_screen.ui.diff_items_tree_view.connect_button_press_event(capture!(main_screen; move |tree_view, event| {
+            if event.get_event_type() != gdk::EventType::ButtonPress || event.get_button() != 3 {
+                return Inhibit(false);
+            }
+
+            let (x, y) = event.get_position();
+            if let Some((Some(path), _, _, _)) = tree_view.get_path_at_pos(x as i32, y as i32) {
+                tree_view.get_selection().select_path(&path);
+            }
+            if main_screen.selected_diff_item().is_none() {
+                return Inhibit(false);
+            }
+
+            main_screen.ui.diff_items_menu.popup_easy(event.get_button(), event.get_time());
+            Inhibit(true)
+        }));
+        main_screen.ui.file_history_menu_item.connect_activate(capture!(main_screen; move |_| {
+            if let Some((_, path, _)) = main_screen.selected_diff_item() {
+                Self::show_file_history(main_screen.clone(), Some(path));
+            }
+        }));
+        main_screen.ui.blame_menu_item.connect_activate(capture!(main_screen; move |_| {
+            Self::blame_selected_file(main_screen.clone());
+        }));
+
+        main_screen.ui.details_notebook.connect_switch_page(capture!(main_screen; move |_, _, page_num| {
+            if page_num == TREE_PAGE {
+                Self::load_tree(main_screen.clone());
+            }
+        }));
+        main_screen.ui.tree_browser.connect_expand(capture!(main_screen; move |tree_id, path| {
+            Self::load_tree_children(main_screen.clone(), tree_id, path);
+        }));
+        main_screen.ui.tree_browser.connect_file_selected(capture!(main_screen; move |item| {
+            Self::load_tree_preview(main_screen.clone(), item);
+        }));
+        main_screen.ui.tree_browser.connect_save(capture!(main_screen; move |item| {
+            Self::save_blob(main_screen.clone(), item);
+        }));
+        main_screen.ui.tree_browser.connect_history(capture!(main_screen; move |path| {
+            Self::show_file_history(main_screen.clone(), Some(path));
+        }));
+        main_screen.ui.tree_browser.connect_blame(capture!(main_screen; move |commit, path| {
+            Self::run_blame(main_screen.clone(), move |backend| backend.blame_file(Some(commit), &path));
+        }));
+
+        main_screen.ui.blame_view.connect_commit_selected(capture!(main_screen; move |commit| {
+            Self::select_commit(main_screen.clone(), commit);
+        }));
+        main_screen.ui.blame_view.connect_blame_parent(capture!(main_screen; move |commit, path| {
+            Self::run_blame(main_screen.clone(), move |backend| backend.blame_parent(commit, &path));
+        }));
+
+        main_screen.ui.stage_file_button.connect_clicked(capture!(main_screen; move |_| {
+            Self::stage_selected(main_screen.clone(), StagingScope::File);
+        }));
+        main_screen.ui.stage_hunks_button.connect_clicked(capture!(main_screen; move |_| {
+            Self::stage_selected(main_screen.clone(), StagingScope::Hunks);
+        }));
+        main_screen.ui.stage_lines_button.connect_clicked(capture!(main_screen; move |_| {
+            Self::stage_selected(main_screen.clone(), StagingScope::Lines);
+        }));
+        main_screen.ui.discard_file_button.connect_clicked(capture!(main_screen; move |_| {
+            Self::discard_selected(main_screen.clone(), StagingScope::File);
+        }));
+        main_screen.ui.discard_hunks_button.connect_clicked(capture!(main_screen; move |_| {
+            Self::discard_selected(main_screen.clone(), StagingScope::Hunks);
+        }));
+
+        main_screen.ui.commit_panel_toggle_button.connect_toggled(capture!(main_screen; move |toggle_button| {
+            main_screen.ui.commit_panel.widget().set_visible(toggle_button.get_active());
+            Self::load_commit_files(main_screen.clone());
+        }));
+
+        main_screen.ui.commit_panel.connect_amend_toggled(capture!(main_screen; move |amend| {
+            if amend && main_screen.ui.commit_panel.is_message_empty() {
+                Self::load_head_message(main_screen.clone());
+            }
+            Self::load_commit_files(main_screen.clone());
+        }));
+
+        main_screen.ui.commit_panel.connect_commit(capture!(main_screen; move || {
+            Self::create_commit(main_screen.clone());
+        }));
+
+        main_screen.ui.dense_combined_diff_check_button.connect_toggled(capture!(main_screen; move |_| {
+            Self::load_selected_file_diff(main_screen.clone());
+        }));
+
+        main_screen.ui.diff_whitespace_combo_box.connect_changed(capture!(main_screen; move |_| {
+            Self::apply_diff_settings(main_screen.clone());
+        }));
+        main_screen.ui.diff_context_lines_spin_button.connect_value_changed(capture!(main_screen; move |_| {
+            Self::apply_diff_settings_later(main_screen.clone());
+        }));
+        main_screen.ui.diff_algorithm_combo_box.connect_changed(capture!(main_screen; move |_| {
+            Self::apply_diff_settings(main_screen.clone());
+        }));
+        main_screen.ui.diff_binary_as_text_check_button.connect_toggled(capture!(main_screen; move |_| {
+            Self::apply_diff_settings(main_screen.clone());
+        }));
+        main_screen.ui.diff_rename_threshold_spin_button.connect_value_changed(capture!(main_screen; move |_| {
+            Self::apply_diff_settings_later(main_screen.clone());
+        }));
+
+        main_screen.ui.refs_filter_combo_box.connect_changed(capture!(main_screen; move |_| {
+            Self::load_commits(main_screen.clone(), main_screen.refs_filter());
+        }));
+
+        main_screen.ui.compare_with_combo_box.connect_changed(capture!(main_screen; move |_| {
+            Self::load_selected_comparison(main_screen.clone());
+        }));
+
+        main_screen.ui.commits_tree_view.get_selection().connect_changed(capture!(main_screen; move |_| {
+            Self::load_selected_comparison(main_screen.clone());
+        }));
+
+        main_screen.ui.commits_tree_view.connect_button_press_event(capture!(main_screen; move |tree_view, event| {
+            if event.get_event_type() != gdk::EventType::ButtonPress || event.get_button() != 3 {
+                return Inhibit(false);
+            }
+
+            // Right click on an unselected row selects it, like a left click would
+            let (x, y) = event.get_position();
+            if let Some((Some(path), _, _, _)) = tree_view.get_path_at_pos(x as i32, y as i32) {
+                let selection = tree_view.get_selection();
+                if !selection.path_is_selected(&path) {
+                    selection.unselect_all();
+                    selection.select_path(&path);
+                }
+            }
+
+            if main_screen.selected_stash().is_some() {
+                main_screen.ui.stash_menu.popup_easy(event.get_button(), event.get_time());
+                return Inhibit(true);
+            }
+
+            let has_selected_commits = !main_screen.selected_commits().is_empty();
+            main_screen.ui.cherry_pick_menu_item.set_sensitive(has_selected_commits);
+            main_screen.ui.revert_menu_item.set_sensitive(has_selected_commits);
+            main_screen.ui.rebase_menu_item.set_sensitive(main_screen.selected_commits().len() == 1);
+            main_screen.ui.create_tag_menu_item.set_sensitive(has_selected_commits);
+            main_screen.ui.commits_menu.popup_easy(event.get_button(), event.get_time());
+            Inhibit(true)
+        }));
+
+        main_screen.ui.cherry_pick_menu_item.connect_activate(capture!(main_screen; move |_| {
+            Self::pick_selected(main_screen.clone(), PickOperation::CherryPick);
+        }));
+        main_screen.ui.revert_menu_item.connect_activate(capture!(main_screen; move |_| {
+            Self::pick_selected(main_screen.clone(), PickOperation::Revert);
+        }));
+        main_screen.ui.rebase_menu_item.connect_activate(capture!(main_screen; move |_| {
+            Self::rebase_onto_selected(main_screen.clone());
+        }));
+        main_screen.ui.apply_stash_menu_item.connect_activate(capture!(main_screen; move |_| {
+            Self::apply_selected_stash(main_screen.clone(), false);
+        }));
+        main_screen.ui.pop_stash_menu_item.connect_activate(capture!(main_screen; move |_| {
+            Self::apply_selected_stash(main_screen.clone(), true);
+        }));
+        main_screen.ui.drop_stash_menu_item.connect_activate(capture!(main_screen; move |_| {
+            Self::drop_selected_stash(main_screen.clone());
+        }));
+
+        main_screen.ui.conflicts_view.connect_continue(capture!(main_screen; move || {
+            match main_screen.ui.stopped_operation.get() {
+                Some(StoppedOperation::Merge) => {
+                    Self::run_merge(main_screen.clone(), "Error concluding merge", |backend| backend.conclude_merge());
+                },
+                Some(StoppedOperation::Pick(_)) => Self::continue_pick(main_screen.clone()),
+                Some(StoppedOperation::Rebase) => {
+                    Self::run_rebase(main_screen.clone(), "Error continuing rebase", |backend| backend.continue_rebase());
+                },
+                None => {},
+            }
+        }));
+        main_screen.ui.conflicts_view.connect_abort(capture!(main_screen; move || {
+            Self::abort_operation(main_screen.clone());
+        }));
+        main_screen.ui.conflicts_view.connect_path_selected(capture!(main_screen; move |path| {
+            Self::load_conflict_sides(main_screen.clone(), path);
+        }));
+        main_screen.ui.conflicts_view.connect_resolve(capture!(main_screen; move |path, resolution| {
+            Self::resolve_conflict(main_screen.clone(), path, resolution);
+        }));
+
+        main_screen.ui.refs_sidebar.connect_action(RefAction::Merge, capture!(main_screen; move |entry| {
+            if let Some(entry) = entry {
+                Self::merge(main_screen.clone(), entry);
+            }
+        }));
+
+        main_screen.ui.create_tag_menu_item.connect_activate(capture!(main_screen; move |_| {
+            Self::create_tag(main_screen.clone());
+        }));
+
+        main_screen.ui.refs_sidebar.connect_ref_selected(capture!(main_screen; move |entry| {
+            main_screen.ui.reflog_view.set_active_ref(&entry.full_name);
+            Self::select_commit(main_screen.clone(), entry.target);
+        }));
+
+        main_screen.ui.reflog_view.connect_ref_changed(capture!(main_screen; move |_| {
+            Self::load_reflog(main_screen.clone());
+        }));
+        main_screen.ui.reflog_view.connect_entry_selected(capture!(main_screen; move |commit| {
+            Self::select_commit(main_screen.clone(), commit);
+        }));
+
+        main_screen.ui.refs_sidebar.connect_action(RefAction::NewBranch, capture!(main_screen; move |_| {
+            Self::create_branch(main_screen.clone());
+        }));
+        main_screen.ui.refs_sidebar.connect_action(RefAction::Rename, capture!(main_screen; move |entry| {
+            if let Some(entry) = entry {
+                Self::rename_branch(main_screen.clone(), entry);
+            }
+        }));
+        main_screen.ui.refs_sidebar.connect_action(RefAction::Delete, capture!(main_screen; move |entry| {
+            match entry {
+                Some(entry) if entry.kind == RefKind::Tag => Self::delete_tag(main_screen.clone(), entry),
+                Some(entry) => Self::delete_branch(main_screen.clone(), entry),
+                None => {},
+            }
+        }));
+        main_screen.ui.refs_sidebar.connect_action(RefAction::MoveTag, capture!(main_screen; move |entry| {
+            if let Some(entry) = entry {
+                Self::move_tag(main_screen.clone(), entry);
+            }
+        }));
+        main_screen.ui.refs_sidebar.connect_action(RefAction::Checkout, capture!(main_screen; move |entry| {
+            if let Some(entry) = entry {
+                Self::update_refs(main_screen.clone(), "Error checking out", move |repo| {
+                    crate::refs::checkout_ref(repo, &entry)
+                });
+            }
+        }));
+    }
+
+    /// Applies the diff options once they have not changed for a while
+    fn apply_diff_settings_later(main_screen: Rc<Self>) {
+        if let Some(timeout) = main_screen.ui.diff_settings_timeout.borrow_mut().take() {
+            glib::source_remove(timeout);
+        }
+
+        let timeout = glib::timeout_add_local(DIFF_SETTINGS_DELAY_MS, capture!(main_screen; move || {
+            main_screen.ui.diff_settings_timeout.replace(None);
+            Self::apply_diff_settings(main_screen.clone());
+            glib::Continue(false)
+        }));
+        main_screen.ui.diff_settings_timeout.replace(Some(timeout));
+    }
+
+    fn apply_diff_settings(main_screen: Rc<Self>) {
+        if let Some(timeout) = main_screen.ui.diff_settings_timeout.borrow_mut().take() {
+            glib::source_remove(timeout);
+        }
+
+        let diff_settings = DiffSettings {
+            whitespace: main_screen.ui.diff_whitespace_combo_box.get_active_id()
+                .and_then(|id| WhitespaceMode::from_id(&id))
+                .unwrap_or(WhitespaceMode::Show),
+            context_lines: main_screen.ui.diff_context_lines_spin_button.get_value_as_int().max(0) as u32,
+            algorithm: main_screen.ui.diff_algorithm_combo_box.get_active_id()
+                .and_then(|id| DiffAlgorithm::from_id(&id))
+                .unwrap_or(DiffAlgorithm::Myers),
+            binary_as_text: main_screen.ui.diff_binary_as_text_check_button.get_active(),
+            rename_threshold: main_screen.ui.diff_rename_threshold_spin_button.get_value_as_int().clamp(0, 100) as u16,
+        };
+
+        *main_screen.backend.diff_settings.lock().unwrap() = diff_settings;
+
+        gtk_spawn_local(capture!(main_screen; async move {
+            let result = async_std::task::spawn(capture!(backend = main_screen.backend; async move {
+                let repo = backend.repo.lock().unwrap();
+                diff_settings.save(&repo)
+                    .map_err(|e| format!("Error saving diff options: {}", e))
+            })).await;
+
+            if let Err(e) = result {
+                main_screen.show_error(&e);
+            }
+        }));
+
+        Self::load_selected_comparison(main_screen);
+    }
+
+    fn load_selected_comparison(main_screen: Rc<Self>) {
+        let comparison = main_screen.selected_comparison();
+        *main_screen.backend.requested_comparison.lock().unwrap() = comparison;
+
+        main_screen.update_staging_buttons(comparison);
+
+        let msg = match comparison {
+            None => "".to_owned(),
+            Some(comparison) => {
+                gtk_spawn_local(capture!(main_screen; async move {
+                    let result = async_std::task::spawn(capture!(backend = main_screen.backend; async move {
+                        backend.load_comparison(comparison)
+                    })).await;
+
+                    match result {
+                        Ok(Some((summary_text, changes))) => {
+                            // Reloading the same comparison (e.g. with other diff options) keeps the selected file
+                            let selected_item = main_screen.ui.diff_items_tree_view.get_selection().get_selected()
+                                .map(|(model, iter)| (
+                                    model.get_value(&iter, 0).get::<String>().unwrap_or_default(),
+                                    model.get_value(&iter, 1).get::<String>().unwrap_or_default()
+                                ));
+                            main_screen.ui.commit_info_view.get_buffer().unwrap().set_text(&summary_text);
+                            main_screen.ui.diff_items_list_store.clear();
+                            for (commit_id, item) in changes {
+                                let iter = main_screen.ui.diff_items_list_store.insert_with_values(
+                                    None,
+                                    &[0, 1, 2, 3, 4, 5, 6],
+                                    &[
+                                        &commit_id,
+                                        &item.path,
+                                        &item.change_type.code(),
+                                        &item.old_path,
+                                        &item.change_type.icon_name(),
+                                        &item.change_type.markup(),
+                                        &item.display_path()
+                                    ]
+                                );
+                                if selected_item.as_ref() == Some(&(commit_id, item.path)) {
+                                    main_screen.ui.diff_items_tree_view.get_selection().select_iter(&iter);
+                                }
+                            }
+                        },
+                        Ok(None) => {
+                            // Do nothing
+                        },
+                        Err(e) => {
+                            main_screen.ui.commit_info_view.get_buffer().unwrap().set_text(&e);
+                        }
+                    }
+                }));
+                
+                "Loading".to_owned()
+            },
+        };
+
+        if comparison.is_none() {
+            main_screen.ui.diff_items_list_store.clear();
+        }
+        main_screen.ui.commit_info_view.get_buffer().unwrap().set_text(&msg);
+
+        if main_screen.ui.details_notebook.get_current_page() == Some(TREE_PAGE) {
+            Self::load_tree(main_screen);
+        }
+    }
+
+    /// Shows the tree of the commit on the new side of the selected comparison in the tree browser
+    fn load_tree(main_screen: Rc<Self>) {
+        let commit = match main_screen.selected_comparison().map(|comparison| comparison.target()) {
+            Some(DiffTarget::Commit(commit)) => Some(commit),
+            _ => None,
+        };
+        if commit == main_screen.ui.tree_browser.commit() {
+            return;
+        }
+        let commit = match commit {
+            Some(commit) => commit,
+            None => {
+                main_screen.ui.tree_browser.set_root(None, &[]);
+                return;
+            },
+        };
+
+        gtk_spawn_local(async move {
+            let result = async_std::task::spawn(capture!(backend = main_screen.backend; async move {
+                backend.list_commit_tree(commit)
+            })).await;
+
+            // Another commit may have been selected while loading
+            if main_screen.selected_comparison().map(|comparison| comparison.target()) != Some(DiffTarget::Commit(commit)) {
+                return;
+            }
+            match result {
+                Ok(items) => main_screen.ui.tree_browser.set_root(Some(commit), &items),
+                Err(e) => {
+                    main_screen.ui.tree_browser.set_root(None, &[]);
+                    main_screen.show_error(&format!("Error reading tree of {:.8}: {}", commit, e.message()));
+                },
+            }
+        });
+    }
+
+    fn load_tree_children(main_screen: Rc<Self>, tree_id: git2::Oid, path: String) {
+        let commit = match main_screen.ui.tree_browser.commit() {
+            Some(commit) => commit,
+            None => return,
+        };
+
+        gtk_spawn_local(async move {
+            let result = async_std::task::spawn(capture!(backend = main_screen.backend, path; async move {
+                backend.list_tree(tree_id, &path)
+            })).await;
+
+            match result {
+                Ok(items) => main_screen.ui.tree_browser.set_children(commit, &path, &items),
+                Err(e) => main_screen.show_error(&format!("Error reading directory {}: {}", path, e.message())),
+            }
+        });
+    }
+
+    fn load_tree_preview(main_screen: Rc<Self>, item: TreeItem) {
+        let (blob, path) = (item.id, item.path);
+        main_screen.ui.tree_browser.show_preview("Loading\u{2026}");
+
+        gtk_spawn_local(async move {
+            let result = async_std::task::spawn(capture!(backend = main_screen.backend; async move {
+                backend.load_preview(blob, &path)
+            })).await;
+
+            // Another file may have been selected while loading
+            if main_screen.ui.tree_browser.selected_item().map(|selected| selected.id) != Some(blob) {
+                return;
+            }
+            match result {
+                Ok((text, highlights)) => main_screen.ui.tree_browser.show_file(&text, &highlights),
+                Err(e) => main_screen.ui.tree_browser.show_preview(&format!("Error loading file: {}", e.message())),
+            }
+        });
+    }
+
+    fn save_blob(main_screen: Rc<Self>, item: TreeItem) {
+        gtk_spawn_local(async move {
+            let file_path = match main_screen.ask_save_path(&item.name).await {
+                Some(file_path) => file_path,
+                None => return,
+            };
+
+            let result = async_std::task::spawn(capture!(backend = main_screen.backend; async move {
+                backend.save_blob(item.id, &file_path)
+            })).await;
+            if let Err(e) = result {
+                main_screen.show_error(&format!("Error saving file: {}", e.message()));
+            }
+        });
+    }
+
+    fn insert_commit_row(&self, position: Option<u32>, row: &CommitRow, graph_row_idx: i32) {
+        self.ui.commits_list_store.insert_with_values(
+            position,
+            &[0, 1, 2, 3, 4, 5],
+            &[
+                &row.id,
+                &row.summary,
+                &row.date,
+                &row.author,
+                &graph_row_idx,
+                &row.summary_markup
+            ]
+        );
+    }
+
+    fn find_commit_row(&self, commit: git2::Oid) -> Option<gtk::TreeIter> {
+        let store = &self.ui.commits_list_store;
+        let commit = commit.to_string();
+        let iter = store.get_iter_first()?;
+        loop {
+            if store.get_value(&iter, 0).get::<String>().as_ref() == Some(&commit) {
+                return Some(iter);
+            }
+            if !store.iter_next(&iter) {
+                return None;
+            }
+        }
+    }
+
+    /// Puts a newly created commit at the top of the commits list and lays out the graph again
+    fn insert_created_commit(&self, created: CreatedCommit) {
+        let store = &self.ui.commits_list_store;
+        let mut nodes = self.ui.commit_nodes.borrow_mut();
+
+        if let Some(iter) = self.find_commit_row(created.previous_head) {
+            // The amended commit stays in the list while a ref or another listed commit still reaches it
+            let reachable = nodes.iter().any(|node| node.parents.contains(&created.previous_head));
+            if created.previous_head_unreferenced && !reachable {
+                store.remove(&iter);
+                nodes.retain(|node| node.id != created.previous_head);
+            } else if let Some(row) = &created.previous_head_row {
+                store.set_value(&iter, 5, &row.summary_markup.to_value());
+            }
+        }
+
+        nodes.insert(0, created.node);
+        self.ui.commits_graph.replace(layout_nodes(&nodes));
+
+        // New commit goes right after the uncommitted changes and stash rows
+        let mut position = 0;
+        while let Some(iter) = store.iter_nth_child(None, position) {
+            let id = store.get_value(&iter, 0).get::<String>().unwrap_or_default();
+            if !is_pseudo_row(&id) {
+                break;
+            }
+            position += 1;
+        }
+        self.insert_commit_row(Some(position as u32), &created.row, 0);
+
+        // Graph rows of all the following commits are shifted
+        let mut graph_row_idx = 0;
+        while let Some(iter) = store.iter_nth_child(None, position) {
+            store.set_value(&iter, 4, &graph_row_idx.to_value());
+            graph_row_idx += 1;
+            position += 1;
+        }
+    }
+
+    fn load_commit_files(main_screen: Rc<Self>) {
+        if !main_screen.ui.commit_panel_toggle_button.get_active() {
+            return;
+        }
+        let amend = main_screen.ui.commit_panel.is_amend();
+
+        gtk_spawn_local(async move {
+            let result = async_std::task::spawn(capture!(backend = main_screen.backend; async move {
+                backend.load_commit_files(amend)
+                    .map_err(|e| format!("Error loading staged files: {}", e))
+            })).await;
+
+            match result {
+                Ok(items) => main_screen.ui.commit_panel.set_files(&items),
+                Err(e) => main_screen.show_error(&e),
+            }
+        });
+    }
+
+    fn load_head_message(main_screen: Rc<Self>) {
+        gtk_spawn_local(async move {
+            let result = async_std::task::spawn(capture!(backend = main_screen.backend; async move {
+                backend.load_head_message()
+                    .map_err(|e| format!("Error loading the last commit: {}", e))
+            })).await;
+
+            match result {
+                Ok(Some(message)) => main_screen.ui.commit_panel.set_message(&message),
+                Ok(None) => {},
+                Err(e) => main_screen.show_error(&e),
+            }
+        });
+    }
+
+    fn create_commit(main_screen: Rc<Self>) {
+        let panel = &main_screen.ui.commit_panel;
+        if panel.summary().trim().is_empty() {
+            main_screen.show_error("Commit summary is empty");
+            return;
+        }
+
+        let request = CommitRequest {
+            message: panel.message(),
+            author: panel.author(),
+            amend: panel.is_amend(),
+            sign_off: panel.is_sign_off(),
+        };
+
+        gtk_spawn_local(async move {
+            let result = async_std::task::spawn(capture!(backend = main_screen.backend; async move {
+                backend.create_commit(&request)
+                    .map_err(|e| format!("Error creating commit: {}", e))
+            })).await;
+
+            match result {
+                // The new commit may not belong to the listed file history, which is loaded again
+                Ok(_) if main_screen.backend.requested_file_history.lock().unwrap().is_some() => {
+                    main_screen.ui.commit_panel.clear();
+                    let filter = main_screen.refs_filter();
+                    Self::load_commits(main_screen, filter);
+                },
+                Ok(created) => {
+                    main_screen.insert_created_commit(created);
+                    main_screen.ui.commit_panel.clear();
+                    Self::refresh_uncommitted_changes(main_screen);
+                },
+                Err(e) => main_screen.show_error(&e),
+            }
+        });
+    }
+
+    fn update_staging_buttons(&self, comparison: Option<Comparison>) {
+        let verb = match comparison {
+            Some(Comparison::Unstaged) => "Stage",
+            Some(Comparison::Staged) => "Unstage",
+            _ => {
+                self.ui.staging_hbox.set_visible(false);
+                return;
+            },
+        };
+
+        self.ui.stage_file_button.set_label(&format!("{} file", verb));
+        self.ui.stage_hunks_button.set_label(&format!("{} hunk", verb));
+        self.ui.stage_lines_button.set_label(&format!("{} lines", verb));
+        // Only changes that are not in the index can be discarded
+        self.ui.discard_file_button.set_visible(comparison == Some(Comparison::Unstaged));
+        self.ui.discard_hunks_button.set_visible(comparison == Some(Comparison::Unstaged));
+        self.ui.staging_hbox.set_visible(true);
+    }
+
+    /// Adds or removes the uncommitted changes rows at the top of the commits list
+    fn update_uncommitted_changes_rows(&self, has_unstaged: bool, has_staged: bool) {
+        let store = &self.ui.commits_list_store;
+        let rows = [
+            (has_unstaged, UNSTAGED_CHANGES_ROW, "Unstaged changes", "#c0392b"),
+            (has_staged, STAGED_CHANGES_ROW, "Staged changes", "#27ae60"),
+        ];
+
+        let mut position = 0;
+        for (wanted, id, title, color) in rows.iter() {
+            let iter = store.iter_nth_child(None, position);
+            let exists = iter.as_ref()
+                .and_then(|iter| store.get_value(iter, 0).get::<String>())
+                .map(|row_id| row_id == *id)
+                .unwrap_or(false);
+
+            match (wanted, iter) {
+                (true, _) if exists => position += 1,
+                (true, _) => {
+                    store.insert_with_values(
+                        Some(position as u32),
+                        &[0, 1, 2, 3, 4, 5],
+                        &[
+                            id,
+                            title,
+                            &"",
+                            &"",
+                            &-1i32,
+                            &format!("<span foreground=\"{}\"><i>{}</i></span>", color, title)
+                        ]
+                    );
+                    position += 1;
+                },
+                (false, Some(iter)) if exists => {
+                    store.remove(&iter);
+                },
+                (false, _) => {},
+            }
+        }
+    }
+
+    /// Replaces the stash rows, which follow the uncommitted changes rows
+    fn update_stash_rows(&self, rows: &[CommitRow]) {
+        let store = &self.ui.commits_list_store;
+
+        let mut position = 0;
+        while let Some(iter) = store.iter_nth_child(None, position) {
+            let id = store.get_value(&iter, 0).get::<String>().unwrap_or_default();
+            if id.starts_with(STASH_ROW_PREFIX) {
+                store.remove(&iter);
+            } else if is_pseudo_row(&id) {
+                position += 1;
+            } else {
+                break;
+            }
+        }
+
+        for row in rows {
+            self.insert_commit_row(Some(position as u32), row, -1);
+            position += 1;
+        }
+    }
+
+    fn refresh_uncommitted_changes(main_screen: Rc<Self>) {
+        gtk_spawn_local(async move {
+            let result = async_std::task::spawn(capture!(backend = main_screen.backend; async move {
+                let repo = backend.repo.lock().unwrap();
+                let status = uncommitted_changes_status(&repo)
+                    .map_err(|e| format!("Error reading working tree status: {}", e))?;
+                let conflicts = backend.stopped_operation_conflicts(&repo)
+                    .map_err(|e| format!("Error reading conflicts: {}", e))?;
+
+                Ok::<_, String>((status, conflicts))
+            })).await;
+
+            match result {
+                Ok(((has_unstaged, has_staged), conflicts)) => {
+                    main_screen.update_uncommitted_changes_rows(has_unstaged, has_staged);
+                    main_screen.update_conflicts_view(conflicts);
+                    Self::load_commit_files(main_screen.clone());
+                    Self::load_selected_comparison(main_screen);
+                },
+                Err(e) => main_screen.show_error(&e),
+            }
+        });
+    }
+
+    /// Stages the selected file, or the hunks or lines selected in the diff view; unstages them when staged changes are shown
+    fn stage_selected(main_screen: Rc<Self>, scope: StagingScope) {
+        let unstage = match main_screen.selected_comparison() {
+            Some(Comparison::Unstaged) => false,
+            Some(Comparison::Staged) => true,
+            _ => return,
+        };
+        let (path, old_path) = match main_screen.selected_diff_item() {
+            Some((_, path, old_path)) => (path, old_path),
+            None => return,
+        };
+
+        let operation = if scope == StagingScope::File {
+            StagingOperation::File { path, old_path, unstage }
+        } else {
+            match main_screen.selected_lines(scope) {
+                Ok(lines) => StagingOperation::Lines { path, lines, unstage },
+                Err(e) => {
+                    main_screen.show_error(&e);
+                    return;
+                },
+            }
+        };
+
+        Self::apply_staging(main_screen, operation);
+    }
+
+    /// Reverts unstaged changes of the selected file or of the hunks selected in the diff view, after a confirmation
+    fn discard_selected(main_screen: Rc<Self>, scope: StagingScope) {
+        if main_screen.selected_comparison() != Some(Comparison::Unstaged) {
+            return;
+        }
+        let (path, old_path) = match main_screen.selected_diff_item() {
+            Some((_, path, old_path)) => (path, old_path),
+            None => return,
+        };
+
+        let (operation, question) = if scope == StagingScope::File {
+            let question = format!("Discard all unstaged changes in {}?", path);
+            (StagingOperation::DiscardFile { path, old_path }, question)
+        } else {
+            match main_screen.selected_lines(scope) {
+                Ok(lines) => {
+                    let question = format!("Discard the selected changes in {}?", path);
+                    (StagingOperation::DiscardLines { path, lines }, question)
+                },
+                Err(e) => {
+                    main_screen.show_error(&e);
+                    return;
+                },
+            }
+        };
+
+        gtk_spawn_local(async move {
+            let confirmed = main_screen.confirm(&question, "The changes will be lost.", "Discard").await;
+            if confirmed {
+                Self::apply_staging(main_screen, operation);
+            }
+        });
+    }
+
+    /// Changed lines of the hunks or the lines selected in the diff view
+    fn selected_lines(&self, scope: StagingScope) -> Result<SelectedLines, String> {
+        let diff = self.ui.diff_view.diff().ok_or_else(|| "No file is selected".to_string())?;
+        if !diff.is_partially_applicable() {
+            return Err("Only whole files can be changed when they are added, deleted, renamed or binary".to_string());
+        }
+
+        let positions = self.ui.diff_view.selected_positions();
+        let lines = match scope {
+            StagingScope::Hunks => SelectedLines::hunks(&diff, |hunk_idx| {
+                positions.iter().any(|position| position.hunk == hunk_idx)
+            }),
+            _ => SelectedLines::lines(&diff, |hunk_idx, line_idx| {
+                positions.contains(&DiffPosition { hunk: hunk_idx, line: Some(line_idx) })
+            }),
+        };
+        if !lines.selects_changes_of(&diff) {
+            return Err("No changed lines are selected".to_string());
+        }
+
+        Ok(lines)
+    }
+
+    fn apply_staging(main_screen: Rc<Self>, operation: StagingOperation) {
+        gtk_spawn_local(async move {
+            let result = async_std::task::spawn(capture!(backend = main_screen.backend; async move {
+                backend.apply_staging(&operation)
+                    .map_err(|e| format!("Error updating uncommitted changes: {}", e))
+            })).await;
+
+            if let Err(e) = result {
+                main_screen.show_error(&e);
+            }
+            Self::refresh_uncommitted_changes(main_screen);
+        });
+    }
+
+    /// Selects the row of a commit and scrolls to it. If the commit is not listed, all refs are shown
+    /// and the commit is selected once they are loaded.
+    fn select_commit(main_screen: Rc<Self>, commit: git2::Oid) {
+        let iter = match main_screen.find_commit_row(commit) {
+            Some(iter) => iter,
+            None => {
+                if main_screen.refs_filter() != RefsFilter::AllRefs {
+                    main_screen.ui.pending_selected_commit.replace(Some(commit));
+                    main_screen.ui.refs_filter_combo_box.set_active_id(Some(RefsFilter::AllRefs.id()));
+                } else if !commit.is_zero() && main_screen.backend.add_extra_tip(commit) {
+                    // No reference leads to the commit, e.g. a commit of the reflog lost by a reset
+                    main_screen.ui.pending_selected_commit.replace(Some(commit));
+                    Self::load_commits(main_screen.clone(), RefsFilter::AllRefs);
+                }
+                return;
+            },
+        };
+
+        let selection = main_screen.ui.commits_tree_view.get_selection();
+        selection.unselect_all();
+        selection.select_iter(&iter);
+        if let Some(path) = main_screen.ui.commits_list_store.get_path(&iter) {
+            main_screen.ui.commits_tree_view.scroll_to_cell(Some(&path), None::<&gtk::TreeViewColumn>, true, 0.5, 0.0);
+        }
+    }
+
+    fn load_reflog(main_screen: Rc<Self>) {
+        let ref_name = main_screen.ui.reflog_view.active_ref();
+
+        gtk_spawn_local(async move {
+            let result = async_std::task::spawn(capture!(backend = main_screen.backend, ref_name; async move {
+                backend.load_reflog(&ref_name)
+            })).await;
+
+            // Another reference may have been chosen while loading
+            if main_screen.ui.reflog_view.active_ref() != ref_name {
+                return;
+            }
+            match result {
+                Ok(entries) => main_screen.ui.reflog_view.set_entries(&entries),
+                Err(e) => {
+                    main_screen.ui.reflog_view.set_entries(&[]);
+                    main_screen.show_error(&format!("Error reading reflog of {}: {}", ref_name, e.message()));
+                },
+            }
+        });
+    }
+
+    /// Blames the selected file at the new side of the comparison; uncommitted changes are blamed at HEAD
+    fn blame_selected_file(main_screen: Rc<Self>) {
+        let (path, comparison) = match (main_screen.selected_diff_item(), main_screen.selected_comparison()) {
+            (Some((_, path, _)), Some(comparison)) => (path, comparison),
+            _ => return,
+        };
+        let commit = match comparison.target() {
+            DiffTarget::Commit(commit) => Some(commit),
+            DiffTarget::Index | DiffTarget::WorkingTree => None,
+        };
+
+        Self::run_blame(main_screen, move |backend| backend.blame_file(commit, &path));
+    }
+
+    fn run_blame<F>(main_screen: Rc<Self>, f: F)
+        where F: FnOnce(&MainScreenBackend) -> Result<FileBlame, git2::Error> + Send + 'static
+    {
+        gtk_spawn_local(async move {
+            let result = async_std::task::spawn(capture!(backend = main_screen.backend; async move {
+                f(&backend)
+            })).await;
+
+            match result {
+                Ok(blame) => main_screen.ui.blame_view.show_blame(&blame),
+                Err(e) => main_screen.show_error(&format!("Error blaming file: {}", e.message())),
+            }
+        });
+    }
+
+    /// Lists only the commits changing the file at `path`, following renames, or all commits again with `None`
+    fn show_file_history(main_screen: Rc<Self>, path: Option<String>) {
+        match &path {
+            Some(path) => {
+                main_screen.ui.file_history_label.set_markup(&format!("History of <b>{}</b>", glib::markup_escape_text(path)));
+                main_screen.ui.file_history_bar.show();
+            },
+            None => main_screen.ui.file_history_bar.hide(),
+        }
+        *main_screen.backend.requested_file_history.lock().unwrap() = path;
+
+        let filter = main_screen.refs_filter();
+        Self::load_commits(main_screen, filter);
+    }
+
+    fn refs_filter(&self) -> RefsFilter {
+        self.ui.refs_filter_combo_box.get_active_id()
+            .and_then(|id| RefsFilter::from_id(&id))
+            .unwrap_or(RefsFilter::Head)
+    }
+
+    /// Selected commits of the commits list from top to bottom, not counting the uncommitted changes rows
+    fn selected_commits(&self) -> Vec<git2::Oid> {
+        let (paths, model) = self.ui.commits_tree_view.get_selection().get_selected_rows();
+        paths.iter()
+            .filter_map(|path| model.get_iter(path))
+            .filter_map(|iter| model.get_value(&iter, 0).get::<String>())
+            .filter_map(|id| git2::Oid::from_str(&id).ok())
+            .collect()
+    }
+
+    fn selected_commit(&self) -> Option<git2::Oid> {
+        self.selected_commits().into_iter().next()
+    }
+
+    /// Stash commit of the selected row, if a single stash row is selected
+    fn selected_stash(&self) -> Option<git2::Oid> {
+        let (paths, model) = self.ui.commits_tree_view.get_selection().get_selected_rows();
+        match paths.as_slice() {
+            [path] => {
+                let id = model.get_value(&model.get_iter(path)?, 0).get::<String>()?;
+                git2::Oid::from_str(id.strip_prefix(STASH_ROW_PREFIX)?).ok()
+            },
+            _ => None,
+        }
+    }
+
+    fn save_stash(main_screen: Rc<Self>) {
+        gtk_spawn_local(async move {
+            let request = match main_screen.ask_stash().await {
+                Some(request) => request,
+                None => return,
+            };
+
+            Self::update_stashes(main_screen, "Error stashing", move |backend| backend.save_stash(&request));
+        });
+    }
+
+    fn apply_selected_stash(main_screen: Rc<Self>, pop: bool) {
+        let stash = match main_screen.selected_stash() {
+            Some(stash) => stash,
+            None => return,
+        };
+
+        gtk_spawn_local(async move {
+            let result = async_std::task::spawn(capture!(backend = main_screen.backend; async move {
+                backend.apply_stash(stash, pop)
+            })).await;
+
+            match result {
+                Ok(StashApplied::Applied) => {},
+                Ok(StashApplied::Conflicts(paths)) => {
+                    main_screen.show_message(
+                        gtk::MessageType::Warning,
+                        &format!("Stash applied with conflicts in {} file(s)", paths.len()),
+                        Some(&format!(
+                            "{}\n\nResolve them in the working tree and stage the files.{}",
+                            paths.join("\n"),
+                            if pop { " The stash entry is kept." } else { "" }
+                        ))
+                    );
+                },
+                Err(e) => main_screen.show_error(&format!("Error applying stash: {}", e.message())),
+            }
+
+            let filter = main_screen.refs_filter();
+            Self::load_commits(main_screen, filter);
+        });
+    }
+
+    fn drop_selected_stash(main_screen: Rc<Self>) {
+        let stash = match main_screen.selected_stash() {
+            Some(stash) => stash,
+            None => return,
+        };
+
+        gtk_spawn_local(async move {
+            let details = "The stashed changes will be lost.";
+            if !main_screen.confirm(&format!("Drop stash entry {:.8}?", stash), details, "Drop").await {
+                return;
+            }
+
+            Self::update_stashes(main_screen, "Error dropping stash", move |backend| backend.drop_stash(stash));
+        });
+    }
+
+    /// Changes the stash in the backend, then reloads the commits list along with the stash and uncommitted changes rows
+    fn update_stashes<F>(main_screen: Rc<Self>, error_message: &'static str, f: F)
+        where F: FnOnce(&MainScreenBackend) -> Result<(), git2::Error> + Send + 'static
+    {
+        gtk_spawn_local(async move {
+            let result = async_std::task::spawn(capture!(backend = main_screen.backend; async move {
+                f(&backend)
+            })).await;
+
+            if let Err(e) = result {
+                main_screen.show_error(&format!("{}: {}", error_message, e.message()));
+            }
+            let filter = main_screen.refs_filter();
+            Self::load_commits(main_screen, filter);
+        });
+    }
+
+    fn pick_selected(main_screen: Rc<Self>, operation: PickOperation) {
+        let commits = main_screen.selected_commits();
+        if commits.is_empty() {
+            return;
+        }
+
+        let error_message = match operation {
+            PickOperation::CherryPick => "Error cherry-picking",
+            PickOperation::Revert => "Error reverting",
+        };
+        Self::run_pick(main_screen, error_message, move |backend| backend.pick_commits(operation, &commits));
+    }
+
+    fn continue_pick(main_screen: Rc<Self>) {
+        Self::run_pick(main_screen, "Error continuing", |backend| backend.continue_pick());
+    }
+
+    fn merge(main_screen: Rc<Self>, entry: RefEntry) {
+        gtk_spawn_local(async move {
+            let details = "The current branch is fast-forwarded if possible; otherwise a merge commit is created.";
+            if !main_screen.confirm(&format!("Merge {} into the current branch?", entry.name), details, "Merge").await {
+                return;
+            }
+
+            Self::run_merge(main_screen, "Error merging", move |backend| backend.merge(&entry.full_name));
+        });
+    }
+
+    /// Merges or concludes a merge in the backend, reports the outcome and reloads the commits list
+    fn run_merge<F>(main_screen: Rc<Self>, error_message: &'static str, f: F)
+        where F: FnOnce(&MainScreenBackend) -> Result<MergeOutcome, git2::Error> + Send + 'static
+    {
+        gtk_spawn_local(async move {
+            let result = async_std::task::spawn(capture!(backend = main_screen.backend; async move {
+                f(&backend)
+            })).await;
+
+            match result {
+                Ok(outcome) => {
+                    let (message_type, message) = match outcome {
+                        MergeOutcome::UpToDate => (gtk::MessageType::Info, "Already up to date".to_owned()),
+                        MergeOutcome::FastForward(oid) => (gtk::MessageType::Info, format!("Fast-forwarded to {:.8}", oid)),
+                        MergeOutcome::Merged(oid) => (gtk::MessageType::Info, format!("Merge commit {:.8} created", oid)),
+                        MergeOutcome::Conflicts(paths) => (
+                            gtk::MessageType::Warning,
+                            format!("Merge stopped with conflicts in {} file(s). Resolve them, then continue the merge.", paths.len())
+                        ),
+                    };
+                    main_screen.show_message(message_type, &message, None);
+                },
+                Err(e) => main_screen.show_error(&format!("{}: {}", error_message, e.message())),
+            }
+
+            let filter = main_screen.refs_filter();
+            Self::load_commits(main_screen, filter);
+        });
+    }
+
+    /// Lets the todo list of an interactive rebase of the current branch onto the selected commit be edited, then starts it
+    fn rebase_onto_selected(main_screen: Rc<Self>) {
+        let onto = match main_screen.selected_commit() {
+            Some(onto) => onto,
+            None => return,
+        };
+
+        gtk_spawn_local(async move {
+            let result = async_std::task::spawn(capture!(backend = main_screen.backend; async move {
+                backend.load_rebase_todo(onto)
+            })).await;
+            let todo = match result {
+                Ok(todo) if todo.is_empty() => {
+                    main_screen.show_error(&format!("Nothing to rebase: all commits of HEAD are reachable from {:.8}", onto));
+                    return;
+                },
+                Ok(todo) => todo,
+                Err(e) => {
+                    main_screen.show_error(&format!("Error listing commits to rebase: {}", e.message()));
+                    return;
+                },
+            };
+
+            let editor = RebaseEditor::new(&main_screen.ui.window, &format!("Rebase onto {:.8}", onto), &todo);
+            let todo = match editor.run().await {
+                Some(todo) => todo,
+                None => return,
+            };
+
+            Self::run_rebase(main_screen, "Error rebasing", move |backend| backend.start_rebase(onto, todo));
+        });
+    }
+
+    /// Starts or continues a rebase in the backend, reports where it stopped and reloads the commits list
+    fn run_rebase<F>(main_screen: Rc<Self>, error_message: &'static str, f: F)
+        where F: FnOnce(&MainScreenBackend) -> Result<RebaseProgress, git2::Error> + Send + 'static
+    {
+        gtk_spawn_local(async move {
+            let result = async_std::task::spawn(capture!(backend = main_screen.backend; async move {
+                f(&backend)
+            })).await;
+
+            match result {
+                Ok(progress) => {
+                    let (message_type, message) = match progress {
+                        RebaseProgress::Finished(oid) => (gtk::MessageType::Info, format!("Rebase finished at {:.8}", oid)),
+                        RebaseProgress::Conflicts(paths) => (
+                            gtk::MessageType::Warning,
+                            format!("Rebase stopped with conflicts in {} file(s). Resolve them, then continue the rebase.", paths.len())
+                        ),
+                        RebaseProgress::Edit(oid) => (
+                            gtk::MessageType::Info,
+                            format!("Rebase stopped at {:.8}. Amend the commit if needed, then continue the rebase.", oid)
+                        ),
+                    };
+                    main_screen.show_message(message_type, &message, None);
+                },
+                Err(e) => main_screen.show_error(&format!("{}: {}", error_message, e.message())),
+            }
+
+            let filter = main_screen.refs_filter();
+            Self::load_commits(main_screen, filter);
+        });
+    }
+
+    fn load_conflict_sides(main_screen: Rc<Self>, path: String) {
+        gtk_spawn_local(async move {
+            let result = async_std::task::spawn(capture!(backend = main_screen.backend, path; async move {
+                crate::conflicts::load_conflict_sides(&backend.repo.lock().unwrap(), &path)
+            })).await;
+
+            // Another file may have been selected while loading
+            if main_screen.ui.conflicts_view.selected_path().as_ref() != Some(&path) {
+                return;
+            }
+            match result {
+                Ok(sides) => main_screen.ui.conflicts_view.show_sides(&sides),
+                Err(e) => {
+                    main_screen.ui.conflicts_view.show_sides(&ConflictSides::default());
+                    main_screen.show_error(&format!("Error loading conflict: {}", e.message()));
+                },
+            }
+        });
+    }
+
+    fn resolve_conflict(main_screen: Rc<Self>, path: String, resolution: ConflictResolution) {
+        gtk_spawn_local(async move {
+            if resolution != ConflictResolution::MarkResolved {
+                let details = "Changes made to the file in the working tree will be lost.";
+                if !main_screen.confirm(&format!("Replace {}?", path), details, "Replace").await {
+                    return;
+                }
+            }
+
+            let result = async_std::task::spawn(capture!(backend = main_screen.backend; async move {
+                crate::conflicts::resolve_conflict(&backend.repo.lock().unwrap(), &path, resolution)
+            })).await;
+            if let Err(e) = result {
+                main_screen.show_error(&format!("Error resolving conflict: {}", e.message()));
+            }
+
+            Self::refresh_uncommitted_changes(main_screen);
+        });
+    }
 
-pub struct MainScreenUi {
-    window: gtk::Window,
+    fn abort_operation(main_screen: Rc<Self>) {
+        gtk_spawn_local(async move {
+            let details = "Changes made by the operation, including conflict resolutions, will be lost.";
+            if !main_screen.confirm("Abort the operation in progress?", details, "Abort").await {
+                return;
+            }
 
-    commits_list_store: gtk::ListStore,
-    commits_tree_view: gtk::TreeView,
+            let result = async_std::task::spawn(capture!(backend = main_screen.backend; async move {
+                backend.abort_operation()
+            })).await;
+            if let Err(e) = result {
+                main_screen.show_error(&format!("Error aborting: {}", e.message()));
+            }
 
-    diff_items_list_store: gtk::ListStore,
+            let filter = main_screen.refs_filter();
+            Self::load_commits(main_screen, filter);
+        });
+    }
 
-    commit_info_view: gtk::TextView,
-}
+    /// Cherry-picks or reverts commits in the backend, reports the outcome of each commit
+    /// and reloads the commits list
+    fn run_pick<F>(main_screen: Rc<Self>, error_message: &'static str, f: F)
+        where F: FnOnce(&MainScreenBackend) -> Result<PickReport, git2::Error> + Send + 'static
+    {
+        gtk_spawn_local(async move {
+            let result = async_std::task::spawn(capture!(backend = main_screen.backend; async move {
+                f(&backend)
+            })).await;
 
-pub struct MainScreenBackend {
-    repo: Mutex<git2::Repository>,
-    requested_commit: Mutex<Option<git2::Oid>>,
-}
+            match result {
+                Ok(report) => main_screen.show_pick_report(&report),
+                Err(e) => main_screen.show_error(&format!("{}: {}", error_message, e.message())),
+            }
 
-pub struct MainScreen {
-    backend: Arc<MainScreenBackend>,
-    ui: MainScreenUi,
-}
+            let filter = main_screen.refs_filter();
+            Self::load_commits(main_screen, filter);
+        });
+    }
 
-impl MainScreen {
-    pub async fn create(repo_path: PathBuf) -> std::result::Result<Rc<MainScreen>, String> {
-        let window = gtk::Window::new(gtk::WindowType::Toplevel);
-        window.set_title(&repo_path.to_string_lossy());
+    fn show_pick_report(&self, report: &PickReport) {
+        let mut details = String::new();
+        for step in report.steps.iter() {
+            let outcome = match &step.result {
+                PickResult::Committed(oid) => format!("committed as {:.8}", oid),
+                PickResult::Empty => "no changes, skipped".to_owned(),
+                PickResult::Conflicts(paths) => format!("stopped with conflicts in {} file(s)", paths.len()),
+            };
+            details.push_str(&format!("{:.8} {}: {}\n", step.commit, step.summary, outcome));
+        }
+        if report.remaining > 0 {
+            details.push_str(&format!("\n{} more commit(s) will be applied after the conflicts are resolved.", report.remaining));
+        }
 
-        let commits_list_store = gtk::ListStore::new(&[
-            gtk::Type::String, // Commit ID as string
-            gtk::Type::String, // Commit message
-            gtk::Type::String, // Commit date as string
-            gtk::Type::String // Commit author email
-        ]);
+        let operation = report.operation;
+        let has_conflicts = report.steps.iter().any(|step| matches!(step.result, PickResult::Conflicts(_)));
+        let (message_type, message) = if has_conflicts {
+            (gtk::MessageType::Warning, format!("{} stopped with conflicts", operation.title()))
+        } else {
+            (gtk::MessageType::Info, format!("{} finished", operation.title()))
+        };
 
-        let diff_items_list_store = gtk::ListStore::new(&[
-            gtk::Type::String, // Parent OID; empty if this a "grouping" item
-            gtk::Type::String, // File path
-            gtk::Type::U8, // Change type, 0: none, 1: delete, 2: add, 3: rename, 0: update
-            gtk::Type::String, // Renamed from path; empty if not renamed
-        ]);
+        self.show_message(message_type, &message, Some(details.trim_end()));
+    }
 
-        let vpane = gtk::Paned::new(gtk::Orientation::Vertical);
+    /// Shows the conflicts of a merge, cherry-pick, revert or rebase in progress, hides the conflicts view if there is none
+    fn update_conflicts_view(&self, conflicts: Option<(StoppedOperation, Vec<String>)>) {
+        self.ui.stopped_operation.set(conflicts.as_ref().map(|(operation, _)| *operation));
+        match conflicts {
+            Some((operation, paths)) => {
+                self.ui.conflicts_view.set_conflicts(&format!("{} in progress", operation.title()), &paths);
+                self.ui.conflicts_view.widget().show();
+            },
+            None => self.ui.conflicts_view.widget().hide(),
+        }
+    }
 
-        window.add(&vpane);
+    fn create_branch(main_screen: Rc<Self>) {
+        let target = match main_screen.selected_commit() {
+            Some(target) => target,
+            None => {
+                main_screen.show_error("Select a commit to create the branch at");
+                return;
+            },
+        };
 
-        let commits_tree_view = gtk::TreeView::new();
+        gtk_spawn_local(async move {
+            let name = match main_screen.prompt("New branch", &format!("Create a branch at {:.8}:", target), "").await {
+                Some(name) => name,
+                None => return,
+            };
+            Self::update_refs(main_screen, "Error creating branch", move |repo| {
+                crate::refs::create_branch(repo, &name, target)
+            });
+        });
+    }
 
-        let scrolled_window = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+    fn rename_branch(main_screen: Rc<Self>, entry: RefEntry) {
+        gtk_spawn_local(async move {
+            let new_name = match main_screen.prompt("Rename branch", &format!("New name of branch {}:", entry.name), &entry.name).await {
+                Some(new_name) if new_name != entry.name => new_name,
+                _ => return,
+            };
+            Self::update_refs(main_screen, "Error renaming branch", move |repo| {
+                crate::refs::rename_branch(repo, &entry.name, &new_name)
+            });
+        });
+    }
 
-        scrolled_window.add(&commits_tree_view);
+    fn delete_branch(main_screen: Rc<Self>, entry: RefEntry) {
+        if entry.kind != RefKind::LocalBranch {
+            return;
+        }
 
-        commits_tree_view.set_model(Some(&commits_list_store));
+        gtk_spawn_local(async move {
+            let is_merged = async_std::task::spawn(capture!(backend = main_screen.backend, name = entry.name; async move {
+                crate::refs::is_branch_merged(&backend.repo.lock().unwrap(), &name)
+            })).await;
 
-        {
-            let cell_renderer = gtk::CellRendererText::new();
-            cell_renderer.set_property_ellipsize(pango::EllipsizeMode::End);
-            let column = gtk::TreeViewColumn::new();
-            column.set_title("Summary");
-            column.set_resizable(true);
-            column.set_expand(true);
-            column.pack_start(&cell_renderer, true);
-            column.add_attribute(&cell_renderer, "text", 1);
+            let (question, details) = match is_merged {
+                Ok(true) => (format!("Delete branch {}?", entry.name), String::new()),
+                Ok(false) => (
+                    format!("Branch {} is not fully merged", entry.name),
+                    format!("Commits of the branch are not reachable from HEAD or its upstream branch and may be lost. Its tip is {}.", entry.target),
+                ),
+                Err(e) => {
+                    main_screen.show_error(&format!("Error reading branch: {}", e));
+                    return;
+                },
+            };
+            if !main_screen.confirm(&question, &details, "Delete").await {
+                return;
+            }
 
-            commits_tree_view.append_column(&column);
-        }
+            Self::update_refs(main_screen, "Error deleting branch", move |repo| {
+                crate::refs::delete_branch(repo, &entry.name)
+            });
+        });
+    }
 
-        {
-            let cell_renderer = gtk::CellRendererText::new();
-            let column = gtk::TreeViewColumn::new();
-            column.set_title("Time");
-            column.set_resizable(true);
-            column.set_expand(false);
-            column.pack_start(&cell_renderer, true);
-            column.add_attribute(&cell_renderer, "text", 2);
+    fn create_tag(main_screen: Rc<Self>) {
+        let target = match main_screen.selected_commit() {
+            Some(target) => target,
+            None => return,
+        };
 
-            commits_tree_view.append_column(&column);
-        }
+        gtk_spawn_local(async move {
+            let request = match main_screen.ask_tag(target).await {
+                Some(request) => request,
+                None => return,
+            };
+            Self::update_tags(main_screen, "Error creating tag", move |repo| {
+                crate::refs::create_tag(repo, &request.name, target, request.message.as_deref())
+            });
+        });
+    }
 
-        {
-            let cell_renderer = gtk::CellRendererText::new();
-            cell_renderer.set_property_ellipsize(pango::EllipsizeMode::End);
-            let column = gtk::TreeViewColumn::new();
-            column.set_title("Author");
-            column.set_resizable(true);
-            column.set_expand(true);
-            column.pack_start(&cell_renderer, true);
-            column.add_attribute(&cell_renderer, "text", 3);
+    fn delete_tag(main_screen: Rc<Self>, entry: RefEntry) {
+        gtk_spawn_local(async move {
+            if !main_screen.confirm(&format!("Delete tag {}?", entry.name), "", "Delete").await {
+                return;
+            }
+            Self::update_tags(main_screen, "Error deleting tag", move |repo| {
+                crate::refs::delete_tag(repo, &entry.name)
+            });
+        });
+    }
 
-            commits_tree_view.append_column(&column);
+    fn move_tag(main_screen: Rc<Self>, entry: RefEntry) {
+        let target = match main_screen.selected_commit() {
+            Some(target) if target != entry.target => target,
+            Some(_) => {
+                let message = format!("Tag {} already points to the selected commit", entry.name);
+                main_screen.show_message(gtk::MessageType::Info, &message, None);
+                return;
+            },
+            None => {
+                main_screen.show_error("Select a commit to move the tag to");
+                return;
+            },
+        };
+
+        gtk_spawn_local(async move {
+            let details = format!("The tag will point to {} instead of {}.", target, entry.target);
+            if !main_screen.confirm(&format!("Move tag {}?", entry.name), &details, "Move").await {
+                return;
+            }
+            Self::update_tags(main_screen, "Error moving tag", move |repo| {
+                crate::refs::move_tag(repo, &entry.name, target)
+            });
+        });
+    }
+
+    /// Changes tags in the backend, then updates the ref labels of the listed commits and the refs sidebar
+    /// without reloading the commits list
+    fn update_tags<F>(main_screen: Rc<Self>, error_message: &'static str, f: F)
+        where F: FnOnce(&git2::Repository) -> Result<(), git2::Error> + Send + 'static
+    {
+        gtk_spawn_local(async move {
+            let result = async_std::task::spawn(capture!(backend = main_screen.backend; async move {
+                let repo = backend.repo.lock().unwrap();
+                f(&repo).map_err(|e| format!("{}: {}", error_message, e.message()))?;
+
+                let ref_labels = crate::ref_labels::collect_ref_labels(&repo)
+                    .map_err(|e| format!("Error reading references: {}", e))?;
+                let refs = crate::refs::collect_refs(&repo)
+                    .map_err(|e| format!("Error reading references: {}", e))?;
+
+                Ok::<_, String>((ref_labels, refs))
+            })).await;
+
+            match result {
+                Ok((ref_labels, refs)) => {
+                    main_screen.update_ref_labels(&ref_labels);
+                    main_screen.ui.refs_sidebar.set_refs(refs);
+                },
+                Err(e) => main_screen.show_error(&e),
+            }
+        });
+    }
+
+    fn update_ref_labels(&self, ref_labels: &HashMap<git2::Oid, Vec<RefLabel>>) {
+        let store = &self.ui.commits_list_store;
+        let iter = match store.get_iter_first() {
+            Some(iter) => iter,
+            None => return,
+        };
+        loop {
+            let commit = store.get_value(&iter, 0).get::<String>()
+                .and_then(|id| git2::Oid::from_str(&id).ok());
+            if let Some(commit) = commit {
+                let summary = store.get_value(&iter, 1).get::<String>().unwrap_or_default();
+                let labels = ref_labels.get(&commit).map(|labels| labels.as_slice()).unwrap_or(&[]);
+                store.set_value(&iter, 5, &summary_markup(&summary, labels).to_value());
+            }
+            if !store.iter_next(&iter) {
+                return;
+            }
         }
+    }
 
-        vpane.pack1(&scrolled_window, true, false);
+    /// Changes references in the backend, then reloads the commits list and the refs sidebar
+    fn update_refs<F>(main_screen: Rc<Self>, error_message: &'static str, f: F)
+        where F: FnOnce(&git2::Repository) -> Result<(), git2::Error> + Send + 'static
+    {
+        gtk_spawn_local(async move {
+            let result = async_std::task::spawn(capture!(backend = main_screen.backend; async move {
+                f(&backend.repo.lock().unwrap())
+            })).await;
 
-        let commit_info_view = gtk::TextView::new();
-        commit_info_view.set_editable(false);
+            if let Err(e) = result {
+                main_screen.show_error(&format!("{}: {}", error_message, e.message()));
+            }
+            let filter = main_screen.refs_filter();
+            Self::load_commits(main_screen, filter);
+        });
+    }
 
-        let scrolled_window_2 = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+    /// Asks the user for the name and the kind of a new tag; resolves to `None` if cancelled
+    fn ask_tag(&self, target: git2::Oid) -> Promise<Option<TagRequest>> {
+        let (promise, resolver) = Promise::new();
 
-        vpane.pack2(&scrolled_window_2, true, false);
+        let dialog = gtk::Dialog::new_with_buttons(
+            Some("Create tag"),
+            Some(&self.ui.window),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            &[("Cancel", gtk::ResponseType::Cancel), ("Create", gtk::ResponseType::Accept)]
+        );
+        dialog.set_default_response(gtk::ResponseType::Accept);
+
+        let name_entry = gtk::Entry::new();
+        name_entry.set_placeholder_text(Some("Name"));
+        name_entry.set_activates_default(true);
+        let annotated_check_button = gtk::CheckButton::new_with_label("Annotated tag with message:");
+        annotated_check_button.set_active(true);
+        let message_text_view = gtk::TextView::new();
+        let message_scrolled_window = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        message_scrolled_window.set_size_request(400, 120);
+        message_scrolled_window.set_shadow_type(gtk::ShadowType::In);
+        message_scrolled_window.add(&message_text_view);
+        annotated_check_button.connect_toggled(capture!(message_text_view; move |check_button| {
+            message_text_view.set_sensitive(check_button.get_active());
+        }));
 
-        let commit_info_viewport = gtk::Viewport::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        let content_area = dialog.get_content_area();
+        content_area.set_spacing(4);
+        content_area.set_border_width(8);
+        let label = gtk::Label::new(Some(&format!("Create a tag at {:.8}:", target)));
+        label.set_halign(gtk::Align::Start);
+        content_area.pack_start(&label, false, false, 0);
+        content_area.pack_start(&name_entry, false, false, 0);
+        content_area.pack_start(&annotated_check_button, false, false, 0);
+        content_area.pack_start(&message_scrolled_window, true, true, 0);
+
+        fn entered_message(text_view: &gtk::TextView) -> String {
+            let buffer = text_view.get_buffer().unwrap();
+            buffer.get_text(&buffer.get_start_iter(), &buffer.get_end_iter(), false)
+                .map(|text| text.trim().to_owned())
+                .unwrap_or_default()
+        }
 
-        scrolled_window_2.add(&commit_info_viewport);
+        // A tag needs a name, and an annotated tag needs a message too
+        let update_create_button = Rc::new(capture!(dialog, name_entry, annotated_check_button, message_text_view; move || {
+            let has_name = name_entry.get_text().map(|text| !text.trim().is_empty()).unwrap_or(false);
+            let has_message = !annotated_check_button.get_active() || !entered_message(&message_text_view).is_empty();
+            dialog.set_response_sensitive(gtk::ResponseType::Accept, has_name && has_message);
+        }));
+        name_entry.connect_changed(capture!(update_create_button; move |_| update_create_button()));
+        annotated_check_button.connect_toggled(capture!(update_create_button; move |_| update_create_button()));
+        message_text_view.get_buffer().unwrap().connect_changed(capture!(update_create_button; move |_| update_create_button()));
+        update_create_button();
+
+        dialog.connect_response(capture!(resolver, dialog; move |_, response| {
+            let name = name_entry.get_text().map(|text| text.trim().to_owned()).unwrap_or_default();
+            let message = if annotated_check_button.get_active() {
+                Some(format!("{}\n", entered_message(&message_text_view)))
+            } else {
+                None
+            };
+            dialog.destroy();
+            resolver.resolve(if response == gtk::ResponseType::Accept && !name.is_empty() {
+                Some(TagRequest { name, message })
+            } else {
+                None
+            });
+        }));
 
-        let commit_info_vbox = gtk::Box::new(gtk::Orientation::Vertical, 0);
-        commit_info_vbox.pack_start(&commit_info_view, false, false, 0);
-        commit_info_viewport.add(&commit_info_vbox);
+        dialog.show_all();
 
-        let diff_items_tree_view = gtk::TreeView::new();
-        commit_info_vbox.add(&diff_items_tree_view);
+        promise
+    }
 
-        diff_items_tree_view.set_model(Some(&diff_items_list_store));
+    /// Asks the user for the message and the options of a new stash entry; resolves to `None` if cancelled
+    fn ask_stash(&self) -> Promise<Option<StashRequest>> {
+        let (promise, resolver) = Promise::new();
 
-        {
-            let column = gtk::TreeViewColumn::new();
-            column.set_title("Change");
-            column.set_resizable(true);
-            column.set_expand(true);
+        let dialog = gtk::Dialog::new_with_buttons(
+            Some("Stash changes"),
+            Some(&self.ui.window),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            &[("Cancel", gtk::ResponseType::Cancel), ("Stash", gtk::ResponseType::Accept)]
+        );
+        dialog.set_default_response(gtk::ResponseType::Accept);
+
+        let message_entry = gtk::Entry::new();
+        message_entry.set_placeholder_text(Some("Message (optional)"));
+        message_entry.set_activates_default(true);
+        let include_untracked_check_button = gtk::CheckButton::new_with_label("Include untracked files");
+        let keep_index_check_button = gtk::CheckButton::new_with_label("Keep staged changes in the index");
+
+        let content_area = dialog.get_content_area();
+        content_area.set_spacing(4);
+        content_area.set_border_width(8);
+        content_area.pack_start(&message_entry, false, false, 0);
+        content_area.pack_start(&include_untracked_check_button, false, false, 0);
+        content_area.pack_start(&keep_index_check_button, false, false, 0);
+
+        dialog.connect_response(capture!(resolver, dialog; move |_, response| {
+            let message = message_entry.get_text().map(|text| text.trim().to_owned()).unwrap_or_default();
+            let request = StashRequest {
+                message: if message.is_empty() { None } else { Some(message) },
+                options: StashOptions {
+                    include_untracked: include_untracked_check_button.get_active(),
+                    keep_index: keep_index_check_button.get_active(),
+                },
+            };
+            dialog.destroy();
+            resolver.resolve(if response == gtk::ResponseType::Accept { Some(request) } else { None });
+        }));
 
-            let cell_renderer_change_type = gtk::CellRendererText::new();
-            cell_renderer_change_type.set_property_ellipsize(pango::EllipsizeMode::End);
-            column.pack_start(&cell_renderer_change_type, false);
-            column.add_attribute(&cell_renderer_change_type, "text", 2); /* 2: change type */
-            
-            let cell_renderer_path = gtk::CellRendererText::new();
-            cell_renderer_path.set_property_ellipsize(pango::EllipsizeMode::End);
-            column.pack_start(&cell_renderer_path, true);
-            column.add_attribute(&cell_renderer_path, "text", 1); /* 1: file path */
+        dialog.show_all();
 
-            diff_items_tree_view.append_column(&column);
-        }
+        promise
+    }
 
-        let repo = async_std::task::spawn(async move {
-            use git2::Repository;
-            let repo = Repository::discover(repo_path).map_err(|e| format!("Error opening repository: {}", e))?;
+    /// Asks the user where to save a file, proposing `name`; resolves to `None` if cancelled
+    fn ask_save_path(&self, name: &str) -> Promise<Option<PathBuf>> {
+        let (promise, resolver) = Promise::new();
 
-            Ok::<_, String>(repo)
-        }).await?;
+        let dialog = gtk::FileChooserDialog::with_buttons(
+            Some("Save blob as"),
+            Some(&self.ui.window),
+            gtk::FileChooserAction::Save,
+            &[("Cancel", gtk::ResponseType::Cancel), ("Save", gtk::ResponseType::Accept)]
+        );
+        dialog.set_current_name(name);
+        dialog.set_do_overwrite_confirmation(true);
+        dialog.set_default_response(gtk::ResponseType::Accept);
+
+        dialog.connect_response(capture!(resolver; move |dialog, response| {
+            let file_path = if response == gtk::ResponseType::Accept { dialog.get_filename() } else { None };
+            dialog.destroy();
+            resolver.resolve(file_path);
+        }));
 
-        let main_screen = MainScreen {
-            backend: Arc::new(MainScreenBackend {
-                repo: Mutex::new(repo),
-                requested_commit: Mutex::new(None),
-            }),
-            ui: MainScreenUi {
-                window,
-                commits_list_store,
-                commits_tree_view,
-                commit_info_view,
-                diff_items_list_store,
-            },
-        };
+        dialog.show_all();
 
-        let main_screen = Rc::new(main_screen);
+        promise
+    }
 
-        Self::subscribe(main_screen.clone());
-        Ok(main_screen)
+    /// Asks the user for a single line of text; resolves to `None` if cancelled or left empty
+    fn prompt(&self, title: &str, label: &str, initial: &str) -> Promise<Option<String>> {
+        let (promise, resolver) = Promise::new();
+
+        let dialog = gtk::Dialog::new_with_buttons(
+            Some(title),
+            Some(&self.ui.window),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            &[("Cancel", gtk::ResponseType::Cancel), ("OK", gtk::ResponseType::Accept)]
+        );
+        dialog.set_default_response(gtk::ResponseType::Accept);
+
+        let entry = gtk::Entry::new();
+        entry.set_text(initial);
+        entry.set_activates_default(true);
+
+        let content_area = dialog.get_content_area();
+        content_area.set_spacing(4);
+        content_area.set_border_width(8);
+        let label = gtk::Label::new(Some(label));
+        label.set_halign(gtk::Align::Start);
+        content_area.pack_start(&label, false, false, 0);
+        content_area.pack_start(&entry, false, false, 0);
+
+        dialog.connect_response(capture!(resolver, dialog, entry; move |_, response| {
+            let text = entry.get_text().map(|text| text.trim().to_owned()).unwrap_or_default();
+            dialog.destroy();
+            resolver.resolve(if response == gtk::ResponseType::Accept && !text.is_empty() { Some(text) } else { None });
+        }));
+
+        dialog.show_all();
+
+        promise
     }
 
-    fn subscribe(main_screen: Rc<Self>) {
-        main_screen.ui.commits_tree_view.get_selection().connect_changed(capture!(main_screen; move |selection| {
-            let msg = match selection.get_selected() {
-                None => "".to_owned(),
-                Some((model, iter)) => {
-                    let oid_str = model.get_value(&iter, 0).get::<String>().unwrap();
-                    let oid = git2::Oid::from_str(&oid_str).unwrap();
-                    main_screen.backend.requested_commit.lock().unwrap().replace(oid);
-                    gtk_spawn_local(capture!(main_screen; async move {
-                        let result = async_std::task::spawn(capture!(backend = main_screen.backend; async move {
-                            if Some(oid) != *backend.requested_commit.lock().unwrap() {
-                                return Ok(None);
-                            }
+    /// Asks the user to confirm a destructive action; resolves to `true` if it is confirmed
+    fn confirm(&self, question: &str, details: &str, action: &str) -> Promise<bool> {
+        let (promise, resolver) = Promise::new();
 
-                            let repo = backend.repo.lock().unwrap();
-                            let commit = repo.find_commit(oid).or_else(|e| Err(format!("{}", e)))?;
+        let dialog = gtk::MessageDialog::new(Some(&self.ui.window),
+            gtk::DialogFlags::MODAL,
+            gtk::MessageType::Warning,
+            gtk::ButtonsType::None,
+            question
+        );
+        dialog.set_property_secondary_text(Some(details));
+        dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+        dialog.add_button(action, gtk::ResponseType::Accept);
+        dialog.set_default_response(gtk::ResponseType::Cancel);
+        dialog.connect_response(capture!(resolver, dialog; move |_, response| {
+            dialog.destroy();
+            resolver.resolve(response == gtk::ResponseType::Accept);
+        }));
 
-                            let message = String::from_utf8_lossy(
-                                commit.message_bytes()
-                            ).to_string();
+        dialog.show();
 
-                            let parents_count = commit.parent_count();
+        promise
+    }
 
-                            let mut commit_summary = String::new();
+    /// Parent, path and old path of the selected diff item; `None` for grouping items
+    fn selected_diff_item(&self) -> Option<(String, String, String)> {
+        let (model, iter) = self.ui.diff_items_tree_view.get_selection().get_selected()?;
+        let parent = model.get_value(&iter, 0).get::<String>().unwrap_or_default();
+        if parent.is_empty() {
+            return None;
+        }
 
-                            use std::fmt::Write;
-                            use chrono::TimeZone;
+        Some((
+            parent,
+            model.get_value(&iter, 1).get::<String>().unwrap_or_default(),
+            model.get_value(&iter, 3).get::<String>().unwrap_or_default(),
+        ))
+    }
 
-                            write!(&mut commit_summary, "Commit {}\n", commit.id()).unwrap();
-                            for parent in commit.parents() {
-                                write!(&mut commit_summary, "Parent {}\n", parent.id()).unwrap();
-                            }
-                            let author_timestamp =
-                                chrono::Utc.timestamp(commit.author().when().seconds(), 0)
-                                .with_timezone(&chrono::FixedOffset::east(commit.author().when().offset_minutes() * 60));
-                            let author_name = String::from_utf8_lossy(commit.author().name_bytes()).to_string();
-                            let author_email = String::from_utf8_lossy(commit.author().email_bytes()).to_string();
-                            let committer_timestamp =
-                                chrono::Utc.timestamp(commit.committer().when().seconds(), 0)
-                                .with_timezone(&chrono::FixedOffset::east(commit.committer().when().offset_minutes() * 60));
-                            let committer_name = String::from_utf8_lossy(commit.committer().name_bytes()).to_string();
-                            let committer_email = String::from_utf8_lossy(commit.committer().email_bytes()).to_string();
-
-                            write!(
-                                &mut commit_summary,
-                                "Timestamp {}\nAuthor {} <{}>\n",
-                                author_timestamp.format("%Y-%m-%d %H:%M:%S %:z"),
-                                author_name,
-                                author_email
-                            ).unwrap();
-
-                            if author_timestamp != committer_timestamp || author_name != committer_name || author_email != committer_email {
-                                write!(
-                                    &mut commit_summary,
-                                    "Commit timestamp {}\nCommitter {} <{}>\n",
-                                    committer_timestamp.format("%Y-%m-%d %H:%M:%S %:z"),
-                                    committer_name,
-                                    committer_email
-                                ).unwrap();
-                            }
+    fn show_error(&self, message: &str) {
+        self.show_message(gtk::MessageType::Error, message, None);
+    }
 
-                            write!(&mut commit_summary, "\n{}", message).unwrap();
-
-                            let mut changes = Vec::new();
-
-                            if parents_count > 0 {
-                                for parent in commit.parents() {
-
-                                    if parents_count > 1 {
-                                        changes.push((
-                                            "".to_string(),
-                                            format!("Changes from {}", parent.id()),
-                                            0i32,
-                                            "".to_string()
-                                        ));
-                                    }
-
-                                    let diff = repo.diff_tree_to_tree(
-                                        Some(&parent.tree().or_else(|e| Err(format!("{}", e)))?),
-                                        Some(&commit.tree().or_else(|e| Err(format!("{}", e)))?),
-                                        None
-                                    ).or_else(|e| Err(format!("{}", e)))?;
-
-                                    for delta in diff.deltas() {
-                                        let path = String::from_utf8_lossy(
-                                                match (delta.new_file().path_bytes(), delta.old_file().path_bytes()) {
-                                                    (Some(bytes), _) => bytes,
-                                                    (None, Some(bytes)) => bytes,
-                                                    (None, None) => b"(none)",
-                                                }
-                                            ).to_string();
-                                        changes.push((
-                                            "".to_string(), // TODO
-                                            path,
-                                            0i32, // TODO
-                                            "".to_string() // TODO
-                                        ));
-                                    }
-                                }
-                            } else {
-                                let diff = repo.diff_tree_to_tree(
-                                    None,
-                                    Some(&commit.tree().or_else(|e| Err(format!("{}", e)))?),
-                                    None
-                                ).or_else(|e| Err(format!("{}", e)))?;
-
-                                for delta in diff.deltas() {
-                                    let path = String::from_utf8_lossy(
-                                            match (delta.new_file().path_bytes(), delta.old_file().path_bytes()) {
-                                                (Some(bytes), _) => bytes,
-                                                (None, Some(bytes)) => bytes,
-                                                (None, None) => b"(none)",
-                                            }
-                                        ).to_string();
-                                    changes.push((
-                                        "".to_string(), // TODO
-                                        path,
-                                        0i32, // TODO
-                                        "".to_string() // TODO
-                                    ));
-                                }
-                            }
+    /// Shows a modal message box with `details` below the message, if any
+    fn show_message(&self, message_type: gtk::MessageType, message: &str, details: Option<&str>) {
+        let dialog = gtk::MessageDialog::new(Some(&self.ui.window),
+            gtk::DialogFlags::MODAL,
+            message_type,
+            gtk::ButtonsType::Ok,
+            message
+        );
+        if details.is_some() {
+            dialog.set_property_secondary_text(details);
+        }
+        dialog.run();
+        dialog.destroy();
+    }
 
-                            Result::<_, String>::Ok(Some((commit_summary, changes)))
-                        })).await;
-
-                        match result {
-                            Ok(Some((summary_text, changes))) => {
-                                main_screen.ui.commit_info_view.get_buffer().unwrap().set_text(&summary_text);
-                                main_screen.ui.diff_items_list_store.clear();
-                                for (commit_id, path, change_type, old_path) in changes {
-                                    main_screen.ui.diff_items_list_store.insert_with_values(
-                                        None,
-                                        &[0, 1, 2, 3],
-                                        &[
-                                            &commit_id,
-                                            &path,
-                                            &change_type,
-                                            &old_path
-                                        ]
-                                    );
-                                }
-                            },
-                            Ok(None) => {
-                                // Do nothing
-                            },
-                            Err(e) => {
-                                main_screen.ui.commit_info_view.get_buffer().unwrap().set_text(&e);
-                            }
-                        }
-                    }));
-                    
-                    "Loading".to_owned()
-                },
+    fn load_selected_file_diff(main_screen: Rc<Self>) {
+        let request = main_screen.ui.diff_items_tree_view.get_selection().get_selected().and_then(|(model, iter)| {
+            let parent_str = model.get_value(&iter, 0).get::<String>().unwrap_or_default();
+            let base = if parent_str == COMBINED_DIFF_PARENT {
+                DiffBase::Combined { dense: main_screen.ui.dense_combined_diff_check_button.get_active() }
+            } else if parent_str == INDEX_DIFF_PARENT {
+                DiffBase::Index
+            } else {
+                DiffBase::Commit(git2::Oid::from_str(&parent_str).ok()?)
             };
+            let target = main_screen.selected_comparison()?.target();
+            let path = model.get_value(&iter, 1).get::<String>().unwrap_or_default();
+            let old_path = model.get_value(&iter, 3).get::<String>().unwrap_or_default();
+
+            Some(FileDiffRequest { target, base, path, old_path })
+        });
+
+        main_screen.backend.requested_file_diff.lock().unwrap().clone_from(&request);
+
+        let request = match request {
+            Some(request) => request,
+            None => {
+                main_screen.ui.diff_view.show_message("");
+                return;
+            }
+        };
 
-            main_screen.ui.commit_info_view.get_buffer().unwrap().set_text(&msg);
+        main_screen.ui.diff_view.show_message("Loading");
+
+        gtk_spawn_local(capture!(main_screen; async move {
+            let result = async_std::task::spawn(capture!(backend = main_screen.backend; async move {
+                if Some(&request) != backend.requested_file_diff.lock().unwrap().as_ref() {
+                    return Ok(None);
+                }
+
+                backend.load_file_diff(&request)
+                    .map(|diff| Some(diff.ok_or_else(|| format!("File {} is not changed", request.path))))
+                    .map_err(|e| format!("Error loading diff: {}", e))
+            })).await;
+
+            match result {
+                Ok(Some(Ok(diff))) => main_screen.ui.diff_view.show_diff(diff),
+                Ok(Some(Err(e))) | Err(e) => main_screen.ui.diff_view.show_message(&e),
+                Ok(None) => {
+                    // Do nothing
+                },
+            }
         }));
     }
 
+    /// A single selected commit is compared according to the "Compare with" option;
+    /// two selected commits are compared with each other, the lower (older) row being the base.
+    /// The uncommitted changes rows stand for the working tree and the index; a stash row for the stashed working tree.
+    fn selected_comparison(&self) -> Option<Comparison> {
+        if let Some(stash) = self.selected_stash() {
+            return Some(Comparison::Stash(stash));
+        }
+
+        let (paths, model) = self.ui.commits_tree_view.get_selection().get_selected_rows();
+        let rows = paths.iter()
+            .filter_map(|path| model.get_iter(path))
+            .map(|iter| {
+                let id = model.get_value(&iter, 0).get::<String>()?;
+                match id.as_str() {
+                    UNSTAGED_CHANGES_ROW => Some(DiffTarget::WorkingTree),
+                    STAGED_CHANGES_ROW => Some(DiffTarget::Index),
+                    _ => git2::Oid::from_str(id.trim_start_matches(STASH_ROW_PREFIX)).ok().map(DiffTarget::Commit),
+                }
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        match rows.as_slice() {
+            [DiffTarget::Commit(commit)] => {
+                let compare_with = self.ui.compare_with_combo_box.get_active_id()
+                    .and_then(|id| CompareWith::from_id(&id))
+                    .unwrap_or(CompareWith::Parents);
+                Some(match compare_with {
+                    CompareWith::Parents => Comparison::Parents(*commit),
+                    CompareWith::WorkingTree => Comparison::Range { old: *commit, new: DiffTarget::WorkingTree },
+                    CompareWith::Index => Comparison::Range { old: *commit, new: DiffTarget::Index },
+                })
+            },
+            [DiffTarget::WorkingTree] => Some(Comparison::Unstaged),
+            [DiffTarget::Index] => Some(Comparison::Staged),
+            [new, DiffTarget::Commit(old)] => Some(Comparison::Range { old: *old, new: *new }),
+            _ => None,
+        }
+    }
+
     pub fn show(main_screen: Rc<MainScreen>) -> impl Future<Output=()> {
         println!("Showing main screen");
         let (promise, resolver) = Promise::new();
@@ -344,83 +3353,152 @@ impl MainScreen {
             Inhibit(false)
         }));
 
-        {
-            main_screen.ui.commits_list_store.insert_with_values(
-                None,
-                &[0, 1, 2, 3],
-                &[&"", &"Loading...", &"", &""]
-            );
-        }
+        Self::load_commits(main_screen, RefsFilter::Head);
 
-        gtk_spawn_local(capture!(main_screen; async move {
+        promise
+    }
+
+    fn load_commits(main_screen: Rc<Self>, filter: RefsFilter) {
+        let generation = {
+            let mut commits_generation = main_screen.backend.commits_generation.lock().unwrap();
+            *commits_generation += 1;
+            *commits_generation
+        };
+
+        main_screen.ui.commits_list_store.clear();
+        main_screen.ui.commits_graph.borrow_mut().clear();
+        main_screen.ui.commit_nodes.borrow_mut().clear();
+        main_screen.ui.commits_list_store.insert_with_values(
+            None,
+            &[0, 1, 2, 3, 4, 5],
+            &[&"", &"Loading...", &"", &"", &-1i32, &"Loading..."]
+        );
+
+        gtk_spawn_local(async move {
             let commits_result = async_std::task::spawn(capture!(backend = main_screen.backend; async move {
-                let repo = backend.repo.lock().unwrap();
+                if generation != *backend.commits_generation.lock().unwrap() {
+                    return Ok(None);
+                }
+
+                let mut repo = backend.repo.lock().unwrap();
+
+                let stashes = crate::stash::list_stashes(&mut repo)
+                    .map_err(|e| format!("Error reading stash: {}", e))?;
+                let stash_rows: Vec<CommitRow> = stashes.iter()
+                    .map(|entry| repo.find_commit(entry.id).map(|commit| CommitRow::stash(entry, &commit)))
+                    .collect::<Result<_, _>>()
+                    .map_err(|e| format!("Error reading stash: {}", e))?;
 
                 let mut revwalk = repo.revwalk()
                     .map_err(|e| format!("Error loading commit graph: {}", e))?;
 
-                revwalk.set_sorting(git2::Sort::TIME | git2::Sort::TOPOLOGICAL/* | git2::Sort::REVERSE*/);
-                revwalk.push_head()
+                revwalk.set_sorting(git2::Sort::TIME | git2::Sort::TOPOLOGICAL/* | git2::Sort::REVERSE*/)
+                    .map_err(|e| format!("Error initializing commit graph walk: {}", e))?;
+                filter.push_tips(&mut revwalk)
                     .map_err(|e| format!("Error initializing commit graph walk: {}", e))?;
-                let commit_ids: Vec<git2::Oid> = revwalk.collect::<Result<_, _>>()
-                    .map_err(|e| format!("Error iterating over commits: {}", e))?;
+                for tip in backend.extra_tips.lock().unwrap().iter() {
+                    // The commit may have been pruned since
+                    if repo.find_commit(*tip).is_ok() {
+                        revwalk.push(*tip)
+                            .map_err(|e| format!("Error initializing commit graph walk: {}", e))?;
+                    }
+                }
+                let file_history = backend.requested_file_history.lock().unwrap().clone();
+                let (commit_ids, history_parents): (Vec<git2::Oid>, Option<Vec<Vec<git2::Oid>>>) = match &file_history {
+                    Some(path) => {
+                        let history = crate::file_history::file_history(&repo, revwalk, path)
+                            .map_err(|e| format!("Error reading history of {}: {}", path, e))?;
+                        *backend.file_history_paths.lock().unwrap() = history.paths;
+                        let (commit_ids, parents) = history.commits.into_iter().unzip();
+                        (commit_ids, Some(parents))
+                    },
+                    None => {
+                        backend.file_history_paths.lock().unwrap().clear();
+                        let commit_ids = revwalk.collect::<Result<_, _>>()
+                            .map_err(|e| format!("Error iterating over commits: {}", e))?;
+                        (commit_ids, None)
+                    },
+                };
 
                 let commits: Vec<git2::Commit> = commit_ids.into_iter().map(|commit_id| repo.find_commit(commit_id))
                     .collect::<Result<_, _>>()
                     .map_err(|e| format!("Error reading commit: {}", e))?;
 
-                let commit_infos: Vec<_> = commits.into_iter().map(|commit| {
-                    let commit_id = format!("{}", commit.id());
-                    let summary = String::from_utf8_lossy(commit.summary_bytes().unwrap_or(&[])).to_string();
-                    use chrono::TimeZone;
-                    let timestamp =
-                        chrono::Utc.timestamp(commit.author().when().seconds(), 0)
-                        .with_timezone(&chrono::FixedOffset::east(commit.author().when().offset_minutes() * 60));
-                    let author = String::from_utf8_lossy(commit.author().name_bytes()).to_string();
-                    let email = String::from_utf8_lossy(commit.author().email_bytes()).to_string();
+                let nodes: Vec<CommitNode> = match history_parents {
+                    // The graph links each commit of the file history to the closest ones among its ancestors
+                    Some(parents) => commits.iter().zip(parents).map(|(commit, parents)| CommitNode {
+                        id: commit.id(),
+                        parents,
+                    }).collect(),
+                    None => commits.iter().map(|commit| CommitNode {
+                        id: commit.id(),
+                        parents: commit.parent_ids().collect(),
+                    }).collect(),
+                };
+
+                let graph = layout_nodes(&nodes);
+
+                let mut ref_labels = crate::ref_labels::collect_ref_labels(&repo)
+                    .map_err(|e| format!("Error reading references: {}", e))?;
 
-                    (commit_id, summary, timestamp, author, email)
-                }).collect();
+                let commit_rows: Vec<CommitRow> = commits.iter()
+                    .map(|commit| CommitRow::new(commit, &ref_labels.remove(&commit.id()).unwrap_or_default()))
+                    .collect();
 
-                Result::<Vec<_>, String>::Ok(commit_infos)
+                let uncommitted_changes = uncommitted_changes_status(&repo)
+                    .map_err(|e| format!("Error reading working tree status: {}", e))?;
+
+                let refs = crate::refs::collect_refs(&repo)
+                    .map_err(|e| format!("Error reading references: {}", e))?;
+
+                let conflicts = backend.stopped_operation_conflicts(&repo)
+                    .map_err(|e| format!("Error reading conflicts: {}", e))?;
+
+                Result::<_, String>::Ok(Some((commit_rows, nodes, graph, uncommitted_changes, stash_rows, refs, conflicts)))
             })).await;
 
+            if generation != *main_screen.backend.commits_generation.lock().unwrap() {
+                // The commits were requested again while loading
+                return;
+            }
+
             match commits_result {
-                Ok(commits) => {
+                Ok(None) => {
+                    // Do nothing
+                },
+                Ok(Some((commit_rows, nodes, graph, (has_unstaged, has_staged), stash_rows, refs, conflicts))) => {
                     main_screen.ui.commits_list_store.clear();
-                    for (commit_id, summary, timestamp, author, email) in commits {
-                        main_screen.ui.commits_list_store.insert_with_values(
-                            None,
-                            &[0, 1, 2, 3],
-                            &[
-                                &commit_id,
-                                &summary,
-                                &timestamp.format("%Y-%m-%d %H:%M:%S %:z").to_string(),
-                                &format!("{} <{}>", author, email)
-                            ]
-                        );
+                    main_screen.ui.commits_graph.replace(graph);
+                    main_screen.ui.commit_nodes.replace(nodes);
+                    for (row_idx, commit_row) in commit_rows.iter().enumerate() {
+                        main_screen.insert_commit_row(None, commit_row, row_idx as i32);
+                    }
+                    main_screen.update_uncommitted_changes_rows(has_unstaged, has_staged);
+                    main_screen.update_stash_rows(&stash_rows);
+                    let reflog_refs: Vec<(String, String)> = refs.iter()
+                        .filter(|entry| entry.kind != RefKind::Tag)
+                        .map(|entry| (entry.full_name.clone(), entry.name.clone()))
+                        .collect();
+                    main_screen.ui.reflog_view.set_ref_names(&reflog_refs);
+                    main_screen.ui.refs_sidebar.set_refs(refs);
+                    main_screen.update_conflicts_view(conflicts);
+                    Self::load_reflog(main_screen.clone());
+
+                    let pending_selected_commit = main_screen.ui.pending_selected_commit.replace(None);
+                    if let Some(commit) = pending_selected_commit {
+                        Self::select_commit(main_screen.clone(), commit);
                     }
                 },
                 Err(msg) => {
-                    use gtk::{ButtonsType, DialogFlags, MessageType, MessageDialog};
-                    let dialog = MessageDialog::new(Some(&main_screen.ui.window),
-                        DialogFlags::empty() | DialogFlags::MODAL,
-                        MessageType::Error,
-                        ButtonsType::Ok,
-                        &msg
-                    );
-                    dialog.run();
-                    dialog.destroy();
+                    main_screen.show_error(&msg);
                     main_screen.ui.commits_list_store.clear();
                     main_screen.ui.commits_list_store.insert_with_values(
                         None,
-                        &[0],
-                        &[&msg]
+                        &[1, 4, 5],
+                        &[&msg, &-1i32, &glib::markup_escape_text(&msg).to_string()]
                     );
                 }
             }
-        }));
-
-        promise
+        });
     }
-}
\ No newline at end of file
+}
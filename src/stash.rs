@@ -0,0 +1,83 @@
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct StashEntry {
+    /// Position in the stash list, 0 being the most recent entry
+    pub index: usize,
+    pub id: git2::Oid,
+    pub message: String,
+}
+
+impl StashEntry {
+    /// Name like `stash@{0}`
+    pub fn name(&self) -> String {
+        format!("stash@{{{}}}", self.index)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub struct StashOptions {
+    /// Also stash untracked files, removing them from the working tree
+    pub include_untracked: bool,
+    /// Leave the staged changes in the index and the working tree
+    pub keep_index: bool,
+}
+
+/// Outcome of applying a stash entry
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum StashApplied {
+    Applied,
+    /// Applying left conflicts in these files; the entry is kept even if it was popped
+    Conflicts(Vec<String>),
+}
+
+pub fn list_stashes(repo: &mut git2::Repository) -> Result<Vec<StashEntry>, git2::Error> {
+    let mut entries = Vec::new();
+    repo.stash_foreach(|index, message, id| {
+        entries.push(StashEntry { index, id: *id, message: message.to_owned() });
+        true
+    })?;
+
+    Ok(entries)
+}
+
+/// Stashes the uncommitted changes; returns the new entry's commit
+pub fn save(repo: &mut git2::Repository, message: Option<&str>, options: StashOptions) -> Result<git2::Oid, git2::Error> {
+    let mut flags = git2::StashFlags::DEFAULT;
+    if options.include_untracked {
+        flags |= git2::StashFlags::INCLUDE_UNTRACKED;
+    }
+    if options.keep_index {
+        flags |= git2::StashFlags::KEEP_INDEX;
+    }
+
+    let stasher = repo.signature()?;
+    repo.stash_save(&stasher, message.unwrap_or(""), Some(flags))
+}
+
+/// Applies the stash entry with commit `id` to the working tree; with `pop`, drops the entry unless there are conflicts
+pub fn apply(repo: &mut git2::Repository, id: git2::Oid, pop: bool) -> Result<StashApplied, git2::Error> {
+    let index = find_index(repo, id)?;
+    repo.stash_apply(index, None)?;
+
+    let paths = crate::conflicts::conflicted_paths(repo)?;
+    if !paths.is_empty() {
+        return Ok(StashApplied::Conflicts(paths));
+    }
+    if pop {
+        repo.stash_drop(index)?;
+    }
+
+    Ok(StashApplied::Applied)
+}
+
+pub fn drop(repo: &mut git2::Repository, id: git2::Oid) -> Result<(), git2::Error> {
+    let index = find_index(repo, id)?;
+    repo.stash_drop(index)
+}
+
+/// Current position of an entry, which changes as entries are added and dropped
+fn find_index(repo: &mut git2::Repository, id: git2::Oid) -> Result<usize, git2::Error> {
+    list_stashes(repo)?.into_iter()
+        .find(|entry| entry.id == id)
+        .map(|entry| entry.index)
+        .ok_or_else(|| git2::Error::from_str(&format!("Stash entry {} does not exist anymore", id)))
+}
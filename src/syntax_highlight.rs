@@ -0,0 +1,353 @@
+use std::ops::Range;
+
+use crate::file_diff::{FileDiff, DiffLineKind};
+
+/// Files larger than this are not highlighted as a whole; only the lines shown in a diff are highlighted then
+pub const MAX_HIGHLIGHTED_FILE_SIZE: usize = 1024 * 1024;
+/// Lines longer than this (e.g. minified sources) are left unhighlighted
+const MAX_HIGHLIGHTED_LINE_LENGTH: usize = 4096;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TokenClass {
+    Comment,
+    String,
+    Keyword,
+    Number,
+}
+
+/// Highlighted ranges of a single line, in char offsets
+pub type LineHighlights = Vec<(Range<usize>, TokenClass)>;
+
+pub struct Language {
+    extensions: &'static [&'static str],
+    interpreters: &'static [&'static str],
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    string_quotes: &'static [char],
+    keywords: &'static [&'static str],
+}
+
+static LANGUAGES: &[Language] = &[
+    Language {
+        extensions: &["rs"],
+        interpreters: &[],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        string_quotes: &['"'],
+        keywords: &[
+            "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "false",
+            "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return",
+            "self", "Self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use", "where", "while",
+        ],
+    },
+    Language {
+        extensions: &["c", "h", "cc", "cpp", "cxx", "hpp", "hh", "hxx", "m", "mm"],
+        interpreters: &[],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        string_quotes: &['"', '\''],
+        keywords: &[
+            "auto", "bool", "break", "case", "char", "class", "const", "constexpr", "continue", "default", "delete",
+            "do", "double", "else", "enum", "extern", "false", "float", "for", "goto", "if", "inline", "int", "long",
+            "namespace", "new", "nullptr", "private", "protected", "public", "return", "short", "signed", "sizeof",
+            "static", "struct", "switch", "template", "this", "true", "typedef", "typename", "union", "unsigned",
+            "using", "virtual", "void", "volatile", "while", "#include", "#define", "#if", "#ifdef", "#ifndef",
+            "#else", "#elif", "#endif", "#pragma",
+        ],
+    },
+    Language {
+        extensions: &["java", "cs", "kt", "kts", "scala", "groovy", "gradle"],
+        interpreters: &[],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        string_quotes: &['"', '\''],
+        keywords: &[
+            "abstract", "boolean", "break", "case", "catch", "class", "continue", "def", "default", "do", "double",
+            "else", "enum", "extends", "false", "final", "finally", "float", "for", "fun", "if", "implements",
+            "import", "int", "interface", "internal", "is", "long", "namespace", "new", "null", "object", "override",
+            "package", "private", "protected", "public", "return", "static", "string", "super", "switch", "this",
+            "throw", "throws", "true", "try", "using", "val", "var", "void", "when", "while",
+        ],
+    },
+    Language {
+        extensions: &["js", "jsx", "mjs", "cjs", "ts", "tsx"],
+        interpreters: &["node"],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        string_quotes: &['"', '\'', '`'],
+        keywords: &[
+            "async", "await", "break", "case", "catch", "class", "const", "continue", "default", "delete", "do",
+            "else", "export", "extends", "false", "finally", "for", "from", "function", "if", "import", "in",
+            "instanceof", "interface", "let", "new", "null", "return", "super", "switch", "this", "throw", "true",
+            "try", "type", "typeof", "undefined", "var", "void", "while", "yield",
+        ],
+    },
+    Language {
+        extensions: &["go"],
+        interpreters: &[],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        string_quotes: &['"', '\'', '`'],
+        keywords: &[
+            "break", "case", "chan", "const", "continue", "default", "defer", "else", "fallthrough", "false", "for",
+            "func", "go", "goto", "if", "import", "interface", "map", "nil", "package", "range", "return", "select",
+            "struct", "switch", "true", "type", "var",
+        ],
+    },
+    Language {
+        extensions: &["py", "pyw"],
+        interpreters: &["python", "python2", "python3"],
+        line_comments: &["#"],
+        block_comment: None,
+        string_quotes: &['"', '\''],
+        keywords: &[
+            "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del", "elif", "else",
+            "except", "False", "finally", "for", "from", "global", "if", "import", "in", "is", "lambda", "None",
+            "nonlocal", "not", "or", "pass", "raise", "return", "True", "try", "while", "with", "yield",
+        ],
+    },
+    Language {
+        extensions: &["rb", "rake", "gemspec"],
+        interpreters: &["ruby"],
+        line_comments: &["#"],
+        block_comment: None,
+        string_quotes: &['"', '\''],
+        keywords: &[
+            "begin", "break", "case", "class", "def", "do", "else", "elsif", "end", "ensure", "false", "for", "if",
+            "in", "module", "next", "nil", "not", "rescue", "return", "self", "then", "true", "unless", "until",
+            "when", "while", "yield",
+        ],
+    },
+    Language {
+        extensions: &["sh", "bash", "zsh"],
+        interpreters: &["sh", "bash", "zsh", "dash", "ksh"],
+        line_comments: &["#"],
+        block_comment: None,
+        string_quotes: &['"', '\''],
+        keywords: &[
+            "case", "do", "done", "elif", "else", "esac", "export", "fi", "for", "function", "if", "in", "local",
+            "return", "then", "until", "while",
+        ],
+    },
+    Language {
+        extensions: &["toml", "yaml", "yml", "ini", "cfg", "conf", "properties"],
+        interpreters: &[],
+        line_comments: &["#", ";"],
+        block_comment: None,
+        string_quotes: &['"', '\''],
+        keywords: &["true", "false", "yes", "no", "null"],
+    },
+    Language {
+        extensions: &["json"],
+        interpreters: &[],
+        line_comments: &[],
+        block_comment: None,
+        string_quotes: &['"'],
+        keywords: &["true", "false", "null"],
+    },
+    Language {
+        extensions: &["xml", "html", "htm", "xhtml", "svg", "ui", "glade"],
+        interpreters: &[],
+        line_comments: &[],
+        block_comment: Some(("<!--", "-->")),
+        string_quotes: &['"', '\''],
+        keywords: &[],
+    },
+    Language {
+        extensions: &["sql"],
+        interpreters: &[],
+        line_comments: &["--"],
+        block_comment: Some(("/*", "*/")),
+        string_quotes: &['\''],
+        keywords: &[
+            "and", "as", "by", "create", "delete", "drop", "from", "group", "index", "insert", "into", "join",
+            "not", "null", "on", "or", "order", "select", "set", "table", "update", "values", "where",
+            "AND", "AS", "BY", "CREATE", "DELETE", "DROP", "FROM", "GROUP", "INDEX", "INSERT", "INTO", "JOIN",
+            "NOT", "NULL", "ON", "OR", "ORDER", "SELECT", "SET", "TABLE", "UPDATE", "VALUES", "WHERE",
+        ],
+    },
+];
+
+/// Picks the language by the file extension, or by the interpreter named in the shebang line
+pub fn detect_language(path: &str, first_line: Option<&str>) -> Option<&'static Language> {
+    let file_name = path.rsplit('/').next().unwrap_or(path);
+    if let Some(dot_idx) = file_name.rfind('.') {
+        let extension = file_name[dot_idx + 1..].to_lowercase();
+        if let Some(language) = LANGUAGES.iter().find(|language| language.extensions.contains(&extension.as_str())) {
+            return Some(language);
+        }
+    }
+
+    let first_line = first_line?;
+    if !first_line.starts_with("#!") {
+        return None;
+    }
+    let mut words = first_line[2..].split_whitespace();
+    let mut interpreter = words.next()?.rsplit('/').next()?;
+    if interpreter == "env" {
+        interpreter = words.find(|word| !word.starts_with('-'))?;
+    }
+
+    LANGUAGES.iter().find(|language| language.interpreters.contains(&interpreter))
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum LexerState {
+    Normal,
+    BlockComment,
+}
+
+/// Line-by-line lexer; keeps track of block comments spanning several lines
+pub struct Highlighter {
+    language: &'static Language,
+    state: LexerState,
+}
+
+impl Highlighter {
+    pub fn new(language: &'static Language) -> Highlighter {
+        Highlighter {
+            language,
+            state: LexerState::Normal,
+        }
+    }
+
+    pub fn highlight_line(&mut self, line: &str) -> LineHighlights {
+        let chars: Vec<char> = line.chars().collect();
+        let mut result = LineHighlights::new();
+
+        if chars.len() > MAX_HIGHLIGHTED_LINE_LENGTH {
+            return result;
+        }
+
+        let starts_with = |idx: usize, s: &str| s.chars().enumerate().all(|(offset, c)| chars.get(idx + offset) == Some(&c));
+        let is_word_char = |c: char| c.is_alphanumeric() || c == '_' || c == '#';
+
+        let mut idx = 0;
+        while idx < chars.len() {
+            if self.state == LexerState::BlockComment {
+                let (_, end) = self.language.block_comment.expect("Block comment state is only entered for languages with block comments");
+                let start = idx;
+                while idx < chars.len() && !starts_with(idx, end) {
+                    idx += 1;
+                }
+                if idx < chars.len() {
+                    idx += end.chars().count();
+                    self.state = LexerState::Normal;
+                }
+                result.push((start..idx, TokenClass::Comment));
+                continue;
+            }
+
+            let c = chars[idx];
+
+            if self.language.line_comments.iter().any(|comment| starts_with(idx, comment)) {
+                result.push((idx..chars.len(), TokenClass::Comment));
+                break;
+            }
+
+            if let Some((start, _)) = self.language.block_comment {
+                if starts_with(idx, start) {
+                    self.state = LexerState::BlockComment;
+                    let comment_start = idx;
+                    idx += start.chars().count();
+                    let (_, end) = self.language.block_comment.unwrap();
+                    while idx < chars.len() && !starts_with(idx, end) {
+                        idx += 1;
+                    }
+                    if idx < chars.len() {
+                        idx += end.chars().count();
+                        self.state = LexerState::Normal;
+                    }
+                    result.push((comment_start..idx, TokenClass::Comment));
+                    continue;
+                }
+            }
+
+            if self.language.string_quotes.contains(&c) {
+                let start = idx;
+                idx += 1;
+                while idx < chars.len() && chars[idx] != c {
+                    if chars[idx] == '\\' {
+                        idx += 1;
+                    }
+                    idx += 1;
+                }
+                idx = (idx + 1).min(chars.len());
+                result.push((start..idx, TokenClass::String));
+                continue;
+            }
+
+            if is_word_char(c) {
+                let start = idx;
+                while idx < chars.len() && is_word_char(chars[idx]) {
+                    idx += 1;
+                }
+                let word: String = chars[start..idx].iter().collect();
+                if c.is_ascii_digit() {
+                    result.push((start..idx, TokenClass::Number));
+                } else if self.language.keywords.contains(&word.as_str()) {
+                    result.push((start..idx, TokenClass::Keyword));
+                }
+                continue;
+            }
+
+            idx += 1;
+        }
+
+        result
+    }
+}
+
+/// Highlights all lines of `content`
+pub fn highlight_text(language: &'static Language, content: &str) -> Vec<LineHighlights> {
+    let mut highlighter = Highlighter::new(language);
+    content.lines().map(|line| highlighter.highlight_line(line)).collect()
+}
+
+/// Fills in highlights of the lines of `diff`.
+///
+/// When full contents of the old and new versions are given, they are highlighted as a whole and lines
+/// of the diff take highlights by their line numbers, so that e.g. comments started outside the hunks are
+/// recognized. Otherwise the lines of the hunks are highlighted on their own.
+pub fn highlight_file_diff(diff: &mut FileDiff, old_content: Option<&str>, new_content: Option<&str>) {
+    let path = match diff.new_path.as_ref().or(diff.old_path.as_ref()) {
+        Some(path) => path.clone(),
+        None => return,
+    };
+    let first_line = new_content.or(old_content).and_then(|content| content.lines().next())
+        .or_else(|| diff.hunks.first().and_then(|hunk| hunk.lines.first()).map(|line| line.content.as_str()));
+    let language = match detect_language(&path, first_line) {
+        Some(language) => language,
+        None => return,
+    };
+
+    let highlight_content = |content: Option<&str>| content
+        .filter(|content| content.len() <= MAX_HIGHLIGHTED_FILE_SIZE)
+        .map(|content| highlight_text(language, content));
+    let old_highlights = highlight_content(old_content);
+    let new_highlights = highlight_content(new_content);
+
+    let mut old_highlighter = Highlighter::new(language);
+    let mut new_highlighter = Highlighter::new(language);
+
+    for hunk in diff.hunks.iter_mut() {
+        for line in hunk.lines.iter_mut() {
+            let (lineno, whole_file, highlighter) = match line.kind {
+                DiffLineKind::Removed => (line.old_lineno, &old_highlights, &mut old_highlighter),
+                DiffLineKind::Added | DiffLineKind::Context => (line.new_lineno, &new_highlights, &mut new_highlighter),
+                DiffLineKind::NoNewline => continue,
+            };
+
+            line.highlights = match (whole_file, lineno) {
+                (Some(whole_file), Some(lineno)) => whole_file.get((lineno as usize).saturating_sub(1)).cloned().unwrap_or_default(),
+                _ => {
+                    let highlights = highlighter.highlight_line(&line.content);
+                    if line.kind == DiffLineKind::Context {
+                        old_highlighter.highlight_line(&line.content);
+                    }
+                    highlights
+                },
+            };
+        }
+    }
+}
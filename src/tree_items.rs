@@ -0,0 +1,73 @@
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TreeItemKind {
+    Directory,
+    File,
+    /// Commit of a submodule
+    Submodule,
+}
+
+impl TreeItemKind {
+    pub fn icon_name(self) -> &'static str {
+        match self {
+            TreeItemKind::Directory => "folder",
+            TreeItemKind::File => "text-x-generic",
+            TreeItemKind::Submodule => "folder-remote",
+        }
+    }
+}
+
+/// Entry of a tree of the repository
+#[derive(Debug, Clone)]
+pub struct TreeItem {
+    pub name: String,
+    /// Path from the root of the repository
+    pub path: String,
+    pub id: git2::Oid,
+    pub kind: TreeItemKind,
+    /// File mode, e.g. 0o100644
+    pub mode: i32,
+    /// Size of a file in bytes; `None` for directories and submodules
+    pub size: Option<usize>,
+}
+
+/// Entries of the tree `tree_id` found at `prefix` (empty for the root), directories first
+pub fn list_tree(repo: &git2::Repository, tree_id: git2::Oid, prefix: &str) -> Result<Vec<TreeItem>, git2::Error> {
+    let tree = repo.find_tree(tree_id)?;
+    let odb = repo.odb()?;
+
+    let mut items = Vec::with_capacity(tree.len());
+    for entry in tree.iter() {
+        let name = String::from_utf8_lossy(entry.name_bytes()).to_string();
+        let kind = match entry.kind() {
+            Some(git2::ObjectType::Tree) => TreeItemKind::Directory,
+            Some(git2::ObjectType::Commit) => TreeItemKind::Submodule,
+            _ => TreeItemKind::File,
+        };
+        // Reading the header only avoids loading the whole blob
+        let size = match kind {
+            TreeItemKind::File => Some(odb.read_header(entry.id())?.0),
+            _ => None,
+        };
+
+        items.push(TreeItem {
+            path: if prefix.is_empty() { name.clone() } else { format!("{}/{}", prefix, name) },
+            name,
+            id: entry.id(),
+            kind,
+            mode: entry.filemode(),
+            size,
+        });
+    }
+    items.sort_by_key(|item| item.kind != TreeItemKind::Directory);
+
+    Ok(items)
+}
+
+/// Entries of the root tree of a commit
+pub fn list_commit_tree(repo: &git2::Repository, commit: git2::Oid) -> Result<Vec<TreeItem>, git2::Error> {
+    list_tree(repo, repo.find_commit(commit)?.tree_id(), "")
+}
+
+pub fn load_blob(repo: &git2::Repository, id: git2::Oid) -> Result<Vec<u8>, git2::Error> {
+    Ok(repo.find_blob(id)?.content().to_owned())
+}
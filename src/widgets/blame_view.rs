@@ -0,0 +1,195 @@
+use gtk::prelude::*;
+
+use std::rc::Rc;
+
+use crate::blame::FileBlame;
+
+/// Gutter colours of the oldest and of the newest changes; the others are in between
+const OLDEST_COLOR: (f64, f64, f64) = (221.0, 227.0, 234.0);
+const NEWEST_COLOR: (f64, f64, f64) = (245.0, 185.0, 113.0);
+/// Number of the columns with the commit, the author and the date, which make the gutter
+const GUTTER_COLUMNS: i32 = 3;
+
+/// Window with the lines of a file, each annotated with the commit that last changed it
+pub struct BlameView {
+    window: gtk::Window,
+    title_label: gtk::Label,
+    list_store: gtk::ListStore,
+    tree_view: gtk::TreeView,
+    blame_parent_button: gtk::Button,
+}
+
+impl BlameView {
+    pub fn new(parent: &gtk::Window) -> Rc<BlameView> {
+        let window = gtk::Window::new(gtk::WindowType::Toplevel);
+        window.set_transient_for(Some(parent));
+        window.set_default_size(900, 700);
+
+        let header_hbox = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+        header_hbox.set_border_width(4);
+        let title_label = gtk::Label::new(None);
+        title_label.set_halign(gtk::Align::Start);
+        title_label.set_ellipsize(pango::EllipsizeMode::Start);
+        let blame_parent_button = gtk::Button::new_with_label("Blame parent");
+        blame_parent_button.set_tooltip_text(Some("Blame the file as it was before the commit of the selected line"));
+        header_hbox.pack_start(&title_label, true, true, 0);
+        header_hbox.pack_end(&blame_parent_button, false, false, 0);
+
+        let list_store = gtk::ListStore::new(&[
+            gtk::Type::String, // Commit ID
+            gtk::Type::String, // Abbreviated commit ID; empty except on the first line of a hunk
+            gtk::Type::String, // Author; empty except on the first line of a hunk
+            gtk::Type::String, // Date; empty except on the first line of a hunk
+            gtk::Type::String, // Gutter colour, depending on the age of the commit
+            gtk::Type::U32, // Line number
+            gtk::Type::String, // Line content
+            gtk::Type::String, // Path of the file in the commit
+        ]);
+
+        let tree_view = gtk::TreeView::new_with_model(&list_store);
+        tree_view.set_enable_search(false);
+        for (title, column_idx) in [("Commit", 1), ("Author", 2), ("Date", 3), ("Line", 5), ("", 6)].iter() {
+            let cell_renderer = gtk::CellRendererText::new();
+            cell_renderer.set_property_family(Some("monospace"));
+            let column = gtk::TreeViewColumn::new();
+            column.set_title(title);
+            column.pack_start(&cell_renderer, true);
+            column.add_attribute(&cell_renderer, "text", *column_idx);
+            match column_idx {
+                5 => cell_renderer.set_property_xalign(1.0),
+                6 => column.set_expand(true),
+                _ => {
+                    column.add_attribute(&cell_renderer, "cell-background", 4);
+                    column.set_resizable(true);
+                },
+            }
+            tree_view.append_column(&column);
+        }
+
+        let scrolled_window = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        scrolled_window.add(&tree_view);
+
+        let vbox = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        vbox.pack_start(&header_hbox, false, false, 0);
+        vbox.pack_start(&scrolled_window, true, true, 0);
+        window.add(&vbox);
+
+        // The window is reused for the next blame
+        window.connect_delete_event(|window, _| Inhibit(window.hide_on_delete()));
+
+        let blame_view = Rc::new(BlameView {
+            window,
+            title_label,
+            list_store,
+            tree_view,
+            blame_parent_button,
+        });
+
+        blame_view.tree_view.get_selection().connect_changed(capture!(blame_view; move |_| {
+            blame_view.blame_parent_button.set_sensitive(blame_view.selected_line().is_some());
+        }));
+        blame_view.blame_parent_button.set_sensitive(false);
+
+        blame_view
+    }
+
+    /// Shows the lines of `blame` and brings the window to the front
+    pub fn show_blame(&self, blame: &FileBlame) {
+        use chrono::TimeZone;
+
+        let title = format!("Blame of {} at {:.8}", blame.path, blame.commit);
+        self.window.set_title(&title);
+        self.title_label.set_text(&title);
+
+        let oldest = blame.lines.iter().map(|line| line.time.seconds()).min().unwrap_or(0);
+        let newest = blame.lines.iter().map(|line| line.time.seconds()).max().unwrap_or(0);
+
+        self.list_store.clear();
+        for (idx, line) in blame.lines.iter().enumerate() {
+            let (short_id, author, date) = if line.starts_hunk {
+                let timestamp = chrono::Utc.timestamp(line.time.seconds(), 0)
+                    .with_timezone(&chrono::FixedOffset::east(line.time.offset_minutes() * 60));
+                (format!("{:.8}", line.commit), line.author.clone(), timestamp.format("%Y-%m-%d").to_string())
+            } else {
+                Default::default()
+            };
+            let age = if newest > oldest { (line.time.seconds() - oldest) as f64 / (newest - oldest) as f64 } else { 1.0 };
+
+            self.list_store.insert_with_values(
+                None,
+                &[0, 1, 2, 3, 4, 5, 6, 7],
+                &[
+                    &line.commit.to_string(),
+                    &short_id,
+                    &author,
+                    &date,
+                    &gutter_color(age),
+                    &(idx as u32 + 1),
+                    &line.content,
+                    &line.path,
+                ]
+            );
+        }
+
+        self.window.show_all();
+        self.window.present();
+    }
+
+    /// Called with the commit of a line whose gutter is clicked or which is activated
+    pub fn connect_commit_selected<F: Fn(git2::Oid) + 'static>(self: &Rc<Self>, f: F) {
+        let f = Rc::new(f);
+        self.tree_view.connect_button_press_event(capture!(blame_view = *self, f; move |tree_view, event| {
+            if event.get_event_type() != gdk::EventType::ButtonPress || event.get_button() != 1 {
+                return Inhibit(false);
+            }
+
+            let (x, y) = event.get_position();
+            if let Some((Some(path), Some(column), _, _)) = tree_view.get_path_at_pos(x as i32, y as i32) {
+                let in_gutter = (0..GUTTER_COLUMNS).any(|idx| tree_view.get_column(idx).as_ref() == Some(&column));
+                if let Some((commit, _)) = blame_view.line_at(&path).filter(|_| in_gutter) {
+                    f(commit);
+                }
+            }
+            Inhibit(false)
+        }));
+        self.tree_view.connect_row_activated(capture!(blame_view = *self, f; move |_, path, _| {
+            if let Some((commit, _)) = blame_view.line_at(path) {
+                f(commit);
+            }
+        }));
+    }
+
+    /// Called with the commit of the selected line and the path of the file in that commit
+    pub fn connect_blame_parent<F: Fn(git2::Oid, String) + 'static>(self: &Rc<Self>, f: F) {
+        self.blame_parent_button.connect_clicked(capture!(blame_view = *self; move |_| {
+            if let Some((commit, path)) = blame_view.selected_line() {
+                f(commit, path);
+            }
+        }));
+    }
+
+    fn selected_line(&self) -> Option<(git2::Oid, String)> {
+        let (model, iter) = self.tree_view.get_selection().get_selected()?;
+        self.line_at(&model.get_path(&iter)?)
+    }
+
+    /// Commit and path in that commit of the line at `path`
+    fn line_at(&self, tree_path: &gtk::TreePath) -> Option<(git2::Oid, String)> {
+        let iter = self.list_store.get_iter(tree_path)?;
+        let commit = git2::Oid::from_str(&self.list_store.get_value(&iter, 0).get::<String>()?).ok()?;
+        let path = self.list_store.get_value(&iter, 7).get::<String>()?;
+
+        Some((commit, path))
+    }
+}
+
+/// Colour between the oldest and the newest gutter colours; `age` goes from 0 for the oldest to 1 for the newest
+fn gutter_color(age: f64) -> String {
+    let mix = |oldest: f64, newest: f64| (oldest + (newest - oldest) * age).round() as u8;
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        mix(OLDEST_COLOR.0, NEWEST_COLOR.0),
+        mix(OLDEST_COLOR.1, NEWEST_COLOR.1),
+        mix(OLDEST_COLOR.2, NEWEST_COLOR.2)
+    )
+}
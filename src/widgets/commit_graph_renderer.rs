@@ -0,0 +1,152 @@
+use glib::subclass;
+use glib::subclass::prelude::*;
+use glib::translate::*;
+use gtk::prelude::*;
+
+use std::cell::{Cell, RefCell};
+use std::os::raw::c_int;
+use std::rc::Rc;
+
+use crate::dag_layout::LayoutRow;
+use crate::screens::main_screen::CommitNode;
+
+/// Rows of the commit graph, shared with the commit list
+pub type CommitGraph = Rc<RefCell<Vec<LayoutRow<CommitNode>>>>;
+
+static PROPERTIES: [subclass::Property; 1] = [
+    subclass::Property("row", |name| {
+        glib::ParamSpec::int(
+            name,
+            "Row",
+            "Index of the row in the commit graph; -1 if there is no graph row",
+            -1,
+            i32::MAX,
+            -1,
+            glib::ParamFlags::READWRITE,
+        )
+    }),
+];
+
+/// Cell renderer drawing the row of the commit graph set in its `row` property.
+/// Rows are drawn over the whole height of the background area of the cell, so that the links of
+/// adjacent rows join up whatever the height of the rows.
+pub struct CommitGraphRenderer {
+    graph: RefCell<CommitGraph>,
+    row: Cell<i32>,
+}
+
+impl CommitGraphRenderer {
+    /// Creates a renderer drawing rows of `graph`
+    pub fn create(graph: CommitGraph) -> gtk::CellRenderer {
+        let renderer = glib::Object::new(Self::get_type(), &[])
+            .expect("Failed to create commit graph renderer")
+            .downcast::<gtk::CellRenderer>()
+            .expect("Commit graph renderer is a cell renderer");
+        Self::from_instance(&renderer).graph.replace(graph);
+
+        renderer
+    }
+
+    fn width(&self) -> f64 {
+        let graph = self.graph.borrow();
+        let graph = graph.borrow();
+        let row_idx = self.row.get();
+        match graph.get(row_idx as usize).filter(|_| row_idx >= 0) {
+            Some(row) => crate::dag_render::row_width(row, graph.get(row_idx as usize + 1)),
+            None => 0.0,
+        }
+    }
+
+    fn render(&self, cr: &cairo::Context, x: f64, y: f64, height: f64) {
+        let graph = self.graph.borrow();
+        let graph = graph.borrow();
+        let row_idx = self.row.get();
+        if let Some(row) = graph.get(row_idx as usize).filter(|_| row_idx >= 0) {
+            cr.save();
+            cr.translate(x, y);
+            crate::dag_render::render_row(cr, row, graph.get(row_idx as usize + 1), height);
+            cr.restore();
+        }
+    }
+}
+
+impl ObjectSubclass for CommitGraphRenderer {
+    const NAME: &'static str = "StainlessGitCommitGraphRenderer";
+    type ParentType = gtk::CellRenderer;
+    type Instance = subclass::simple::InstanceStruct<Self>;
+    type Class = subclass::simple::ClassStruct<Self>;
+
+    glib::glib_object_subclass!();
+
+    fn class_init(klass: &mut Self::Class) {
+        klass.install_properties(&PROPERTIES);
+    }
+
+    fn new() -> Self {
+        CommitGraphRenderer {
+            graph: RefCell::new(Rc::new(RefCell::new(Vec::new()))),
+            row: Cell::new(-1),
+        }
+    }
+}
+
+impl ObjectImpl for CommitGraphRenderer {
+    glib::glib_object_impl!();
+
+    fn set_property(&self, _obj: &glib::Object, id: usize, value: &glib::Value) {
+        match PROPERTIES[id] {
+            subclass::Property("row", ..) => self.row.set(value.get().unwrap_or(-1)),
+            _ => unreachable!(),
+        }
+    }
+
+    fn get_property(&self, _obj: &glib::Object, id: usize) -> Result<glib::Value, ()> {
+        match PROPERTIES[id] {
+            subclass::Property("row", ..) => Ok(self.row.get().to_value()),
+            _ => unreachable!(),
+        }
+    }
+}
+
+// gtk-rs does not support subclassing cell renderers yet, so the virtual methods are overridden here
+unsafe impl IsSubclassable<CommitGraphRenderer> for gtk::CellRendererClass {
+    fn override_vfuncs(&mut self) {
+        <glib::ObjectClass as IsSubclassable<CommitGraphRenderer>>::override_vfuncs(self);
+        unsafe {
+            let klass = &mut *(self as *mut Self as *mut gtk_sys::GtkCellRendererClass);
+            klass.get_preferred_width = Some(get_preferred_width);
+            klass.render = Some(render);
+        }
+    }
+}
+
+unsafe extern "C" fn get_preferred_width(
+    ptr: *mut gtk_sys::GtkCellRenderer,
+    _widget: *mut gtk_sys::GtkWidget,
+    minimum_width: *mut c_int,
+    natural_width: *mut c_int,
+) {
+    let instance = &*(ptr as *mut <CommitGraphRenderer as ObjectSubclass>::Instance);
+    let width = instance.get_impl().width().ceil() as c_int;
+    if !minimum_width.is_null() {
+        *minimum_width = width;
+    }
+    if !natural_width.is_null() {
+        *natural_width = width;
+    }
+}
+
+unsafe extern "C" fn render(
+    ptr: *mut gtk_sys::GtkCellRenderer,
+    cr: *mut cairo_sys::cairo_t,
+    _widget: *mut gtk_sys::GtkWidget,
+    background_area: *const gdk_sys::GdkRectangle,
+    cell_area: *const gdk_sys::GdkRectangle,
+    _flags: gtk_sys::GtkCellRendererState,
+) {
+    let instance = &*(ptr as *mut <CommitGraphRenderer as ObjectSubclass>::Instance);
+    let cr: cairo::Context = from_glib_none(cr);
+    let (background_area, cell_area) = (&*background_area, &*cell_area);
+
+    instance.get_impl().render(&cr, f64::from(cell_area.x), f64::from(background_area.y), f64::from(background_area.height));
+}
@@ -0,0 +1,253 @@
+use gtk::prelude::*;
+
+use std::rc::Rc;
+
+use crate::commit_message::{BODY_MAX_LENGTH, SUMMARY_MAX_LENGTH, compose_message, split_message};
+use crate::diff_items::DiffItem;
+
+const TAG_MONOSPACE: &str = "monospace";
+const TAG_OVERFLOW: &str = "overflow";
+
+/// Editor of a new commit: message, author override, amend and sign-off options,
+/// and the list of the files that go into the commit.
+pub struct CommitPanel {
+    container: gtk::Box,
+    summary_entry: gtk::Entry,
+    summary_length_label: gtk::Label,
+    body_text_view: gtk::TextView,
+    author_entry: gtk::Entry,
+    amend_check_button: gtk::CheckButton,
+    sign_off_check_button: gtk::CheckButton,
+    files_label: gtk::Label,
+    files_list_store: gtk::ListStore,
+    commit_button: gtk::Button,
+}
+
+impl CommitPanel {
+    pub fn new() -> Rc<CommitPanel> {
+        let container = gtk::Box::new(gtk::Orientation::Vertical, 4);
+        container.set_border_width(4);
+
+        let summary_hbox = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+        let summary_entry = gtk::Entry::new();
+        summary_entry.set_placeholder_text(Some("Summary"));
+        let summary_length_label = gtk::Label::new(None);
+        summary_hbox.pack_start(&summary_entry, true, true, 0);
+        summary_hbox.pack_start(&summary_length_label, false, false, 0);
+        container.pack_start(&summary_hbox, false, false, 0);
+
+        let tag_table = gtk::TextTagTable::new();
+        {
+            let tag = gtk::TextTag::new(Some(TAG_MONOSPACE));
+            tag.set_property_family(Some("monospace"));
+            tag_table.add(&tag);
+
+            let tag = gtk::TextTag::new(Some(TAG_OVERFLOW));
+            tag.set_property_background(Some("#fdb8c0"));
+            tag_table.add(&tag);
+        }
+
+        // Column ruler above the body, marking the summary and the body line limits
+        let ruler_label = gtk::Label::new(None);
+        ruler_label.set_markup(&format!("<tt><span foreground=\"#909497\">{}</span></tt>", ruler(BODY_MAX_LENGTH)));
+        ruler_label.set_halign(gtk::Align::Start);
+        ruler_label.set_tooltip_text(Some("Lines of the message body should not exceed 72 characters"));
+        container.pack_start(&ruler_label, false, false, 0);
+
+        let body_text_view = gtk::TextView::new_with_buffer(&gtk::TextBuffer::new(Some(&tag_table)));
+        body_text_view.set_wrap_mode(gtk::WrapMode::None);
+        let body_scrolled_window = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        body_scrolled_window.set_size_request(-1, 120);
+        body_scrolled_window.set_shadow_type(gtk::ShadowType::In);
+        body_scrolled_window.add(&body_text_view);
+        container.pack_start(&body_scrolled_window, true, true, 0);
+
+        let author_entry = gtk::Entry::new();
+        author_entry.set_placeholder_text(Some("Author override: Name <email>"));
+        container.pack_start(&author_entry, false, false, 0);
+
+        let options_hbox = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+        let amend_check_button = gtk::CheckButton::new_with_label("Amend last commit");
+        let sign_off_check_button = gtk::CheckButton::new_with_label("Add Signed-off-by");
+        let commit_button = gtk::Button::new_with_label("Commit");
+        options_hbox.pack_start(&amend_check_button, false, false, 0);
+        options_hbox.pack_start(&sign_off_check_button, false, false, 0);
+        options_hbox.pack_end(&commit_button, false, false, 0);
+        container.pack_start(&options_hbox, false, false, 0);
+
+        let files_label = gtk::Label::new(None);
+        files_label.set_halign(gtk::Align::Start);
+        container.pack_start(&files_label, false, false, 0);
+
+        let files_list_store = gtk::ListStore::new(&[
+            gtk::Type::String, // Change type icon name
+            gtk::Type::String, // Change type label as markup
+            gtk::Type::String, // Path as displayed
+        ]);
+        let files_tree_view = gtk::TreeView::new_with_model(&files_list_store);
+        files_tree_view.set_headers_visible(false);
+        {
+            let column = gtk::TreeViewColumn::new();
+
+            let cell_renderer_change_icon = gtk::CellRendererPixbuf::new();
+            column.pack_start(&cell_renderer_change_icon, false);
+            column.add_attribute(&cell_renderer_change_icon, "icon-name", 0);
+
+            let cell_renderer_change_type = gtk::CellRendererText::new();
+            column.pack_start(&cell_renderer_change_type, false);
+            column.add_attribute(&cell_renderer_change_type, "markup", 1);
+
+            let cell_renderer_path = gtk::CellRendererText::new();
+            cell_renderer_path.set_property_ellipsize(pango::EllipsizeMode::End);
+            column.pack_start(&cell_renderer_path, true);
+            column.add_attribute(&cell_renderer_path, "text", 2);
+
+            files_tree_view.append_column(&column);
+        }
+        let files_scrolled_window = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        files_scrolled_window.set_size_request(-1, 100);
+        files_scrolled_window.add(&files_tree_view);
+        container.pack_start(&files_scrolled_window, true, true, 0);
+
+        let commit_panel = Rc::new(CommitPanel {
+            container,
+            summary_entry,
+            summary_length_label,
+            body_text_view,
+            author_entry,
+            amend_check_button,
+            sign_off_check_button,
+            files_label,
+            files_list_store,
+            commit_button,
+        });
+
+        commit_panel.summary_entry.connect_changed(capture!(commit_panel; move |_| {
+            commit_panel.update_summary_length();
+        }));
+
+        commit_panel.body_text_view.get_buffer().unwrap().connect_changed(move |buffer| {
+            highlight_overflow(buffer);
+        });
+
+        commit_panel.update_summary_length();
+        commit_panel.set_files(&[]);
+
+        commit_panel
+    }
+
+    pub fn widget(&self) -> &gtk::Box {
+        &self.container
+    }
+
+    pub fn summary(&self) -> String {
+        self.summary_entry.get_text().map(|text| text.to_string()).unwrap_or_default()
+    }
+
+    pub fn message(&self) -> String {
+        let buffer = self.body_text_view.get_buffer().unwrap();
+        let body = buffer.get_text(&buffer.get_start_iter(), &buffer.get_end_iter(), false)
+            .map(|text| text.to_string())
+            .unwrap_or_default();
+
+        compose_message(&self.summary(), &body)
+    }
+
+    pub fn set_message(&self, message: &str) {
+        let (summary, body) = split_message(message);
+        self.summary_entry.set_text(&summary);
+        self.body_text_view.get_buffer().unwrap().set_text(&body);
+    }
+
+    pub fn is_message_empty(&self) -> bool {
+        self.message().trim().is_empty()
+    }
+
+    /// Author identity entered by the user; empty to use the default one
+    pub fn author(&self) -> String {
+        self.author_entry.get_text().map(|text| text.trim().to_string()).unwrap_or_default()
+    }
+
+    pub fn is_amend(&self) -> bool {
+        self.amend_check_button.get_active()
+    }
+
+    pub fn is_sign_off(&self) -> bool {
+        self.sign_off_check_button.get_active()
+    }
+
+    /// Shows the files that the commit will contain
+    pub fn set_files(&self, items: &[DiffItem]) {
+        self.files_list_store.clear();
+        for item in items {
+            self.files_list_store.insert_with_values(
+                None,
+                &[0, 1, 2],
+                &[&item.change_type.icon_name(), &item.change_type.markup(), &item.display_path()]
+            );
+        }
+
+        self.files_label.set_text(&match items.len() {
+            0 => "No files in the commit".to_owned(),
+            1 => "1 file in the commit:".to_owned(),
+            count => format!("{} files in the commit:", count),
+        });
+    }
+
+    /// Resets the panel after a commit is created
+    pub fn clear(&self) {
+        self.summary_entry.set_text("");
+        self.body_text_view.get_buffer().unwrap().set_text("");
+        self.author_entry.set_text("");
+        self.amend_check_button.set_active(false);
+    }
+
+    pub fn connect_commit<F: Fn() + 'static>(&self, f: F) {
+        self.commit_button.connect_clicked(move |_| f());
+    }
+
+    pub fn connect_amend_toggled<F: Fn(bool) + 'static>(&self, f: F) {
+        self.amend_check_button.connect_toggled(move |check_button| f(check_button.get_active()));
+    }
+
+    fn update_summary_length(&self) {
+        let length = self.summary().chars().count();
+        let color = if length > SUMMARY_MAX_LENGTH { "#c0392b" } else { "#909497" };
+        self.summary_length_label.set_markup(&format!("<span foreground=\"{}\">{}/{}</span>", color, length, SUMMARY_MAX_LENGTH));
+    }
+}
+
+/// Ruler text such as `----+----1----+----2--|`, `length` characters long
+fn ruler(length: usize) -> String {
+    (1..=length).map(|column| {
+        if column == length {
+            '|'
+        } else if column % 10 == 0 {
+            std::char::from_digit((column / 10 % 10) as u32, 10).unwrap_or('|')
+        } else if column % 5 == 0 {
+            '+'
+        } else {
+            '-'
+        }
+    }).collect()
+}
+
+/// Tags the characters that go beyond the line length limit of the message body
+fn highlight_overflow(buffer: &gtk::TextBuffer) {
+    let (start, end) = (buffer.get_start_iter(), buffer.get_end_iter());
+    buffer.remove_tag_by_name(TAG_OVERFLOW, &start, &end);
+    buffer.apply_tag_by_name(TAG_MONOSPACE, &start, &end);
+
+    for line in 0..buffer.get_line_count() {
+        let mut overflow_start = buffer.get_iter_at_line(line);
+        if overflow_start.get_chars_in_line() <= BODY_MAX_LENGTH as i32 {
+            continue;
+        }
+        overflow_start.set_line_offset(BODY_MAX_LENGTH as i32);
+        let mut line_end = overflow_start.clone();
+        if !line_end.ends_line() {
+            line_end.forward_to_line_end();
+        }
+        buffer.apply_tag_by_name(TAG_OVERFLOW, &overflow_start, &line_end);
+    }
+}
@@ -0,0 +1,219 @@
+use gtk::prelude::*;
+
+use std::rc::Rc;
+
+use crate::conflicts::{ConflictResolution, ConflictSides};
+
+const TAG_MONOSPACE: &str = "monospace";
+
+/// Panel listing the files with conflicts of an operation that stopped halfway, showing the base, ours and
+/// theirs versions of the selected file, with buttons to resolve it and to continue or abort the operation
+pub struct ConflictsView {
+    container: gtk::Box,
+    title_label: gtk::Label,
+    paths_list_store: gtk::ListStore,
+    paths_tree_view: gtk::TreeView,
+    base_text_view: gtk::TextView,
+    ours_text_view: gtk::TextView,
+    theirs_text_view: gtk::TextView,
+    resolution_buttons: Vec<(ConflictResolution, gtk::Button)>,
+    continue_button: gtk::Button,
+    abort_button: gtk::Button,
+}
+
+impl ConflictsView {
+    pub fn new() -> Rc<ConflictsView> {
+        let container = gtk::Box::new(gtk::Orientation::Vertical, 4);
+        container.set_border_width(4);
+
+        let header_hbox = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+        let title_label = gtk::Label::new(None);
+        title_label.set_halign(gtk::Align::Start);
+        title_label.set_line_wrap(true);
+        let continue_button = gtk::Button::new_with_label("Continue");
+        continue_button.set_tooltip_text(Some("Commit once all conflicts are resolved and staged"));
+        let abort_button = gtk::Button::new_with_label("Abort");
+        abort_button.set_tooltip_text(Some("Reset the index and the working tree to HEAD"));
+        header_hbox.pack_start(&title_label, true, true, 0);
+        header_hbox.pack_end(&abort_button, false, false, 0);
+        header_hbox.pack_end(&continue_button, false, false, 0);
+        container.pack_start(&header_hbox, false, false, 0);
+
+        let paths_list_store = gtk::ListStore::new(&[
+            gtk::Type::String, // File path
+        ]);
+        let paths_tree_view = gtk::TreeView::new_with_model(&paths_list_store);
+        paths_tree_view.set_headers_visible(false);
+        {
+            let column = gtk::TreeViewColumn::new();
+
+            let cell_renderer_icon = gtk::CellRendererPixbuf::new();
+            cell_renderer_icon.set_property_icon_name(Some("dialog-warning"));
+            column.pack_start(&cell_renderer_icon, false);
+
+            let cell_renderer_path = gtk::CellRendererText::new();
+            cell_renderer_path.set_property_ellipsize(pango::EllipsizeMode::End);
+            column.pack_start(&cell_renderer_path, true);
+            column.add_attribute(&cell_renderer_path, "text", 0);
+
+            paths_tree_view.append_column(&column);
+        }
+        let paths_scrolled_window = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        paths_scrolled_window.set_size_request(-1, 80);
+        paths_scrolled_window.set_shadow_type(gtk::ShadowType::In);
+        paths_scrolled_window.add(&paths_tree_view);
+        container.pack_start(&paths_scrolled_window, false, false, 0);
+
+        let sides_hbox = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+        sides_hbox.set_homogeneous(true);
+        let add_side = |title: &str| {
+            let tag_table = gtk::TextTagTable::new();
+            let tag = gtk::TextTag::new(Some(TAG_MONOSPACE));
+            tag.set_property_family(Some("monospace"));
+            tag_table.add(&tag);
+
+            let text_view = gtk::TextView::new_with_buffer(&gtk::TextBuffer::new(Some(&tag_table)));
+            text_view.set_editable(false);
+            let scrolled_window = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+            scrolled_window.set_size_request(-1, 160);
+            scrolled_window.set_shadow_type(gtk::ShadowType::In);
+            scrolled_window.add(&text_view);
+
+            let vbox = gtk::Box::new(gtk::Orientation::Vertical, 2);
+            let label = gtk::Label::new(None);
+            label.set_markup(&format!("<b>{}</b>", title));
+            label.set_halign(gtk::Align::Start);
+            vbox.pack_start(&label, false, false, 0);
+            vbox.pack_start(&scrolled_window, true, true, 0);
+            sides_hbox.pack_start(&vbox, true, true, 0);
+
+            text_view
+        };
+        let ours_text_view = add_side("Ours (HEAD)");
+        let base_text_view = add_side("Base");
+        let theirs_text_view = add_side("Theirs");
+        container.pack_start(&sides_hbox, true, true, 0);
+
+        let resolution_hbox = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+        let resolution_buttons: Vec<(ConflictResolution, gtk::Button)> = [
+            (ConflictResolution::TakeOurs, "Take ours", "Replace the file with the HEAD version and stage it"),
+            (ConflictResolution::TakeTheirs, "Take theirs", "Replace the file with the version being applied and stage it"),
+            (ConflictResolution::MarkResolved, "Mark resolved", "Stage the file as edited in the working tree"),
+        ].iter().map(|(resolution, title, tooltip)| {
+            let button = gtk::Button::new_with_label(title);
+            button.set_tooltip_text(Some(tooltip));
+            resolution_hbox.pack_start(&button, false, false, 0);
+            (*resolution, button)
+        }).collect();
+        container.pack_start(&resolution_hbox, false, false, 0);
+
+        let conflicts_view = Rc::new(ConflictsView {
+            container,
+            title_label,
+            paths_list_store,
+            paths_tree_view,
+            base_text_view,
+            ours_text_view,
+            theirs_text_view,
+            resolution_buttons,
+            continue_button,
+            abort_button,
+        });
+
+        conflicts_view.paths_tree_view.get_selection().connect_changed(capture!(conflicts_view; move |_| {
+            conflicts_view.update_resolution_buttons();
+        }));
+        conflicts_view.update_resolution_buttons();
+
+        conflicts_view
+    }
+
+    pub fn widget(&self) -> &gtk::Box {
+        &self.container
+    }
+
+    /// Shows the conflicts of an operation; `title` describes the operation.
+    /// Keeps the selected file if it still has conflicts.
+    pub fn set_conflicts(&self, title: &str, paths: &[String]) {
+        self.title_label.set_markup(&format!(
+            "<b>{}</b> \u{2014} {}",
+            glib::markup_escape_text(title),
+            match paths.len() {
+                0 => "all conflicts are resolved".to_owned(),
+                1 => "1 file with conflicts:".to_owned(),
+                count => format!("{} files with conflicts:", count),
+            }
+        ));
+
+        let selected = self.selected_path();
+        self.paths_list_store.clear();
+        for path in paths {
+            let iter = self.paths_list_store.insert_with_values(None, &[0], &[path]);
+            if Some(path) == selected.as_ref() {
+                self.paths_tree_view.get_selection().select_iter(&iter);
+            }
+        }
+        if self.selected_path().is_none() {
+            self.show_sides(&ConflictSides::default());
+        }
+    }
+
+    pub fn selected_path(&self) -> Option<String> {
+        let (model, iter) = self.paths_tree_view.get_selection().get_selected()?;
+        model.get_value(&iter, 0).get::<String>()
+    }
+
+    /// Shows the versions of the selected file
+    pub fn show_sides(&self, sides: &ConflictSides) {
+        let set_text = |text_view: &gtk::TextView, content: &Option<Vec<u8>>| {
+            let text = match content {
+                Some(content) if content.contains(&0) => "(binary file)".to_owned(),
+                Some(content) => String::from_utf8_lossy(content).to_string(),
+                None => "(no file)".to_owned(),
+            };
+            let buffer = text_view.get_buffer().unwrap();
+            buffer.set_text(&text);
+            buffer.apply_tag_by_name(TAG_MONOSPACE, &buffer.get_start_iter(), &buffer.get_end_iter());
+        };
+        set_text(&self.base_text_view, &sides.base);
+        set_text(&self.ours_text_view, &sides.ours);
+        set_text(&self.theirs_text_view, &sides.theirs);
+    }
+
+    /// Called with the path of the newly selected file
+    pub fn connect_path_selected<F: Fn(String) + 'static>(self: &Rc<Self>, f: F) {
+        let conflicts_view = self.clone();
+        self.paths_tree_view.get_selection().connect_changed(move |_| {
+            if let Some(path) = conflicts_view.selected_path() {
+                f(path);
+            }
+        });
+    }
+
+    /// Called with the selected file and the way to resolve it
+    pub fn connect_resolve<F: Fn(String, ConflictResolution) + 'static>(self: &Rc<Self>, f: F) {
+        let f = Rc::new(f);
+        for (resolution, button) in self.resolution_buttons.iter() {
+            button.connect_clicked(capture!(conflicts_view = *self, f, resolution = *resolution; move |_| {
+                if let Some(path) = conflicts_view.selected_path() {
+                    f(path, resolution);
+                }
+            }));
+        }
+    }
+
+    pub fn connect_continue<F: Fn() + 'static>(&self, f: F) {
+        self.continue_button.connect_clicked(move |_| f());
+    }
+
+    pub fn connect_abort<F: Fn() + 'static>(&self, f: F) {
+        self.abort_button.connect_clicked(move |_| f());
+    }
+
+    fn update_resolution_buttons(&self) {
+        let has_selection = self.selected_path().is_some();
+        for (_, button) in self.resolution_buttons.iter() {
+            button.set_sensitive(has_selection);
+        }
+    }
+}
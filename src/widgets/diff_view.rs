@@ -0,0 +1,512 @@
+use gtk::prelude::*;
+use glib::translate::ToGlib;
+
+use std::cell::{Cell, RefCell};
+use std::ops::Range;
+use std::rc::Rc;
+
+use crate::file_diff::{FileDiff, DiffLine, DiffLineKind};
+use crate::word_diff::{Tokenizer, hunk_changed_ranges};
+use crate::syntax_highlight::TokenClass;
+
+const TAG_MONOSPACE: &str = "monospace";
+const TAG_FILE_HEADER: &str = "file-header";
+const TAG_HUNK_HEADER: &str = "hunk-header";
+const TAG_LINE_NUMBER: &str = "line-number";
+const TAG_ADDED: &str = "added";
+const TAG_REMOVED: &str = "removed";
+const TAG_NO_NEWLINE: &str = "no-newline";
+const TAG_FILLER: &str = "filler";
+const TAG_ADDED_WORD: &str = "added-word";
+const TAG_REMOVED_WORD: &str = "removed-word";
+const TAG_SYNTAX_COMMENT: &str = "syntax-comment";
+const TAG_SYNTAX_STRING: &str = "syntax-string";
+const TAG_SYNTAX_KEYWORD: &str = "syntax-keyword";
+const TAG_SYNTAX_NUMBER: &str = "syntax-number";
+
+const LAYOUT_UNIFIED: &str = "unified";
+const LAYOUT_SPLIT: &str = "split";
+
+/// Hunk and line of the shown diff that a row of a text view belongs to
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct DiffPosition {
+    pub hunk: usize,
+    /// `None` for the hunk header
+    pub line: Option<usize>,
+}
+
+enum DiffViewContent {
+    Message(String),
+    Diff(FileDiff),
+}
+
+/// Read-only view that shows the hunks of a single file either as a unified diff
+/// or as two side-by-side columns with the old and the new version.
+///
+/// Changed parts of paired removed/added lines are highlighted using the selected tokenizer.
+/// The selected layout and tokenizer are kept when another diff is shown.
+pub struct DiffView {
+    container: gtk::Box,
+    stack: gtk::Stack,
+    tokenizer_combo_box: gtk::ComboBoxText,
+    tokenizer: Cell<Option<Tokenizer>>,
+    unified_text_view: gtk::TextView,
+    old_text_view: gtk::TextView,
+    new_text_view: gtk::TextView,
+    content: RefCell<DiffViewContent>,
+    /// Diff positions of the rows of the unified (or the combined) and the side-by-side views
+    unified_rows: RefCell<Vec<Option<DiffPosition>>>,
+    old_rows: RefCell<Vec<Option<DiffPosition>>>,
+    new_rows: RefCell<Vec<Option<DiffPosition>>>,
+}
+
+impl DiffView {
+    pub fn new() -> Rc<DiffView> {
+        let tag_table = gtk::TextTagTable::new();
+
+        {
+            let add_tag = |name: &str, foreground: Option<&str>, background: Option<&str>| {
+                let tag = gtk::TextTag::new(Some(name));
+                if foreground.is_some() {
+                    tag.set_property_foreground(foreground);
+                }
+                if background.is_some() {
+                    tag.set_property_paragraph_background(background);
+                }
+                tag_table.add(&tag);
+                tag
+            };
+
+            add_tag(TAG_MONOSPACE, None, None).set_property_family(Some("monospace"));
+            add_tag(TAG_FILE_HEADER, Some("#000000"), Some("#e5e8e8")).set_property_weight(pango::Weight::Bold.to_glib());
+            add_tag(TAG_HUNK_HEADER, Some("#2874a6"), Some("#eaf2f8"));
+            add_tag(TAG_LINE_NUMBER, Some("#909497"), None);
+            add_tag(TAG_ADDED, None, Some("#e6ffed"));
+            add_tag(TAG_REMOVED, None, Some("#ffeef0"));
+            add_tag(TAG_NO_NEWLINE, Some("#909497"), None).set_property_style(pango::Style::Italic);
+            add_tag(TAG_FILLER, None, Some("#f2f3f4"));
+            add_syntax_tags(&tag_table);
+
+            let word_tag = gtk::TextTag::new(Some(TAG_ADDED_WORD));
+            word_tag.set_property_background(Some("#acf2bd"));
+            tag_table.add(&word_tag);
+
+            let word_tag = gtk::TextTag::new(Some(TAG_REMOVED_WORD));
+            word_tag.set_property_background(Some("#fdb8c0"));
+            tag_table.add(&word_tag);
+        }
+
+        let new_text_view = || {
+            let text_view = gtk::TextView::new_with_buffer(&gtk::TextBuffer::new(Some(&tag_table)));
+            text_view.set_editable(false);
+            text_view.set_cursor_visible(false);
+            text_view
+        };
+
+        let unified_text_view = new_text_view();
+        let old_text_view = new_text_view();
+        let new_text_view = new_text_view();
+
+        let stack = gtk::Stack::new();
+
+        {
+            let scrolled_window = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+            scrolled_window.add(&unified_text_view);
+            stack.add_titled(&scrolled_window, LAYOUT_UNIFIED, "Unified");
+        }
+
+        {
+            let old_scrolled_window = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+            old_scrolled_window.add(&old_text_view);
+
+            // Both sides share the vertical adjustment, so they are always scrolled to the same rows
+            let new_scrolled_window = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, old_scrolled_window.get_vadjustment().as_ref());
+            new_scrolled_window.add(&new_text_view);
+
+            let hpane = gtk::Paned::new(gtk::Orientation::Horizontal);
+            hpane.pack1(&old_scrolled_window, true, false);
+            hpane.pack2(&new_scrolled_window, true, false);
+            stack.add_titled(&hpane, LAYOUT_SPLIT, "Side by side");
+        }
+
+        let stack_switcher = gtk::StackSwitcher::new();
+        stack_switcher.set_stack(Some(&stack));
+
+        let tokenizer_combo_box = gtk::ComboBoxText::new();
+        tokenizer_combo_box.append(Some(""), "No word highlighting");
+        for tokenizer in Tokenizer::ALL.iter() {
+            tokenizer_combo_box.append(Some(tokenizer.id()), tokenizer.title());
+        }
+        tokenizer_combo_box.set_active_id(Some(Tokenizer::Words.id()));
+
+        let toolbar_hbox = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+        toolbar_hbox.set_border_width(2);
+        toolbar_hbox.pack_start(&tokenizer_combo_box, false, false, 0);
+        toolbar_hbox.pack_end(&stack_switcher, false, false, 0);
+
+        let container = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        container.pack_start(&toolbar_hbox, false, false, 0);
+        container.pack_start(&stack, true, true, 0);
+
+        let diff_view = Rc::new(DiffView {
+            container,
+            stack,
+            tokenizer_combo_box,
+            tokenizer: Cell::new(Some(Tokenizer::Words)),
+            unified_text_view,
+            old_text_view,
+            new_text_view,
+            content: RefCell::new(DiffViewContent::Message(String::new())),
+            unified_rows: RefCell::new(Vec::new()),
+            old_rows: RefCell::new(Vec::new()),
+            new_rows: RefCell::new(Vec::new()),
+        });
+
+        diff_view.stack.connect_property_visible_child_name_notify(capture!(diff_view; move |_| {
+            diff_view.render();
+        }));
+
+        diff_view.tokenizer_combo_box.connect_changed(capture!(diff_view; move |combo_box| {
+            let tokenizer = combo_box.get_active_id().and_then(|id| Tokenizer::from_id(&id));
+            diff_view.tokenizer.set(tokenizer);
+            diff_view.render();
+        }));
+
+        diff_view
+    }
+
+    pub fn widget(&self) -> &gtk::Box {
+        &self.container
+    }
+
+    pub fn show_message(&self, message: &str) {
+        self.content.replace(DiffViewContent::Message(message.to_owned()));
+        self.render();
+    }
+
+    pub fn show_diff(&self, diff: FileDiff) {
+        self.content.replace(DiffViewContent::Diff(diff));
+        self.render();
+    }
+
+    /// The diff being shown, if any
+    pub fn diff(&self) -> Option<FileDiff> {
+        match &*self.content.borrow() {
+            DiffViewContent::Diff(diff) => Some(diff.clone()),
+            DiffViewContent::Message(_) => None,
+        }
+    }
+
+    /// Positions of the rows that are selected in the visible (or, in the side-by-side layout, the focused) text view.
+    /// Without a selection, the row that was clicked last is used.
+    pub fn selected_positions(&self) -> Vec<DiffPosition> {
+        let (text_view, rows) = if !self.is_split() {
+            (&self.unified_text_view, &self.unified_rows)
+        } else if self.new_text_view.has_focus() {
+            (&self.new_text_view, &self.new_rows)
+        } else {
+            (&self.old_text_view, &self.old_rows)
+        };
+
+        let buffer = text_view.get_buffer().unwrap();
+        let (start, end) = buffer.get_selection_bounds()
+            .unwrap_or_else(|| {
+                let cursor = buffer.get_iter_at_mark(&buffer.get_insert().unwrap());
+                (cursor.clone(), cursor)
+            });
+        let start_row = start.get_line();
+        // A selection that ends at the start of a row does not include that row
+        let end_row = if end.get_line() > start_row && end.starts_line() { end.get_line() - 1 } else { end.get_line() };
+
+        let rows = rows.borrow();
+        (start_row..=end_row)
+            .filter_map(|row| rows.get(row as usize).cloned().flatten())
+            .collect()
+    }
+
+    fn is_split(&self) -> bool {
+        self.stack.get_visible_child_name().map(|name| name == LAYOUT_SPLIT).unwrap_or(false)
+    }
+
+    fn render(&self) {
+        let is_split = self.is_split();
+        let content = self.content.borrow();
+
+        match (&*content, is_split) {
+            (DiffViewContent::Message(message), false) => {
+                self.unified_text_view.get_buffer().unwrap().set_text(message);
+                self.unified_rows.borrow_mut().clear();
+            },
+            (DiffViewContent::Message(message), true) => {
+                self.old_text_view.get_buffer().unwrap().set_text(message);
+                self.new_text_view.get_buffer().unwrap().set_text("");
+                self.old_rows.borrow_mut().clear();
+                self.new_rows.borrow_mut().clear();
+            },
+            (DiffViewContent::Diff(diff), false) => {
+                let text = render_unified(diff, self.tokenizer.get());
+                text.apply(&self.unified_text_view.get_buffer().unwrap());
+                self.unified_rows.replace(text.rows);
+            },
+            (DiffViewContent::Diff(diff), true) if diff.combined_parents > 0 => {
+                // Combined diffs have more than two sides, so they are always shown as a unified diff
+                let text = render_unified(diff, self.tokenizer.get());
+                text.apply(&self.old_text_view.get_buffer().unwrap());
+                self.old_rows.replace(text.rows);
+                self.new_text_view.get_buffer().unwrap().set_text("Combined diffs are shown in the unified layout");
+                self.new_rows.borrow_mut().clear();
+            },
+            (DiffViewContent::Diff(diff), true) => {
+                let (old_text, new_text) = render_split(diff, self.tokenizer.get());
+                old_text.apply(&self.old_text_view.get_buffer().unwrap());
+                new_text.apply(&self.new_text_view.get_buffer().unwrap());
+                self.old_rows.replace(old_text.rows);
+                self.new_rows.replace(new_text.rows);
+            },
+        }
+    }
+}
+
+/// Text with tags to be applied to ranges of it, accumulated before being put into a `TextBuffer` at once
+#[derive(Default)]
+struct TaggedText {
+    text: String,
+    len: i32,
+    tags: Vec<(&'static str, i32, i32)>,
+    /// Diff position of each complete row of the text
+    rows: Vec<Option<DiffPosition>>,
+    /// Diff position of the rows being pushed
+    position: Option<DiffPosition>,
+}
+
+impl TaggedText {
+    fn push(&mut self, s: &str, tag: Option<&'static str>) {
+        let len = s.chars().count() as i32;
+        if let Some(tag) = tag {
+            self.tags.push((tag, self.len, self.len + len));
+        }
+        self.text.push_str(s);
+        self.len += len;
+        for _ in s.matches('\n') {
+            self.rows.push(self.position);
+        }
+    }
+
+    fn apply(&self, buffer: &gtk::TextBuffer) {
+        buffer.set_text(&self.text);
+        buffer.apply_tag_by_name(TAG_MONOSPACE, &buffer.get_start_iter(), &buffer.get_end_iter());
+        for (tag, start, end) in self.tags.iter() {
+            buffer.apply_tag_by_name(tag, &buffer.get_iter_at_offset(*start), &buffer.get_iter_at_offset(*end));
+        }
+    }
+}
+
+fn file_header(diff: &FileDiff) -> String {
+    match (&diff.old_path, &diff.new_path) {
+        (Some(old_path), Some(new_path)) if old_path != new_path => format!("{} \u{2192} {}\n", old_path, new_path),
+        (_, Some(path)) | (Some(path), None) => format!("{}\n", path),
+        (None, None) => "(none)\n".to_owned(),
+    }
+}
+
+fn diff_notice(diff: &FileDiff) -> Option<&'static str> {
+    if diff.is_binary {
+        Some("Binary files differ\n")
+    } else if diff.hunks.is_empty() {
+        Some("No changes in file content\n")
+    } else {
+        None
+    }
+}
+
+fn line_number(lineno: Option<u32>) -> String {
+    lineno.map(|n| n.to_string()).unwrap_or_default()
+}
+
+fn line_prefix_and_tag(line: &DiffLine) -> (&str, Option<&'static str>) {
+    let (prefix, tag) = line_kind_prefix_and_tag(line);
+    if line.markers.is_empty() {
+        (prefix, tag)
+    } else {
+        (&line.markers, tag)
+    }
+}
+
+fn line_kind_prefix_and_tag(line: &DiffLine) -> (&'static str, Option<&'static str>) {
+    match line.kind {
+        DiffLineKind::Context => (" ", None),
+        DiffLineKind::Added => ("+", Some(TAG_ADDED)),
+        DiffLineKind::Removed => ("-", Some(TAG_REMOVED)),
+        DiffLineKind::NoNewline => ("\\", Some(TAG_NO_NEWLINE)),
+    }
+}
+
+fn word_tag(line: &DiffLine) -> &'static str {
+    match line.kind {
+        DiffLineKind::Removed => TAG_REMOVED_WORD,
+        _ => TAG_ADDED_WORD,
+    }
+}
+
+/// Adds the tags of syntax highlighting to `tag_table`, for text views showing highlighted code
+pub fn add_syntax_tags(tag_table: &gtk::TextTagTable) {
+    let add_tag = |name: &str, foreground: &str| {
+        let tag = gtk::TextTag::new(Some(name));
+        tag.set_property_foreground(Some(foreground));
+        tag_table.add(&tag);
+        tag
+    };
+
+    add_tag(TAG_SYNTAX_COMMENT, "#7f8c8d").set_property_style(pango::Style::Italic);
+    add_tag(TAG_SYNTAX_STRING, "#a04000");
+    add_tag(TAG_SYNTAX_KEYWORD, "#1f618d").set_property_weight(pango::Weight::Bold.to_glib());
+    add_tag(TAG_SYNTAX_NUMBER, "#7d3c98");
+}
+
+/// Name of the tag added by `add_syntax_tags` for a class of tokens
+pub fn syntax_tag(token_class: TokenClass) -> &'static str {
+    match token_class {
+        TokenClass::Comment => TAG_SYNTAX_COMMENT,
+        TokenClass::String => TAG_SYNTAX_STRING,
+        TokenClass::Keyword => TAG_SYNTAX_KEYWORD,
+        TokenClass::Number => TAG_SYNTAX_NUMBER,
+    }
+}
+
+/// Pushes the prefix and the content of `line`, tagging the `changed` char ranges of the content
+fn push_line_content(text: &mut TaggedText, line: &DiffLine, changed: &[Range<usize>]) {
+    let (prefix, tag) = line_prefix_and_tag(line);
+    let line_start = text.len;
+    text.push(&format!("{}{}\n", prefix, line.content), tag);
+
+    let content_start = line_start + prefix.chars().count() as i32;
+    for (range, token_class) in line.highlights.iter() {
+        text.tags.push((syntax_tag(*token_class), content_start + range.start as i32, content_start + range.end as i32));
+    }
+    for range in changed {
+        text.tags.push((word_tag(line), content_start + range.start as i32, content_start + range.end as i32));
+    }
+}
+
+fn changed_ranges_of_hunk(lines: &[DiffLine], tokenizer: Option<Tokenizer>) -> Vec<Vec<Range<usize>>> {
+    match tokenizer {
+        Some(tokenizer) => hunk_changed_ranges(lines, tokenizer),
+        None => vec![Vec::new(); lines.len()],
+    }
+}
+
+fn render_unified(diff: &FileDiff, tokenizer: Option<Tokenizer>) -> TaggedText {
+    let mut text = TaggedText::default();
+
+    text.push(&file_header(diff), Some(TAG_FILE_HEADER));
+    if let Some(notice) = diff_notice(diff) {
+        text.push(notice, Some(TAG_NO_NEWLINE));
+    }
+
+    for (hunk_idx, hunk) in diff.hunks.iter().enumerate() {
+        text.position = Some(DiffPosition { hunk: hunk_idx, line: None });
+        text.push(&format!("{}\n", hunk.header), Some(TAG_HUNK_HEADER));
+
+        let changed_ranges = changed_ranges_of_hunk(&hunk.lines, tokenizer);
+
+        for (line_idx, (line, changed)) in hunk.lines.iter().zip(changed_ranges.iter()).enumerate() {
+            text.position = Some(DiffPosition { hunk: hunk_idx, line: Some(line_idx) });
+            text.push(
+                &format!("{:>5} {:>5} ", line_number(line.old_lineno), line_number(line.new_lineno)),
+                Some(TAG_LINE_NUMBER)
+            );
+
+            push_line_content(&mut text, line, changed);
+        }
+    }
+
+    text
+}
+
+/// Lays out the hunks as pairs of old/new lines. Runs of removed lines are paired with the following
+/// runs of added lines; the shorter side of each pair of runs is padded with filler rows.
+fn render_split(diff: &FileDiff, tokenizer: Option<Tokenizer>) -> (TaggedText, TaggedText) {
+    let mut old_text = TaggedText::default();
+    let mut new_text = TaggedText::default();
+
+    let header = file_header(diff);
+    old_text.push(&header, Some(TAG_FILE_HEADER));
+    new_text.push(&header, Some(TAG_FILE_HEADER));
+    if let Some(notice) = diff_notice(diff) {
+        old_text.push(notice, Some(TAG_NO_NEWLINE));
+        new_text.push(notice, Some(TAG_NO_NEWLINE));
+    }
+
+    let push_line = |text: &mut TaggedText, line: Option<(usize, &DiffLine, &[Range<usize>])>, lineno: Option<u32>, hunk_idx: usize| {
+        match line {
+            Some((line_idx, line, changed)) => {
+                text.position = Some(DiffPosition { hunk: hunk_idx, line: Some(line_idx) });
+                text.push(&format!("{:>5} ", line_number(lineno)), Some(TAG_LINE_NUMBER));
+                push_line_content(text, line, changed);
+            },
+            None => {
+                text.position = None;
+                text.push("\n", Some(TAG_FILLER));
+            },
+        }
+    };
+
+    for (hunk_idx, hunk) in diff.hunks.iter().enumerate() {
+        old_text.position = Some(DiffPosition { hunk: hunk_idx, line: None });
+        new_text.position = Some(DiffPosition { hunk: hunk_idx, line: None });
+        old_text.push(&format!("{}\n", hunk.header), Some(TAG_HUNK_HEADER));
+        new_text.push(&format!("{}\n", hunk.header), Some(TAG_HUNK_HEADER));
+
+        let changed_ranges = changed_ranges_of_hunk(&hunk.lines, tokenizer);
+
+        let mut removed = Vec::<(usize, &DiffLine, &[Range<usize>])>::new();
+        let mut added = Vec::<(usize, &DiffLine, &[Range<usize>])>::new();
+
+        let mut flush = |removed: &mut Vec<(usize, &DiffLine, &[Range<usize>])>, added: &mut Vec<(usize, &DiffLine, &[Range<usize>])>| {
+            for idx in 0..removed.len().max(added.len()) {
+                let old_line = removed.get(idx).cloned();
+                let new_line = added.get(idx).cloned();
+                push_line(&mut old_text, old_line, old_line.and_then(|(_, line, _)| line.old_lineno), hunk_idx);
+                push_line(&mut new_text, new_line, new_line.and_then(|(_, line, _)| line.new_lineno), hunk_idx);
+            }
+            removed.clear();
+            added.clear();
+        };
+
+        let mut last_kind = DiffLineKind::Context;
+        for (line_idx, (line, changed)) in hunk.lines.iter().zip(changed_ranges.iter()).enumerate() {
+            let line = (line_idx, line, &changed[..]);
+            match line.1.kind {
+                DiffLineKind::Removed => {
+                    if !added.is_empty() {
+                        flush(&mut removed, &mut added);
+                    }
+                    removed.push(line);
+                },
+                DiffLineKind::Added => added.push(line),
+                DiffLineKind::NoNewline => match last_kind {
+                    DiffLineKind::Removed => removed.push(line),
+                    DiffLineKind::Added => added.push(line),
+                    _ => {
+                        flush(&mut removed, &mut added);
+                        removed.push(line);
+                        added.push(line);
+                        flush(&mut removed, &mut added);
+                    },
+                },
+                DiffLineKind::Context => {
+                    flush(&mut removed, &mut added);
+                    removed.push(line);
+                    added.push(line);
+                    flush(&mut removed, &mut added);
+                },
+            }
+            if line.1.kind != DiffLineKind::NoNewline {
+                last_kind = line.1.kind;
+            }
+        }
+        flush(&mut removed, &mut added);
+    }
+
+    (old_text, new_text)
+}
@@ -0,0 +1,9 @@
+pub mod diff_view;
+pub mod commit_panel;
+pub mod refs_sidebar;
+pub mod conflicts_view;
+pub mod rebase_editor;
+pub mod reflog_view;
+pub mod blame_view;
+pub mod tree_browser;
+pub mod commit_graph_renderer;
@@ -0,0 +1,219 @@
+use gtk::prelude::*;
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use crate::async_ui::promise::Promise;
+use crate::rebase::{RebaseAction, TodoItem};
+
+/// Dialog to edit the todo list of an interactive rebase: the order of the commits, the action for each one
+/// and the new messages of reworded commits
+pub struct RebaseEditor {
+    dialog: gtk::Dialog,
+    list_store: gtk::ListStore,
+    tree_view: gtk::TreeView,
+    message_text_view: gtk::TextView,
+    up_button: gtk::Button,
+    down_button: gtk::Button,
+    /// Set while the message of the selected item is loaded into the text view
+    loading_message: Cell<bool>,
+}
+
+impl RebaseEditor {
+    pub fn new(parent: &gtk::Window, title: &str, todo: &[TodoItem]) -> Rc<RebaseEditor> {
+        let dialog = gtk::Dialog::new_with_buttons(
+            Some(title),
+            Some(parent),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            &[("Cancel", gtk::ResponseType::Cancel), ("Rebase", gtk::ResponseType::Accept)]
+        );
+        dialog.set_default_size(700, 500);
+
+        let list_store = gtk::ListStore::new(&[
+            gtk::Type::String, // Action, see RebaseAction::title
+            gtk::Type::String, // Commit ID
+            gtk::Type::String, // Abbreviated commit ID
+            gtk::Type::String, // Summary
+            gtk::Type::String, // Message of the new commit
+        ]);
+        for item in todo {
+            list_store.insert_with_values(
+                None,
+                &[0, 1, 2, 3, 4],
+                &[&item.action.title(), &item.commit.to_string(), &format!("{:.8}", item.commit), &item.summary, &item.message]
+            );
+        }
+
+        let tree_view = gtk::TreeView::new_with_model(&list_store);
+        tree_view.set_reorderable(true);
+
+        let actions_list_store = gtk::ListStore::new(&[
+            gtk::Type::String, // Action title
+        ]);
+        for action in RebaseAction::ALL.iter() {
+            actions_list_store.insert_with_values(None, &[0], &[&action.title()]);
+        }
+        let action_cell_renderer = gtk::CellRendererCombo::new();
+        action_cell_renderer.set_property_model(Some(actions_list_store.upcast_ref()));
+        action_cell_renderer.set_property_text_column(0);
+        action_cell_renderer.set_property_has_entry(false);
+        action_cell_renderer.set_property_editable(true);
+
+        let columns: [(&str, gtk::CellRenderer, i32); 3] = [
+            ("Action", action_cell_renderer.clone().upcast(), 0),
+            ("Commit", gtk::CellRendererText::new().upcast(), 2),
+            ("Summary", gtk::CellRendererText::new().upcast(), 3),
+        ];
+        for (title, cell_renderer, column_idx) in columns.iter() {
+            let column = gtk::TreeViewColumn::new();
+            column.set_title(title);
+            column.pack_start(cell_renderer, true);
+            column.add_attribute(cell_renderer, "text", *column_idx);
+            tree_view.append_column(&column);
+        }
+
+        let scrolled_window = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        scrolled_window.set_shadow_type(gtk::ShadowType::In);
+        scrolled_window.add(&tree_view);
+
+        let up_button = gtk::Button::new_with_label("Move up");
+        let down_button = gtk::Button::new_with_label("Move down");
+        let buttons_hbox = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+        buttons_hbox.pack_start(&up_button, false, false, 0);
+        buttons_hbox.pack_start(&down_button, false, false, 0);
+        let hint_label = gtk::Label::new(Some("Commits are applied from top to bottom; drag rows to reorder them"));
+        hint_label.set_halign(gtk::Align::End);
+        buttons_hbox.pack_end(&hint_label, false, false, 0);
+
+        let message_label = gtk::Label::new(Some("Message of the reworded commit:"));
+        message_label.set_halign(gtk::Align::Start);
+        let message_text_view = gtk::TextView::new();
+        let message_scrolled_window = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        message_scrolled_window.set_size_request(-1, 100);
+        message_scrolled_window.set_shadow_type(gtk::ShadowType::In);
+        message_scrolled_window.add(&message_text_view);
+
+        let content_area = dialog.get_content_area();
+        content_area.set_spacing(4);
+        content_area.set_border_width(8);
+        content_area.pack_start(&scrolled_window, true, true, 0);
+        content_area.pack_start(&buttons_hbox, false, false, 0);
+        content_area.pack_start(&message_label, false, false, 0);
+        content_area.pack_start(&message_scrolled_window, false, false, 0);
+
+        let editor = Rc::new(RebaseEditor {
+            dialog,
+            list_store,
+            tree_view,
+            message_text_view,
+            up_button,
+            down_button,
+            loading_message: Cell::new(false),
+        });
+
+        action_cell_renderer.connect_edited(capture!(editor; move |_, path, new_text| {
+            if let (Some(iter), Some(action)) = (editor.list_store.get_iter(&path), RebaseAction::from_title(new_text)) {
+                editor.list_store.set_value(&iter, 0, &action.title().to_value());
+                editor.load_selected_message();
+            }
+        }));
+
+        editor.tree_view.get_selection().connect_changed(capture!(editor; move |_| {
+            editor.load_selected_message();
+        }));
+
+        editor.message_text_view.get_buffer().unwrap().connect_changed(capture!(editor; move |buffer| {
+            if editor.loading_message.get() {
+                return;
+            }
+            if let Some((_, iter)) = editor.tree_view.get_selection().get_selected() {
+                let message = buffer.get_text(&buffer.get_start_iter(), &buffer.get_end_iter(), false)
+                    .map(|text| text.to_string())
+                    .unwrap_or_default();
+                editor.list_store.set_value(&iter, 4, &message.to_value());
+            }
+        }));
+
+        editor.up_button.connect_clicked(capture!(editor; move |_| {
+            editor.move_selected(true);
+        }));
+        editor.down_button.connect_clicked(capture!(editor; move |_| {
+            editor.move_selected(false);
+        }));
+
+        editor.load_selected_message();
+
+        editor
+    }
+
+    /// Shows the dialog; resolves to the edited todo list, or to `None` if the rebase is cancelled
+    pub fn run(self: &Rc<Self>) -> Promise<Option<Vec<TodoItem>>> {
+        let (promise, resolver) = Promise::new();
+
+        self.dialog.connect_response(capture!(resolver, editor = *self; move |dialog, response| {
+            let todo = if response == gtk::ResponseType::Accept { Some(editor.todo()) } else { None };
+            dialog.destroy();
+            resolver.resolve(todo);
+        }));
+
+        self.dialog.show_all();
+
+        promise
+    }
+
+    fn todo(&self) -> Vec<TodoItem> {
+        let mut todo = Vec::new();
+        let iter = match self.list_store.get_iter_first() {
+            Some(iter) => iter,
+            None => return todo,
+        };
+        loop {
+            let get_string = |column| self.list_store.get_value(&iter, column).get::<String>().unwrap_or_default();
+            let action = RebaseAction::from_title(&get_string(0)).unwrap_or(RebaseAction::Pick);
+            if let Ok(commit) = git2::Oid::from_str(&get_string(1)) {
+                todo.push(TodoItem {
+                    action,
+                    commit,
+                    summary: get_string(3),
+                    message: get_string(4),
+                });
+            }
+            if !self.list_store.iter_next(&iter) {
+                return todo;
+            }
+        }
+    }
+
+    /// Shows the message of the selected item, which can be edited only if the commit is reworded
+    fn load_selected_message(&self) {
+        let selected = self.tree_view.get_selection().get_selected();
+
+        let (message, is_reword) = match &selected {
+            Some((_, iter)) => (
+                self.list_store.get_value(iter, 4).get::<String>().unwrap_or_default(),
+                self.list_store.get_value(iter, 0).get::<String>().as_deref() == Some(RebaseAction::Reword.title()),
+            ),
+            None => (String::new(), false),
+        };
+
+        self.loading_message.set(true);
+        self.message_text_view.get_buffer().unwrap().set_text(&message);
+        self.loading_message.set(false);
+        self.message_text_view.set_sensitive(is_reword);
+
+        self.up_button.set_sensitive(selected.is_some());
+        self.down_button.set_sensitive(selected.is_some());
+    }
+
+    fn move_selected(&self, up: bool) {
+        let (_, iter) = match self.tree_view.get_selection().get_selected() {
+            Some(selected) => selected,
+            None => return,
+        };
+        let other = iter.clone();
+        let moved = if up { self.list_store.iter_previous(&other) } else { self.list_store.iter_next(&other) };
+        if moved {
+            self.list_store.swap(&iter, &other);
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use gtk::prelude::*;
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use crate::reflog::ReflogEntry;
+
+/// Reference whose reflog is shown unless another one is chosen
+pub const HEAD_REF: &str = "HEAD";
+
+/// List of the reflog entries of HEAD or of a branch, to find commits that are not reachable anymore
+pub struct ReflogView {
+    container: gtk::Box,
+    ref_combo_box: gtk::ComboBoxText,
+    list_store: gtk::ListStore,
+    tree_view: gtk::TreeView,
+    /// Set while the choice of references is rebuilt, so that restoring the active one is not reported as a change
+    updating: Cell<bool>,
+}
+
+impl ReflogView {
+    pub fn new() -> Rc<ReflogView> {
+        let header_hbox = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+        let label = gtk::Label::new(None);
+        label.set_markup("<b>Reflog</b>");
+        let ref_combo_box = gtk::ComboBoxText::new();
+        ref_combo_box.append(Some(HEAD_REF), HEAD_REF);
+        ref_combo_box.set_active_id(Some(HEAD_REF));
+        header_hbox.pack_start(&label, false, false, 0);
+        header_hbox.pack_start(&ref_combo_box, true, true, 0);
+
+        let list_store = gtk::ListStore::new(&[
+            gtk::Type::String, // New commit ID
+            gtk::Type::String, // Selector, e.g. HEAD@{0}
+            gtk::Type::String, // Old and new abbreviated commit IDs
+            gtk::Type::String, // Message
+            gtk::Type::String, // Timestamp as string
+        ]);
+
+        let tree_view = gtk::TreeView::new_with_model(&list_store);
+        tree_view.set_tooltip_column(3);
+        for (title, column_idx) in [("Entry", 1), ("Old \u{2192} new", 2), ("Message", 3), ("Date", 4)].iter() {
+            let cell_renderer = gtk::CellRendererText::new();
+            if *column_idx == 3 {
+                cell_renderer.set_property_ellipsize(pango::EllipsizeMode::End);
+            }
+            let column = gtk::TreeViewColumn::new();
+            column.set_title(title);
+            column.set_resizable(true);
+            column.set_expand(*column_idx == 3);
+            column.pack_start(&cell_renderer, true);
+            column.add_attribute(&cell_renderer, "text", *column_idx);
+            tree_view.append_column(&column);
+        }
+
+        let scrolled_window = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        scrolled_window.add(&tree_view);
+
+        let container = gtk::Box::new(gtk::Orientation::Vertical, 2);
+        container.set_border_width(2);
+        container.pack_start(&header_hbox, false, false, 0);
+        container.pack_start(&scrolled_window, true, true, 0);
+
+        Rc::new(ReflogView {
+            container,
+            ref_combo_box,
+            list_store,
+            tree_view,
+            updating: Cell::new(false),
+        })
+    }
+
+    pub fn widget(&self) -> &gtk::Box {
+        &self.container
+    }
+
+    /// Replaces the references that can be chosen besides HEAD, given as full and short names.
+    /// Falls back to HEAD if the chosen reference does not exist anymore.
+    pub fn set_ref_names(&self, names: &[(String, String)]) {
+        let active = self.active_ref();
+
+        self.updating.set(true);
+        self.ref_combo_box.remove_all();
+        self.ref_combo_box.append(Some(HEAD_REF), HEAD_REF);
+        for (full_name, name) in names {
+            self.ref_combo_box.append(Some(full_name), name);
+        }
+        if !self.ref_combo_box.set_active_id(Some(&active)) {
+            self.ref_combo_box.set_active_id(Some(HEAD_REF));
+        }
+        self.updating.set(false);
+    }
+
+    /// Full name of the reference whose reflog is shown
+    pub fn active_ref(&self) -> String {
+        self.ref_combo_box.get_active_id()
+            .map(|id| id.to_string())
+            .unwrap_or_else(|| HEAD_REF.to_owned())
+    }
+
+    /// Chooses the reference whose reflog is shown, if it can be chosen
+    pub fn set_active_ref(&self, ref_name: &str) {
+        self.ref_combo_box.set_active_id(Some(ref_name));
+    }
+
+    /// Replaces the listed entries, keeping the selected one if it is still listed
+    pub fn set_entries(&self, entries: &[ReflogEntry]) {
+        use chrono::TimeZone;
+
+        let selected = self.tree_view.get_selection().get_selected()
+            .and_then(|(model, iter)| model.get_value(&iter, 1).get::<String>());
+
+        self.list_store.clear();
+        for entry in entries {
+            let timestamp = chrono::Utc.timestamp(entry.time.seconds(), 0)
+                .with_timezone(&chrono::FixedOffset::east(entry.time.offset_minutes() * 60));
+            let old_id = if entry.old_id.is_zero() { "(none)".to_owned() } else { format!("{:.8}", entry.old_id) };
+            let iter = self.list_store.insert_with_values(
+                None,
+                &[0, 1, 2, 3, 4],
+                &[
+                    &entry.new_id.to_string(),
+                    &entry.selector,
+                    &format!("{} \u{2192} {:.8}", old_id, entry.new_id),
+                    &entry.message,
+                    &timestamp.format("%Y-%m-%d %H:%M:%S %:z").to_string(),
+                ]
+            );
+            if selected.as_ref() == Some(&entry.selector) {
+                self.tree_view.get_selection().select_iter(&iter);
+            }
+        }
+    }
+
+    /// Called with the full name of the newly chosen reference
+    pub fn connect_ref_changed<F: Fn(String) + 'static>(self: &Rc<Self>, f: F) {
+        self.ref_combo_box.connect_changed(capture!(reflog_view = *self; move |_| {
+            if !reflog_view.updating.get() {
+                f(reflog_view.active_ref());
+            }
+        }));
+    }
+
+    /// Called with the commit that the selected entry set the reference to
+    pub fn connect_entry_selected<F: Fn(git2::Oid) + 'static>(&self, f: F) {
+        self.tree_view.get_selection().connect_changed(move |selection| {
+            let commit = selection.get_selected()
+                .and_then(|(model, iter)| model.get_value(&iter, 0).get::<String>())
+                .and_then(|id| git2::Oid::from_str(&id).ok());
+            if let Some(commit) = commit {
+                f(commit);
+            }
+        });
+    }
+}
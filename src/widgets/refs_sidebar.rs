@@ -0,0 +1,211 @@
+use gtk::prelude::*;
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use crate::refs::{RefEntry, RefKind};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RefAction {
+    /// Create a branch at the selected commit
+    NewBranch,
+    Rename,
+    /// Delete a local branch or a tag
+    Delete,
+    Checkout,
+    /// Point a tag to the selected commit
+    MoveTag,
+    /// Merge the reference into the current branch
+    Merge,
+}
+
+impl RefAction {
+    const ALL: [RefAction; 6] = [
+        RefAction::NewBranch, RefAction::Rename, RefAction::Delete, RefAction::Checkout, RefAction::MoveTag, RefAction::Merge,
+    ];
+
+    fn title(self) -> &'static str {
+        match self {
+            RefAction::NewBranch => "New branch\u{2026}",
+            RefAction::Rename => "Rename\u{2026}",
+            RefAction::Delete => "Delete",
+            RefAction::Checkout => "Checkout",
+            RefAction::MoveTag => "Move to selected commit",
+            RefAction::Merge => "Merge into current branch",
+        }
+    }
+
+    /// Whether the action applies to the selected reference
+    fn is_applicable(self, entry: Option<&RefEntry>) -> bool {
+        match self {
+            RefAction::NewBranch => true,
+            RefAction::Rename => entry.map(|entry| entry.kind == RefKind::LocalBranch).unwrap_or(false),
+            RefAction::Delete => entry.map(|entry| entry.kind != RefKind::RemoteBranch).unwrap_or(false),
+            RefAction::Checkout => entry.map(|entry| !entry.is_head).unwrap_or(false),
+            RefAction::MoveTag => entry.map(|entry| entry.kind == RefKind::Tag).unwrap_or(false),
+            RefAction::Merge => entry.map(|entry| !entry.is_head).unwrap_or(false),
+        }
+    }
+}
+
+/// Tree of local branches, remote-tracking branches (grouped by remote) and tags, with actions on them
+pub struct RefsSidebar {
+    container: gtk::Box,
+    tree_store: gtk::TreeStore,
+    tree_view: gtk::TreeView,
+    action_buttons: Vec<(RefAction, gtk::Button)>,
+    entries: RefCell<Vec<RefEntry>>,
+    /// Set while the tree is rebuilt, so that restoring the selection is not reported as a new one
+    updating: Cell<bool>,
+}
+
+impl RefsSidebar {
+    pub fn new() -> Rc<RefsSidebar> {
+        let tree_store = gtk::TreeStore::new(&[
+            gtk::Type::String, // Name as markup
+            gtk::Type::String, // Full reference name; empty for group rows
+        ]);
+
+        let tree_view = gtk::TreeView::new_with_model(&tree_store);
+        tree_view.set_headers_visible(false);
+        {
+            let cell_renderer = gtk::CellRendererText::new();
+            cell_renderer.set_property_ellipsize(pango::EllipsizeMode::End);
+            let column = gtk::TreeViewColumn::new();
+            column.pack_start(&cell_renderer, true);
+            column.add_attribute(&cell_renderer, "markup", 0);
+            tree_view.append_column(&column);
+        }
+
+        let scrolled_window = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        scrolled_window.add(&tree_view);
+
+        let actions_flow_box = gtk::FlowBox::new();
+        actions_flow_box.set_selection_mode(gtk::SelectionMode::None);
+        let action_buttons: Vec<(RefAction, gtk::Button)> = RefAction::ALL.iter()
+            .map(|action| (*action, gtk::Button::new_with_label(action.title())))
+            .collect();
+        for (_, button) in action_buttons.iter() {
+            actions_flow_box.add(button);
+        }
+
+        let container = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        container.pack_start(&scrolled_window, true, true, 0);
+        container.pack_start(&actions_flow_box, false, false, 0);
+
+        let sidebar = Rc::new(RefsSidebar {
+            container,
+            tree_store,
+            tree_view,
+            action_buttons,
+            entries: RefCell::new(Vec::new()),
+            updating: Cell::new(false),
+        });
+
+        sidebar.tree_view.get_selection().connect_changed(capture!(sidebar; move |_| {
+            sidebar.update_actions();
+        }));
+        sidebar.update_actions();
+
+        sidebar
+    }
+
+    pub fn widget(&self) -> &gtk::Box {
+        &self.container
+    }
+
+    /// Replaces the listed references, keeping the selection if the selected reference still exists
+    pub fn set_refs(&self, entries: Vec<RefEntry>) {
+        let selected = self.selected_ref().map(|entry| entry.full_name);
+
+        self.updating.set(true);
+        self.tree_store.clear();
+
+        let add_group = |title: &str, parent: Option<&gtk::TreeIter>| {
+            self.tree_store.insert_with_values(parent, None, &[0, 1], &[&format!("<b>{}</b>", glib::markup_escape_text(title)), &""])
+        };
+        let branches_iter = add_group("Branches", None);
+        let remotes_iter = add_group("Remotes", None);
+        let tags_iter = add_group("Tags", None);
+        let mut remote_iters = Vec::<(String, gtk::TreeIter)>::new();
+
+        let mut selected_iter = None;
+
+        for entry in entries.iter() {
+            let (parent, name) = match entry.kind {
+                RefKind::LocalBranch => (branches_iter.clone(), entry.name.as_str()),
+                RefKind::Tag => (tags_iter.clone(), entry.name.as_str()),
+                RefKind::RemoteBranch => {
+                    let mut parts = entry.name.splitn(2, '/');
+                    let remote = parts.next().unwrap_or("");
+                    let name = parts.next().unwrap_or(&entry.name);
+                    let remote_iter = match remote_iters.iter().find(|(name, _)| name == remote) {
+                        Some((_, iter)) => iter.clone(),
+                        None => {
+                            let iter = add_group(remote, Some(&remotes_iter));
+                            remote_iters.push((remote.to_owned(), iter.clone()));
+                            iter
+                        },
+                    };
+                    (remote_iter, name)
+                },
+            };
+
+            let markup = if entry.is_head {
+                format!("<b>{}</b> (HEAD)", glib::markup_escape_text(name))
+            } else {
+                glib::markup_escape_text(name).to_string()
+            };
+            let iter = self.tree_store.insert_with_values(Some(&parent), None, &[0, 1], &[&markup, &entry.full_name]);
+            if Some(&entry.full_name) == selected.as_ref() {
+                selected_iter = Some(iter);
+            }
+        }
+
+        self.entries.replace(entries);
+        self.tree_view.expand_all();
+
+        if let Some(iter) = selected_iter {
+            self.tree_view.get_selection().select_iter(&iter);
+        }
+        self.updating.set(false);
+        self.update_actions();
+    }
+
+    pub fn selected_ref(&self) -> Option<RefEntry> {
+        let (model, iter) = self.tree_view.get_selection().get_selected()?;
+        let full_name = model.get_value(&iter, 1).get::<String>()?;
+
+        self.entries.borrow().iter().find(|entry| entry.full_name == full_name).cloned()
+    }
+
+    /// Called with the newly selected reference
+    pub fn connect_ref_selected<F: Fn(RefEntry) + 'static>(self: &Rc<Self>, f: F) {
+        let sidebar = self.clone();
+        self.tree_view.get_selection().connect_changed(move |_| {
+            if sidebar.updating.get() {
+                return;
+            }
+            if let Some(entry) = sidebar.selected_ref() {
+                f(entry);
+            }
+        });
+    }
+
+    /// Called with the selected reference when the button of `action` is clicked
+    pub fn connect_action<F: Fn(Option<RefEntry>) + 'static>(self: &Rc<Self>, action: RefAction, f: F) {
+        let f = Rc::new(f);
+        for (_, button) in self.action_buttons.iter().filter(|(button_action, _)| *button_action == action) {
+            button.connect_clicked(capture!(sidebar = *self, f; move |_| {
+                f(sidebar.selected_ref());
+            }));
+        }
+    }
+
+    fn update_actions(&self) {
+        let selected = self.selected_ref();
+        for (action, button) in self.action_buttons.iter() {
+            button.set_sensitive(action.is_applicable(selected.as_ref()));
+        }
+    }
+}
@@ -0,0 +1,317 @@
+use gtk::prelude::*;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::syntax_highlight::LineHighlights;
+use crate::tree_items::{TreeItem, TreeItemKind};
+use crate::widgets::diff_view::{add_syntax_tags, syntax_tag};
+
+const TAG_MONOSPACE: &str = "monospace";
+
+/// Directory tree of a commit, with subtrees loaded when expanded, and a preview of the selected file
+pub struct TreeBrowser {
+    container: gtk::Paned,
+    tree_store: gtk::TreeStore,
+    tree_view: gtk::TreeView,
+    preview_text_view: gtk::TextView,
+    save_button: gtk::Button,
+    menu: gtk::Menu,
+    history_menu_item: gtk::MenuItem,
+    blame_menu_item: gtk::MenuItem,
+    save_menu_item: gtk::MenuItem,
+    /// Commit whose tree is shown
+    commit: RefCell<Option<git2::Oid>>,
+    /// Listed items by path
+    items: RefCell<HashMap<String, TreeItem>>,
+}
+
+impl TreeBrowser {
+    pub fn new() -> Rc<TreeBrowser> {
+        let tree_store = gtk::TreeStore::new(&[
+            gtk::Type::String, // Name
+            gtk::Type::String, // Path; empty for the placeholder row of a directory that is not loaded yet
+            gtk::Type::String, // Icon name
+            gtk::Type::String, // File mode in octal
+            gtk::Type::String, // Size
+        ]);
+
+        let tree_view = gtk::TreeView::new_with_model(&tree_store);
+        {
+            let column = gtk::TreeViewColumn::new();
+            column.set_title("Name");
+            column.set_resizable(true);
+            column.set_expand(true);
+
+            let cell_renderer_icon = gtk::CellRendererPixbuf::new();
+            column.pack_start(&cell_renderer_icon, false);
+            column.add_attribute(&cell_renderer_icon, "icon-name", 2);
+
+            let cell_renderer_name = gtk::CellRendererText::new();
+            cell_renderer_name.set_property_ellipsize(pango::EllipsizeMode::End);
+            column.pack_start(&cell_renderer_name, true);
+            column.add_attribute(&cell_renderer_name, "text", 0);
+
+            tree_view.append_column(&column);
+        }
+        for (title, column_idx) in [("Mode", 3), ("Size", 4)].iter() {
+            let cell_renderer = gtk::CellRendererText::new();
+            cell_renderer.set_property_xalign(1.0);
+            let column = gtk::TreeViewColumn::new();
+            column.set_title(title);
+            column.pack_start(&cell_renderer, true);
+            column.add_attribute(&cell_renderer, "text", *column_idx);
+            tree_view.append_column(&column);
+        }
+
+        let tree_scrolled_window = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        tree_scrolled_window.add(&tree_view);
+
+        let save_button = gtk::Button::new_with_label("Save blob as\u{2026}");
+        save_button.set_tooltip_text(Some("Save the selected file as it is in the commit"));
+        let buttons_hbox = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+        buttons_hbox.set_border_width(2);
+        buttons_hbox.pack_end(&save_button, false, false, 0);
+
+        let tree_vbox = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        tree_vbox.pack_start(&tree_scrolled_window, true, true, 0);
+        tree_vbox.pack_start(&buttons_hbox, false, false, 0);
+
+        let tag_table = gtk::TextTagTable::new();
+        let tag = gtk::TextTag::new(Some(TAG_MONOSPACE));
+        tag.set_property_family(Some("monospace"));
+        tag_table.add(&tag);
+        add_syntax_tags(&tag_table);
+        let preview_text_view = gtk::TextView::new_with_buffer(&gtk::TextBuffer::new(Some(&tag_table)));
+        preview_text_view.set_editable(false);
+        let preview_scrolled_window = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        preview_scrolled_window.add(&preview_text_view);
+
+        let container = gtk::Paned::new(gtk::Orientation::Vertical);
+        container.pack1(&tree_vbox, true, false);
+        container.pack2(&preview_scrolled_window, true, false);
+
+        let menu = gtk::Menu::new();
+        let history_menu_item = gtk::MenuItem::new_with_label("Show history of this file");
+        let blame_menu_item = gtk::MenuItem::new_with_label("Blame this file");
+        let save_menu_item = gtk::MenuItem::new_with_label("Save blob as\u{2026}");
+        menu.append(&history_menu_item);
+        menu.append(&blame_menu_item);
+        menu.append(&gtk::SeparatorMenuItem::new());
+        menu.append(&save_menu_item);
+        menu.show_all();
+
+        let tree_browser = Rc::new(TreeBrowser {
+            container,
+            tree_store,
+            tree_view,
+            preview_text_view,
+            save_button,
+            menu,
+            history_menu_item,
+            blame_menu_item,
+            save_menu_item,
+            commit: RefCell::new(None),
+            items: RefCell::new(HashMap::new()),
+        });
+
+        tree_browser.tree_view.get_selection().connect_changed(capture!(tree_browser; move |_| {
+            tree_browser.update_actions();
+        }));
+        tree_browser.tree_view.connect_button_press_event(capture!(tree_browser; move |tree_view, event| {
+            if event.get_event_type() != gdk::EventType::ButtonPress || event.get_button() != 3 {
+                return Inhibit(false);
+            }
+
+            let (x, y) = event.get_position();
+            if let Some((Some(path), _, _, _)) = tree_view.get_path_at_pos(x as i32, y as i32) {
+                tree_view.get_selection().select_path(&path);
+            }
+            if tree_browser.selected_item().is_none() {
+                return Inhibit(false);
+            }
+
+            tree_browser.menu.popup_easy(event.get_button(), event.get_time());
+            Inhibit(true)
+        }));
+        tree_browser.update_actions();
+
+        tree_browser
+    }
+
+    pub fn widget(&self) -> &gtk::Paned {
+        &self.container
+    }
+
+    /// Commit whose tree is shown
+    pub fn commit(&self) -> Option<git2::Oid> {
+        *self.commit.borrow()
+    }
+
+    /// Shows the root entries of the tree of `commit`, or nothing with `None`
+    pub fn set_root(&self, commit: Option<git2::Oid>, items: &[TreeItem]) {
+        self.commit.replace(commit);
+        self.items.borrow_mut().clear();
+        self.tree_store.clear();
+        self.insert_items(None, items);
+        self.show_preview("");
+    }
+
+    /// Shows the entries of a directory of the tree of `commit` once they are loaded
+    pub fn set_children(&self, commit: git2::Oid, path: &str, items: &[TreeItem]) {
+        if self.commit() != Some(commit) {
+            return;
+        }
+        let parent = match self.find_row(None, path) {
+            Some(parent) => parent,
+            None => return,
+        };
+
+        while let Some(child) = self.tree_store.iter_children(Some(&parent)) {
+            self.tree_store.remove(&child);
+        }
+        self.insert_items(Some(&parent), items);
+        if let Some(tree_path) = self.tree_store.get_path(&parent) {
+            self.tree_view.expand_row(&tree_path, false);
+        }
+    }
+
+    pub fn show_preview(&self, text: &str) {
+        let buffer = self.preview_text_view.get_buffer().unwrap();
+        buffer.set_text(text);
+        buffer.apply_tag_by_name(TAG_MONOSPACE, &buffer.get_start_iter(), &buffer.get_end_iter());
+    }
+
+    /// Shows the content of a file, each line with its syntax highlighting if there is any
+    pub fn show_file(&self, text: &str, highlights: &[LineHighlights]) {
+        self.show_preview(text);
+
+        let buffer = self.preview_text_view.get_buffer().unwrap();
+        for (line_idx, line_highlights) in highlights.iter().enumerate() {
+            for (range, token_class) in line_highlights.iter() {
+                let start = buffer.get_iter_at_line_offset(line_idx as i32, range.start as i32);
+                let end = buffer.get_iter_at_line_offset(line_idx as i32, range.end as i32);
+                buffer.apply_tag_by_name(syntax_tag(*token_class), &start, &end);
+            }
+        }
+    }
+
+    pub fn selected_item(&self) -> Option<TreeItem> {
+        let (model, iter) = self.tree_view.get_selection().get_selected()?;
+        let path = model.get_value(&iter, 1).get::<String>()?;
+
+        self.items.borrow().get(&path).cloned()
+    }
+
+    /// Called with the ID and the path of a directory whose entries have to be loaded
+    pub fn connect_expand<F: Fn(git2::Oid, String) + 'static>(self: &Rc<Self>, f: F) {
+        self.tree_view.connect_test_expand_row(capture!(tree_browser = *self; move |_, iter, _| {
+            let is_loaded = tree_browser.tree_store.iter_children(Some(iter))
+                .and_then(|child| tree_browser.tree_store.get_value(&child, 1).get::<String>())
+                .map(|path| !path.is_empty())
+                .unwrap_or(true);
+            if !is_loaded {
+                let item = tree_browser.tree_store.get_value(iter, 1).get::<String>()
+                    .and_then(|path| tree_browser.items.borrow().get(&path).cloned());
+                if let Some(item) = item {
+                    f(item.id, item.path);
+                }
+            }
+            Inhibit(false)
+        }));
+    }
+
+    /// Called with the newly selected file
+    pub fn connect_file_selected<F: Fn(TreeItem) + 'static>(self: &Rc<Self>, f: F) {
+        self.tree_view.get_selection().connect_changed(capture!(tree_browser = *self; move |_| {
+            match tree_browser.selected_item() {
+                Some(item) if item.kind == TreeItemKind::File => f(item),
+                Some(item) if item.kind == TreeItemKind::Submodule => {
+                    tree_browser.show_preview(&format!("Submodule at commit {}", item.id));
+                },
+                _ => tree_browser.show_preview(""),
+            }
+        }));
+    }
+
+    /// Called with the selected file to save
+    pub fn connect_save<F: Fn(TreeItem) + 'static>(self: &Rc<Self>, f: F) {
+        let f = Rc::new(f);
+        self.save_button.connect_clicked(capture!(tree_browser = *self, f; move |_| {
+            if let Some(item) = tree_browser.selected_item() {
+                f(item);
+            }
+        }));
+        self.save_menu_item.connect_activate(capture!(tree_browser = *self, f; move |_| {
+            if let Some(item) = tree_browser.selected_item() {
+                f(item);
+            }
+        }));
+    }
+
+    /// Called with the path of the selected file or directory
+    pub fn connect_history<F: Fn(String) + 'static>(self: &Rc<Self>, f: F) {
+        self.history_menu_item.connect_activate(capture!(tree_browser = *self; move |_| {
+            if let Some(item) = tree_browser.selected_item() {
+                f(item.path);
+            }
+        }));
+    }
+
+    /// Called with the commit whose tree is shown and the path of the selected file
+    pub fn connect_blame<F: Fn(git2::Oid, String) + 'static>(self: &Rc<Self>, f: F) {
+        self.blame_menu_item.connect_activate(capture!(tree_browser = *self; move |_| {
+            if let (Some(commit), Some(item)) = (tree_browser.commit(), tree_browser.selected_item()) {
+                f(commit, item.path);
+            }
+        }));
+    }
+
+    fn insert_items(&self, parent: Option<&gtk::TreeIter>, items: &[TreeItem]) {
+        let mut listed = self.items.borrow_mut();
+        for item in items {
+            let (name, size) = match item.kind {
+                TreeItemKind::Submodule => (format!("{} @ {:.8}", item.name, item.id), String::new()),
+                _ => (item.name.clone(), item.size.map(|size| size.to_string()).unwrap_or_default()),
+            };
+            let iter = self.tree_store.insert_with_values(
+                parent,
+                None,
+                &[0, 1, 2, 3, 4],
+                &[&name, &item.path, &item.kind.icon_name(), &format!("{:06o}", item.mode), &size]
+            );
+            if item.kind == TreeItemKind::Directory {
+                // Lets the directory be expanded before its entries are loaded
+                self.tree_store.insert_with_values(Some(&iter), None, &[0, 1], &[&"Loading\u{2026}", &""]);
+            }
+            listed.insert(item.path.clone(), item.clone());
+        }
+    }
+
+    /// Row of the item at `path` among the descendants of `parent`
+    fn find_row(&self, parent: Option<&gtk::TreeIter>, path: &str) -> Option<gtk::TreeIter> {
+        let iter = self.tree_store.iter_children(parent)?;
+        loop {
+            let row_path = self.tree_store.get_value(&iter, 1).get::<String>().unwrap_or_default();
+            if row_path == path {
+                return Some(iter);
+            }
+            if !row_path.is_empty() && path.starts_with(&format!("{}/", row_path)) {
+                return self.find_row(Some(&iter), path);
+            }
+            if !self.tree_store.iter_next(&iter) {
+                return None;
+            }
+        }
+    }
+
+    fn update_actions(&self) {
+        let selected = self.selected_item();
+        let is_file = selected.as_ref().map(|item| item.kind == TreeItemKind::File).unwrap_or(false);
+        self.save_button.set_sensitive(is_file);
+        self.save_menu_item.set_sensitive(is_file);
+        self.blame_menu_item.set_sensitive(is_file);
+        self.history_menu_item.set_sensitive(selected.map(|item| item.kind != TreeItemKind::Submodule).unwrap_or(false));
+    }
+}
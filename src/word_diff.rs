@@ -0,0 +1,172 @@
+use std::ops::Range;
+
+use crate::file_diff::{DiffLine, DiffLineKind};
+
+/// Line pairs with more token pairs than this are not compared and get no highlighting
+const MAX_COMPARED_TOKEN_PAIRS: usize = 250_000;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Tokenizer {
+    Characters,
+    Words,
+    /// Words, with changes in whitespace not being highlighted
+    WordsIgnoringWhitespace,
+}
+
+impl Tokenizer {
+    pub const ALL: [Tokenizer; 3] = [Tokenizer::Characters, Tokenizer::Words, Tokenizer::WordsIgnoringWhitespace];
+
+    pub fn id(self) -> &'static str {
+        match self {
+            Tokenizer::Characters => "chars",
+            Tokenizer::Words => "words",
+            Tokenizer::WordsIgnoringWhitespace => "words-ignore-ws",
+        }
+    }
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Tokenizer::Characters => "Characters",
+            Tokenizer::Words => "Words",
+            Tokenizer::WordsIgnoringWhitespace => "Words, ignore whitespace",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Tokenizer> {
+        Self::ALL.iter().cloned().find(|tokenizer| tokenizer.id() == id)
+    }
+
+    /// Splits `s` into tokens, returned as ranges of char offsets
+    fn tokenize(self, s: &str) -> Vec<Range<usize>> {
+        let chars: Vec<char> = s.chars().collect();
+        match self {
+            Tokenizer::Characters => (0..chars.len()).map(|idx| idx..idx + 1).collect(),
+            Tokenizer::Words | Tokenizer::WordsIgnoringWhitespace => {
+                #[derive(Eq, PartialEq)]
+                enum Class { Word, Whitespace, Punctuation }
+
+                let class_of = |c: char| if c.is_alphanumeric() || c == '_' {
+                    Class::Word
+                } else if c.is_whitespace() {
+                    Class::Whitespace
+                } else {
+                    Class::Punctuation
+                };
+
+                let mut result = Vec::<Range<usize>>::new();
+                for (idx, c) in chars.iter().enumerate() {
+                    match result.last_mut() {
+                        Some(last) if class_of(chars[last.start]) == class_of(*c) && class_of(*c) != Class::Punctuation => {
+                            last.end = idx + 1;
+                        },
+                        _ => result.push(idx..idx + 1),
+                    }
+                }
+                result
+            },
+        }
+    }
+}
+
+/// Computes the ranges (in char offsets) of `old` and `new` that differ between them.
+pub fn changed_ranges(old: &str, new: &str, tokenizer: Tokenizer) -> (Vec<Range<usize>>, Vec<Range<usize>>) {
+    let old_chars: Vec<char> = old.chars().collect();
+    let new_chars: Vec<char> = new.chars().collect();
+    let old_tokens = tokenizer.tokenize(old);
+    let new_tokens = tokenizer.tokenize(new);
+
+    if old_tokens.len() * new_tokens.len() > MAX_COMPARED_TOKEN_PAIRS {
+        return (Vec::new(), Vec::new());
+    }
+
+    let is_whitespace = |chars: &[char], token: &Range<usize>| chars[token.clone()].iter().all(|c| c.is_whitespace());
+    let tokens_equal = |old_token: &Range<usize>, new_token: &Range<usize>| {
+        if tokenizer == Tokenizer::WordsIgnoringWhitespace
+            && is_whitespace(&old_chars, old_token) && is_whitespace(&new_chars, new_token) {
+            return true;
+        }
+        old_chars[old_token.clone()] == new_chars[new_token.clone()]
+    };
+
+    // Longest common subsequence of tokens; lcs[i][j] is for old_tokens[i..] and new_tokens[j..]
+    let mut lcs = vec![vec![0u32; new_tokens.len() + 1]; old_tokens.len() + 1];
+    for i in (0..old_tokens.len()).rev() {
+        for j in (0..new_tokens.len()).rev() {
+            lcs[i][j] = if tokens_equal(&old_tokens[i], &new_tokens[j]) {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut old_changed = Vec::new();
+    let mut new_changed = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old_tokens.len() || j < new_tokens.len() {
+        if i < old_tokens.len() && j < new_tokens.len() && tokens_equal(&old_tokens[i], &new_tokens[j]) {
+            i += 1;
+            j += 1;
+        } else if j == new_tokens.len() || (i < old_tokens.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            old_changed.push(old_tokens[i].clone());
+            i += 1;
+        } else {
+            new_changed.push(new_tokens[j].clone());
+            j += 1;
+        }
+    }
+
+    if tokenizer == Tokenizer::WordsIgnoringWhitespace {
+        old_changed.retain(|token| !is_whitespace(&old_chars, token));
+        new_changed.retain(|token| !is_whitespace(&new_chars, token));
+    }
+
+    (merge_adjacent(old_changed), merge_adjacent(new_changed))
+}
+
+fn merge_adjacent(ranges: Vec<Range<usize>>) -> Vec<Range<usize>> {
+    let mut result = Vec::<Range<usize>>::new();
+    for range in ranges {
+        match result.last_mut() {
+            Some(last) if last.end == range.start => last.end = range.end,
+            _ => result.push(range),
+        }
+    }
+    result
+}
+
+/// Computes changed ranges for the lines of a hunk. Each run of removed lines is paired line by line
+/// with the run of added lines that follows it; lines without a pair get no ranges.
+pub fn hunk_changed_ranges(lines: &[DiffLine], tokenizer: Tokenizer) -> Vec<Vec<Range<usize>>> {
+    let mut result = vec![Vec::new(); lines.len()];
+
+    let mut idx = 0;
+    while idx < lines.len() {
+        if lines[idx].kind != DiffLineKind::Removed {
+            idx += 1;
+            continue;
+        }
+
+        let removed_start = idx;
+        while idx < lines.len() && lines[idx].kind == DiffLineKind::Removed {
+            idx += 1;
+        }
+        let removed_end = idx;
+        while idx < lines.len() && lines[idx].kind == DiffLineKind::NoNewline {
+            idx += 1;
+        }
+        let added_start = idx;
+        while idx < lines.len() && lines[idx].kind == DiffLineKind::Added {
+            idx += 1;
+        }
+        let added_end = idx;
+
+        for (old_idx, new_idx) in (removed_start..removed_end).zip(added_start..added_end) {
+            let (old_ranges, new_ranges) = changed_ranges(&lines[old_idx].content, &lines[new_idx].content, tokenizer);
+            result[old_idx] = old_ranges;
+            result[new_idx] = new_ranges;
+        }
+    }
+
+    result
+}